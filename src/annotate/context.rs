@@ -0,0 +1,429 @@
+//! Detect whether annotated concepts are negated, historical, hypothetical, or experienced by
+//! someone other than the patient, using trigger phrases in the style of NegEx and ConText.
+//!
+//! Each trigger phrase affects the concepts within a window of tokens after it (forward
+//! triggers), before it (backward triggers), or both. The scope of a trigger stops early at the
+//! end of the sentence or at a termination phrase such as "but". Pseudo-triggers are phrases
+//! that contain a trigger but should not be treated as one, such as "no increase".
+
+use std::{ops::Range, path::Path};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{tokenize, Annotation, Token};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerCategory {
+    Negated,
+    Historical,
+    Hypothetical,
+    /// The concept refers to someone other than the patient, such as a family member.
+    OtherExperiencer,
+    /// A phrase that contains a trigger but is not a trigger itself.
+    Pseudo,
+    /// A phrase that ends the scope of other triggers.
+    Termination,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// The trigger applies to concepts after it.
+    #[default]
+    Forward,
+    /// The trigger applies to concepts before it.
+    Backward,
+    /// The trigger applies to concepts on either side of it.
+    Both,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Trigger {
+    pub phrase: String,
+    pub category: TriggerCategory,
+    #[serde(default)]
+    pub direction: Direction,
+}
+
+/// The set of triggers used to classify annotations. This can be loaded from a JSON file with
+/// the same structure.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContextRules {
+    /// The maximum number of tokens that a trigger applies to.
+    #[serde(default = "default_window")]
+    pub window: usize,
+    pub triggers: Vec<Trigger>,
+}
+
+fn default_window() -> usize {
+    5
+}
+
+const DEFAULT_TRIGGERS: &[(&str, TriggerCategory, Direction)] = {
+    use Direction::*;
+    use TriggerCategory::*;
+    &[
+        ("no", Negated, Forward),
+        ("not", Negated, Forward),
+        ("denies", Negated, Forward),
+        ("denied", Negated, Forward),
+        ("denying", Negated, Forward),
+        ("without", Negated, Forward),
+        ("absence of", Negated, Forward),
+        ("no evidence of", Negated, Forward),
+        ("no signs of", Negated, Forward),
+        ("no sign of", Negated, Forward),
+        ("negative for", Negated, Forward),
+        ("free of", Negated, Forward),
+        ("ruled out", Negated, Both),
+        ("was ruled out", Negated, Backward),
+        ("is ruled out", Negated, Backward),
+        ("unlikely", Negated, Backward),
+        ("was negative", Negated, Backward),
+        ("not seen", Negated, Backward),
+        ("absent", Negated, Backward),
+        ("resolved", Negated, Backward),
+        ("no increase", Pseudo, Forward),
+        ("no change", Pseudo, Forward),
+        ("no further", Pseudo, Forward),
+        ("not only", Pseudo, Forward),
+        ("not necessarily", Pseudo, Forward),
+        ("not certain if", Pseudo, Forward),
+        ("not rule out", Pseudo, Forward),
+        ("without difficulty", Pseudo, Forward),
+        ("gram negative", Pseudo, Forward),
+        ("history of", Historical, Forward),
+        ("h o", Historical, Forward),
+        ("past medical history", Historical, Forward),
+        ("pmh", Historical, Forward),
+        ("previous", Historical, Forward),
+        ("prior", Historical, Forward),
+        ("status post", Historical, Forward),
+        ("history and physical", Pseudo, Forward),
+        ("history and examination", Pseudo, Forward),
+        ("if", Hypothetical, Forward),
+        ("rule out", Hypothetical, Forward),
+        ("r o", Hypothetical, Forward),
+        ("return if", Hypothetical, Forward),
+        ("in case of", Hypothetical, Forward),
+        ("as needed for", Hypothetical, Forward),
+        ("should", Hypothetical, Forward),
+        ("could", Hypothetical, Forward),
+        ("family history of", OtherExperiencer, Forward),
+        ("family history", OtherExperiencer, Forward),
+        ("mother", OtherExperiencer, Both),
+        ("father", OtherExperiencer, Both),
+        ("sister", OtherExperiencer, Both),
+        ("brother", OtherExperiencer, Both),
+        ("aunt", OtherExperiencer, Both),
+        ("uncle", OtherExperiencer, Both),
+        ("grandmother", OtherExperiencer, Both),
+        ("grandfather", OtherExperiencer, Both),
+        ("son", OtherExperiencer, Both),
+        ("daughter", OtherExperiencer, Both),
+        ("but", Termination, Both),
+        ("however", Termination, Both),
+        ("although", Termination, Both),
+        ("though", Termination, Both),
+        ("except", Termination, Both),
+        ("apart from", Termination, Both),
+        ("aside from", Termination, Both),
+        ("which", Termination, Both),
+        ("presenting", Termination, Both),
+        ("secondary to", Termination, Both),
+        ("cause of", Termination, Both),
+        ("patient", Termination, Both),
+    ]
+};
+
+impl Default for ContextRules {
+    fn default() -> Self {
+        Self {
+            window: default_window(),
+            triggers: DEFAULT_TRIGGERS
+                .iter()
+                .map(|&(phrase, category, direction)| Trigger {
+                    phrase: phrase.to_string(),
+                    category,
+                    direction,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ContextRules {
+    /// Load the rules from a JSON file.
+    pub fn from_file(path: &Path) -> Result<ContextRules> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let rules = serde_json::from_reader(file)?;
+        Ok(rules)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Experiencer {
+    #[default]
+    Patient,
+    Other,
+}
+
+/// The context of an annotated concept.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Assertion {
+    pub negated: bool,
+    pub historical: bool,
+    pub hypothetical: bool,
+    pub experiencer: Experiencer,
+}
+
+/// A trigger phrase found in the text.
+struct TriggerMatch {
+    /// The range of tokens that the trigger phrase covers.
+    tokens: Range<usize>,
+    category: TriggerCategory,
+    direction: Direction,
+}
+
+pub struct ContextClassifier {
+    window: usize,
+    /// The triggers, split into lowercase words, sorted from longest to shortest.
+    triggers: Vec<(Vec<String>, TriggerCategory, Direction)>,
+}
+
+impl ContextClassifier {
+    pub fn new(rules: ContextRules) -> ContextClassifier {
+        let mut triggers = rules
+            .triggers
+            .into_iter()
+            .map(|t| {
+                let lower = t.phrase.to_lowercase();
+                let words = tokenize(&lower)
+                    .into_iter()
+                    .map(|token| lower[token.byte_start..token.byte_end].to_string())
+                    .collect::<Vec<_>>();
+                (words, t.category, t.direction)
+            })
+            .filter(|(words, _, _)| !words.is_empty())
+            .collect::<Vec<_>>();
+        triggers.sort_by_key(|t| std::cmp::Reverse(t.0.len()));
+
+        ContextClassifier {
+            window: rules.window,
+            triggers,
+        }
+    }
+
+    /// Set the assertion for each annotation, based on the triggers around it in `text`.
+    pub fn classify(&self, text: &str, annotations: &mut [Annotation]) {
+        for annotation in annotations.iter_mut() {
+            annotation.assertion = Assertion::default();
+        }
+
+        let tokens = tokenize(text);
+        let words = tokens
+            .iter()
+            .map(|t| text[t.byte_start..t.byte_end].to_lowercase())
+            .collect::<Vec<_>>();
+
+        for sentence in sentences(text, &tokens) {
+            let matches = self.find_triggers(&words, sentence.clone());
+            let terminations = matches
+                .iter()
+                .filter(|m| m.category == TriggerCategory::Termination)
+                .map(|m| m.tokens.start)
+                .collect::<Vec<_>>();
+
+            for m in &matches {
+                let scope = match m.category {
+                    TriggerCategory::Pseudo | TriggerCategory::Termination => continue,
+                    _ => self.scope(m, sentence.clone(), &terminations),
+                };
+
+                for scope in scope {
+                    if scope.is_empty() {
+                        continue;
+                    }
+
+                    let char_start = tokens[scope.start].char_start;
+                    let char_end = tokens[scope.end - 1].char_end;
+                    for annotation in annotations.iter_mut() {
+                        if annotation.start < char_end && annotation.end > char_start {
+                            apply(&mut annotation.assertion, m.category);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Find the triggers within a sentence. Longer triggers take priority over shorter ones that
+    /// overlap them.
+    fn find_triggers(&self, words: &[String], sentence: Range<usize>) -> Vec<TriggerMatch> {
+        let mut matches = Vec::new();
+        let mut pos = sentence.start;
+        while pos < sentence.end {
+            let found = self.triggers.iter().find(|(phrase, _, _)| {
+                pos + phrase.len() <= sentence.end
+                    && phrase.iter().zip(&words[pos..]).all(|(p, w)| p == w)
+            });
+
+            match found {
+                Some((phrase, category, direction)) => {
+                    matches.push(TriggerMatch {
+                        tokens: pos..pos + phrase.len(),
+                        category: *category,
+                        direction: *direction,
+                    });
+                    pos += phrase.len();
+                }
+                None => pos += 1,
+            }
+        }
+
+        matches
+    }
+
+    /// Get the ranges of tokens that a trigger applies to.
+    fn scope(
+        &self,
+        m: &TriggerMatch,
+        sentence: Range<usize>,
+        terminations: &[usize],
+    ) -> [Range<usize>; 2] {
+        let forward = if m.direction == Direction::Backward {
+            m.tokens.end..m.tokens.end
+        } else {
+            let end = terminations
+                .iter()
+                .copied()
+                .filter(|&t| t >= m.tokens.end)
+                .min()
+                .unwrap_or(sentence.end)
+                .min(m.tokens.end + self.window);
+            m.tokens.end..end
+        };
+
+        let backward = if m.direction == Direction::Forward {
+            m.tokens.start..m.tokens.start
+        } else {
+            let start = terminations
+                .iter()
+                .copied()
+                .filter(|&t| t < m.tokens.start)
+                .max()
+                .map(|t| t + 1)
+                .unwrap_or(sentence.start)
+                .max(m.tokens.start.saturating_sub(self.window));
+            start..m.tokens.start
+        };
+
+        [forward, backward]
+    }
+}
+
+fn apply(assertion: &mut Assertion, category: TriggerCategory) {
+    match category {
+        TriggerCategory::Negated => assertion.negated = true,
+        TriggerCategory::Historical => assertion.historical = true,
+        TriggerCategory::Hypothetical => assertion.hypothetical = true,
+        TriggerCategory::OtherExperiencer => assertion.experiencer = Experiencer::Other,
+        TriggerCategory::Pseudo | TriggerCategory::Termination => {}
+    }
+}
+
+/// Split the tokens into sentences, returning the range of tokens in each sentence.
+fn sentences(text: &str, tokens: &[Token]) -> Vec<Range<usize>> {
+    let mut output = Vec::new();
+    let mut start = 0;
+    for i in 1..tokens.len() {
+        let between = &text[tokens[i - 1].byte_end..tokens[i].byte_start];
+        if between.contains(['.', '!', '?', ';', '\n']) {
+            output.push(start..i);
+            start = i;
+        }
+    }
+
+    if start < tokens.len() {
+        output.push(start..tokens.len());
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn annotation(text: &str, phrase: &str) -> Annotation {
+        let byte_start = text.find(phrase).unwrap();
+        let start = text[..byte_start].chars().count();
+        Annotation {
+            start,
+            end: start + phrase.chars().count(),
+            text: phrase.to_string(),
+            concepts: Vec::new(),
+            assertion: Assertion::default(),
+        }
+    }
+
+    fn classify(text: &str, phrases: &[&str]) -> Vec<Assertion> {
+        let classifier = ContextClassifier::new(ContextRules::default());
+        let mut annotations = phrases
+            .iter()
+            .map(|p| annotation(text, p))
+            .collect::<Vec<_>>();
+        classifier.classify(text, &mut annotations);
+        annotations.into_iter().map(|a| a.assertion).collect()
+    }
+
+    #[test]
+    fn negation() {
+        let result = classify(
+            "No evidence of pneumonia. Cough is present.",
+            &["pneumonia", "Cough"],
+        );
+        assert!(result[0].negated);
+        assert!(!result[1].negated);
+    }
+
+    #[test]
+    fn termination() {
+        let result = classify("Denies fever but reports cough.", &["fever", "cough"]);
+        assert!(result[0].negated);
+        assert!(!result[1].negated);
+    }
+
+    #[test]
+    fn backward_trigger() {
+        let result = classify("Pneumonia was ruled out.", &["Pneumonia"]);
+        assert!(result[0].negated);
+    }
+
+    #[test]
+    fn pseudo_trigger() {
+        let result = classify("No increase in edema.", &["edema"]);
+        assert!(!result[0].negated);
+    }
+
+    #[test]
+    fn experiencer_and_history() {
+        let result = classify(
+            "Family history of diabetes. History of hypertension.",
+            &["diabetes", "hypertension"],
+        );
+        assert_eq!(result[0].experiencer, Experiencer::Other);
+        assert!(!result[0].historical);
+        assert_eq!(result[1].experiencer, Experiencer::Patient);
+        assert!(result[1].historical);
+    }
+
+    #[test]
+    fn window() {
+        let result = classify("No one two three four five six pneumonia", &["pneumonia"]);
+        assert!(!result[0].negated);
+    }
+}
@@ -0,0 +1,506 @@
+//! Find UMLS concepts in free text.
+//!
+//! The text is split into tokens, and then spans of tokens are matched against the strings in the
+//! index, walking the FST one token at a time so that every span starting at a token is checked
+//! in a single pass.
+
+use std::str::FromStr;
+
+use eyre::eyre;
+use fst::raw::{CompiledAddr, Output};
+use serde::Serialize;
+use smallvec::SmallVec;
+use smol_str::SmolStr;
+
+use crate::index::Index;
+
+use self::context::{Assertion, ContextClassifier, ContextRules};
+
+pub mod context;
+
+/// Common words which are also the abbreviations of concepts, such as "NO" for nitric oxide.
+/// Single-token matches on these words are ignored.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "can", "for", "had", "has", "have",
+    "he", "her", "his", "if", "in", "is", "it", "may", "no", "not", "of", "on", "or", "she", "so",
+    "the", "this", "to", "was", "we", "were", "will", "with",
+];
+
+/// How to handle matches that overlap each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlapStrategy {
+    /// Scan left to right, taking the longest match at each position and skipping past it, so
+    /// that no matches overlap.
+    #[default]
+    Longest,
+    /// Keep every match that is not contained within a longer match. Matches that partially
+    /// overlap are all kept.
+    NonNested,
+    /// Keep every match.
+    All,
+}
+
+impl FromStr for OverlapStrategy {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "longest" => Ok(OverlapStrategy::Longest),
+            "non-nested" => Ok(OverlapStrategy::NonNested),
+            "all" => Ok(OverlapStrategy::All),
+            _ => Err(eyre!(
+                "Unknown overlap strategy {s}, expected longest, non-nested, or all"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnnotateOptions {
+    pub overlap: OverlapStrategy,
+    /// The maximum number of tokens in a single match.
+    pub max_tokens: usize,
+    /// Ignore matches shorter than this many characters.
+    pub min_length: usize,
+    /// Only match concepts that have an atom from one of these sources. If empty, all sources
+    /// are used.
+    pub sources: Vec<SmolStr>,
+    /// Only match concepts with one of these semantic type tree numbers, or their children, or
+    /// in one of these semantic groups, such as DISO. If empty, all semantic types are used.
+    pub semantic_types: Vec<SmolStr>,
+    /// If set, detect whether each annotation is negated, historical, hypothetical, or about
+    /// someone other than the patient.
+    pub context: Option<ContextRules>,
+}
+
+impl Default for AnnotateOptions {
+    fn default() -> Self {
+        Self {
+            overlap: OverlapStrategy::default(),
+            max_tokens: 10,
+            min_length: 3,
+            sources: Vec::new(),
+            semantic_types: Vec::new(),
+            context: None,
+        }
+    }
+}
+
+/// A concept found in the text.
+#[derive(Serialize, Debug, Clone)]
+pub struct AnnotatedConcept {
+    pub concept_id: u32,
+    pub cui: SmolStr,
+    pub preferred_name: SmolStr,
+    /// The names of the concept's semantic types
+    pub semantic_types: Vec<SmolStr>,
+}
+
+/// A span of text that matched one or more concepts.
+#[derive(Serialize, Debug, Clone)]
+pub struct Annotation {
+    /// The character offset of the start of the span
+    pub start: usize,
+    /// The character offset just past the end of the span
+    pub end: usize,
+    pub text: String,
+    pub concepts: Vec<AnnotatedConcept>,
+    /// The context of the concepts. This is only filled in when [AnnotateOptions::context] is
+    /// set.
+    pub assertion: Assertion,
+}
+
+/// A token in the text, with its byte and character ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Token {
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+/// Split text into tokens, which are runs of alphanumeric characters.
+pub(crate) fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut current: Option<Token> = None;
+
+    for (char_pos, (byte_pos, c)) in text.char_indices().enumerate() {
+        if c.is_alphanumeric() {
+            let token = current.get_or_insert(Token {
+                byte_start: byte_pos,
+                byte_end: byte_pos,
+                char_start: char_pos,
+                char_end: char_pos,
+            });
+            token.byte_end = byte_pos + c.len_utf8();
+            token.char_end = char_pos + 1;
+        } else if let Some(token) = current.take() {
+            tokens.push(token);
+        }
+    }
+
+    tokens.extend(current);
+    tokens
+}
+
+/// The candidate ways to join two tokens: the text between them with whitespace collapsed, and
+/// a single space.
+fn separators(between: &str) -> SmallVec<[String; 2]> {
+    let mut collapsed = String::with_capacity(between.len());
+    let mut last_space = false;
+    for c in between.chars() {
+        if c.is_whitespace() {
+            if !last_space {
+                collapsed.push(' ');
+            }
+            last_space = true;
+        } else {
+            collapsed.push(c);
+            last_space = false;
+        }
+    }
+
+    let mut output = SmallVec::new();
+    if collapsed != " " {
+        output.push(collapsed);
+    }
+    output.push(String::from(" "));
+    output
+}
+
+/// A match found before resolving overlaps.
+struct Candidate {
+    start_token: usize,
+    end_token: usize,
+    concepts: Vec<u32>,
+}
+
+pub struct Annotator<'a> {
+    index: &'a Index,
+    options: AnnotateOptions,
+    context: Option<ContextClassifier>,
+}
+
+impl<'a> Annotator<'a> {
+    pub fn new(index: &'a Index, mut options: AnnotateOptions) -> Annotator<'a> {
+        let context = options.context.take().map(ContextClassifier::new);
+        Annotator {
+            index,
+            options,
+            context,
+        }
+    }
+
+    /// Find the concepts in a piece of text.
+    pub fn annotate(&self, text: &str) -> Vec<Annotation> {
+        let tokens = tokenize(text);
+        let candidates = self.find_candidates(text, &tokens);
+        let candidates = resolve_overlaps(candidates, self.options.overlap);
+
+        let mut annotations = candidates
+            .into_iter()
+            .map(|c| {
+                let start = &tokens[c.start_token];
+                let end = &tokens[c.end_token];
+                Annotation {
+                    start: start.char_start,
+                    end: end.char_end,
+                    text: text[start.byte_start..end.byte_end].to_string(),
+                    concepts: c
+                        .concepts
+                        .into_iter()
+                        .map(|id| self.annotated_concept(id))
+                        .collect(),
+                    assertion: Assertion::default(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(context) = &self.context {
+            context.classify(text, &mut annotations);
+        }
+
+        annotations
+    }
+
+    fn annotated_concept(&self, id: u32) -> AnnotatedConcept {
+        let concept = self.index.concept(id);
+        AnnotatedConcept {
+            concept_id: id,
+            cui: concept.cui().into(),
+            preferred_name: concept.preferred_name().into(),
+            semantic_types: concept
+                .types()
+                .filter_map(|t| self.index.semantic_types.get(&t))
+                .map(|t| t.name.clone())
+                .collect(),
+        }
+    }
+
+    fn normalize<'s>(&self, s: &'s str) -> std::borrow::Cow<'s, str> {
+        if self.index.meta.case_insensitive {
+            std::borrow::Cow::Owned(s.to_lowercase())
+        } else {
+            std::borrow::Cow::Borrowed(s)
+        }
+    }
+
+    fn find_candidates(&self, text: &str, tokens: &[Token]) -> Vec<Candidate> {
+        let fst = self.index.strings_fst();
+        let mut candidates = Vec::new();
+
+        for start in 0..tokens.len() {
+            let first = self.normalize(&text[tokens[start].byte_start..tokens[start].byte_end]);
+            let mut states: SmallVec<[(CompiledAddr, Output); 2]> =
+                walk(fst, fst.root().addr(), Output::zero(), first.as_bytes())
+                    .into_iter()
+                    .collect();
+
+            let is_stopword = STOPWORDS.contains(&first.to_lowercase().as_str());
+
+            let last = tokens.len().min(start + self.options.max_tokens);
+            for end in start..last {
+                if end > start {
+                    let between = &text[tokens[end - 1].byte_end..tokens[end].byte_start];
+                    let token = self.normalize(&text[tokens[end].byte_start..tokens[end].byte_end]);
+
+                    let mut next_states = SmallVec::new();
+                    for (addr, output) in &states {
+                        for sep in separators(between) {
+                            let next = walk(fst, *addr, *output, sep.as_bytes())
+                                .and_then(|(a, o)| walk(fst, a, o, token.as_bytes()));
+                            if let Some(next) = next {
+                                if !next_states.contains(&next) {
+                                    next_states.push(next);
+                                }
+                            }
+                        }
+                    }
+                    states = next_states;
+                }
+
+                if states.is_empty() {
+                    break;
+                }
+
+                let span_len = tokens[end].char_end - tokens[start].char_start;
+                if span_len < self.options.min_length {
+                    continue;
+                }
+
+                if start == end && is_stopword {
+                    continue;
+                }
+
+                let mut concepts = Vec::new();
+                for (addr, output) in &states {
+                    let node = fst.node(*addr);
+                    if !node.is_final() {
+                        continue;
+                    }
+
+                    let value = output.cat(node.final_output()).value();
+                    for id in self.index.concept_ids(value) {
+                        let id = id as u32;
+                        if !concepts.contains(&id)
+                            && self.index.concept_matches_filters(
+                                id,
+                                &self.options.sources,
+                                &self.options.semantic_types,
+                            )
+                        {
+                            concepts.push(id);
+                        }
+                    }
+                }
+
+                if !concepts.is_empty() {
+                    candidates.push(Candidate {
+                        start_token: start,
+                        end_token: end,
+                        concepts,
+                    });
+                }
+            }
+        }
+
+        candidates
+    }
+}
+
+/// Follow the transitions for `input` from the node at `addr`.
+fn walk<D: AsRef<[u8]>>(
+    fst: &fst::raw::Fst<D>,
+    addr: CompiledAddr,
+    mut output: Output,
+    input: &[u8],
+) -> Option<(CompiledAddr, Output)> {
+    let mut node = fst.node(addr);
+    for &b in input {
+        let t = node.transition(node.find_input(b)?);
+        output = output.cat(t.out);
+        node = fst.node(t.addr);
+    }
+
+    Some((node.addr(), output))
+}
+
+fn resolve_overlaps(mut candidates: Vec<Candidate>, strategy: OverlapStrategy) -> Vec<Candidate> {
+    match strategy {
+        OverlapStrategy::All => candidates,
+        OverlapStrategy::Longest => {
+            // Candidates are generated ordered by start token, then by end token, so the last
+            // candidate for each start token is the longest.
+            let mut output: Vec<Candidate> = Vec::new();
+            let mut next_free = 0;
+            let mut candidates = candidates.into_iter().peekable();
+            while let Some(c) = candidates.next() {
+                if candidates
+                    .peek()
+                    .map(|next| next.start_token == c.start_token)
+                    .unwrap_or(false)
+                {
+                    continue;
+                }
+
+                if c.start_token >= next_free {
+                    next_free = c.end_token + 1;
+                    output.push(c);
+                }
+            }
+
+            output
+        }
+        OverlapStrategy::NonNested => {
+            let spans = candidates
+                .iter()
+                .map(|c| (c.start_token, c.end_token))
+                .collect::<Vec<_>>();
+            let mut i = 0;
+            candidates.retain(|c| {
+                i += 1;
+                !spans.iter().enumerate().any(|(j, &(start, end))| {
+                    j != i - 1
+                        && start <= c.start_token
+                        && end >= c.end_token
+                        && (start, end) != (c.start_token, c.end_token)
+                })
+            });
+            candidates
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    #[test]
+    fn annotate_text() {
+        let data = TestData::new();
+        let text = "Née has Type 2 diabetes mellitus and DM, treated with metformin.";
+        let cuis = |a: &Annotation| a.concepts.iter().map(|c| c.cui.clone()).collect::<Vec<_>>();
+
+        // "Type 2 diabetes" and "diabetes mellitus" are inside the longest match, and "DM" is
+        // shorter than the minimum length.
+        let annotator = Annotator::new(&data.index, AnnotateOptions::default());
+        let annotations = annotator.annotate(text);
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations[0].text, "Type 2 diabetes mellitus");
+        // The offsets count characters, not bytes.
+        assert_eq!((annotations[0].start, annotations[0].end), (8, 32));
+        assert_eq!(cuis(&annotations[0]), ["C0011860"]);
+        assert_eq!(
+            annotations[0].concepts[0].semantic_types,
+            ["Disease or Syndrome"]
+        );
+        assert_eq!(annotations[1].text, "metformin");
+        assert_eq!(cuis(&annotations[1]), ["C0025598"]);
+
+        let annotator = Annotator::new(
+            &data.index,
+            AnnotateOptions {
+                overlap: OverlapStrategy::All,
+                min_length: 2,
+                ..Default::default()
+            },
+        );
+        let spans = annotator
+            .annotate(text)
+            .iter()
+            .map(|a| (a.text.clone(), cuis(a).join(",")))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                ("Type 2 diabetes".into(), "C0011860".into()),
+                ("Type 2 diabetes mellitus".into(), "C0011860".into()),
+                ("diabetes mellitus".into(), "C0011849".into()),
+                ("DM".into(), "C0011849,C0011860".into()),
+                ("metformin".into(), "C0025598".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_offsets() {
+        let tokens = tokenize("Née: type-2 diabetes");
+        let words = tokens
+            .iter()
+            .map(|t| (t.char_start, t.char_end))
+            .collect::<Vec<_>>();
+        assert_eq!(words, vec![(0, 3), (5, 9), (10, 11), (12, 20)]);
+        assert_eq!(tokens[0].byte_end, 4);
+    }
+
+    #[test]
+    fn separator_variants() {
+        assert_eq!(separators(" ").as_slice(), &[" "]);
+        assert_eq!(separators(",  ").as_slice(), &[", ", " "]);
+        assert_eq!(separators("-").as_slice(), &["-", " "]);
+    }
+
+    fn candidate(start_token: usize, end_token: usize) -> Candidate {
+        Candidate {
+            start_token,
+            end_token,
+            concepts: vec![1],
+        }
+    }
+
+    fn spans(candidates: Vec<Candidate>) -> Vec<(usize, usize)> {
+        candidates
+            .into_iter()
+            .map(|c| (c.start_token, c.end_token))
+            .collect()
+    }
+
+    #[test]
+    fn longest_overlap() {
+        let candidates = vec![
+            candidate(0, 0),
+            candidate(0, 2),
+            candidate(1, 3),
+            candidate(3, 3),
+        ];
+        assert_eq!(
+            spans(resolve_overlaps(candidates, OverlapStrategy::Longest)),
+            vec![(0, 2), (3, 3)]
+        );
+    }
+
+    #[test]
+    fn non_nested_overlap() {
+        let candidates = vec![
+            candidate(0, 0),
+            candidate(0, 2),
+            candidate(1, 3),
+            candidate(3, 3),
+        ];
+        assert_eq!(
+            spans(resolve_overlaps(candidates, OverlapStrategy::NonNested)),
+            vec![(0, 2), (1, 3)]
+        );
+    }
+}
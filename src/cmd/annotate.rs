@@ -0,0 +1,136 @@
+use std::{io::Read, path::Path, path::PathBuf};
+
+use clap::Args;
+use eyre::{eyre, Result};
+use smol_str::SmolStr;
+use umls::{
+    annotate::{
+        context::{ContextRules, Experiencer},
+        AnnotateOptions, Annotator, OverlapStrategy,
+    },
+    files::Files,
+    index::Index,
+};
+
+#[derive(Args, Debug)]
+pub struct AnnotateArgs {
+    /// The text to annotate. If omitted, the text is read from --file or from stdin.
+    pub text: Option<String>,
+
+    /// Read the text to annotate from this file
+    #[clap(short = 'f', long = "file")]
+    pub file: Option<PathBuf>,
+
+    /// How to handle overlapping matches: longest, non-nested, or all
+    #[clap(short = 'o', long = "overlap", default_value = "longest")]
+    pub overlap: OverlapStrategy,
+
+    /// The maximum number of tokens in a single match
+    #[clap(long = "max-tokens", default_value_t = 10)]
+    pub max_tokens: usize,
+
+    /// Ignore matches shorter than this many characters
+    #[clap(long = "min-length", default_value_t = 3)]
+    pub min_length: usize,
+
+    /// Only match concepts with atoms from these sources
+    #[clap(short = 's', long = "source")]
+    pub sources: Vec<SmolStr>,
+
+    /// Only match concepts with these semantic type tree numbers (and their children)
+    /// or semantic groups, such as DISO
+    #[clap(long = "type")]
+    pub semantic_types: Vec<SmolStr>,
+
+    /// Detect negation, historical, hypothetical, and family member context for each match
+    #[clap(short = 'c', long = "context")]
+    pub context: bool,
+
+    /// Load the context trigger rules from this JSON file instead of using the built-in rules.
+    /// Implies --context.
+    #[clap(long = "context-rules")]
+    pub context_rules: Option<PathBuf>,
+
+    /// Output the annotations as JSON lines
+    #[clap(long = "json")]
+    pub json: bool,
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: AnnotateArgs) -> Result<()> {
+    let text = match (args.text, args.file) {
+        (Some(text), None) => text,
+        (None, Some(file)) => std::fs::read_to_string(file)?,
+        (None, None) => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            text
+        }
+        (Some(_), Some(_)) => return Err(eyre!("Pass either text or --file, not both")),
+    };
+
+    let context = match (args.context_rules, args.context) {
+        (Some(path), _) => Some(ContextRules::from_file(&path)?),
+        (None, true) => Some(ContextRules::default()),
+        (None, false) => None,
+    };
+
+    let index = Index::new(&base_dir.join("index"))?;
+    let annotator = Annotator::new(
+        &index,
+        AnnotateOptions {
+            overlap: args.overlap,
+            max_tokens: args.max_tokens,
+            min_length: args.min_length,
+            sources: args.sources,
+            semantic_types: args.semantic_types,
+            context,
+        },
+    );
+
+    for annotation in annotator.annotate(&text) {
+        if args.json {
+            println!("{}", serde_json::to_string(&annotation)?);
+            continue;
+        }
+
+        let assertion = annotation.assertion;
+        let flags = [
+            (assertion.negated, "negated"),
+            (assertion.historical, "historical"),
+            (assertion.hypothetical, "hypothetical"),
+            (
+                assertion.experiencer == Experiencer::Other,
+                "other experiencer",
+            ),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, label)| label)
+        .collect::<Vec<_>>();
+
+        if flags.is_empty() {
+            println!(
+                "{}-{} {}",
+                annotation.start, annotation.end, annotation.text
+            );
+        } else {
+            println!(
+                "{}-{} {} [{}]",
+                annotation.start,
+                annotation.end,
+                annotation.text,
+                flags.join(", ")
+            );
+        }
+        for concept in annotation.concepts {
+            println!(
+                "  {} - {} ({})",
+                concept.cui,
+                concept.preferred_name,
+                concept.semantic_types.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use clap::Args;
+use eyre::{eyre, Result};
+use smol_str::SmolStr;
+use umls::{files::Files, index::Index};
+
+#[derive(Args, Debug)]
+pub struct AttributesArgs {
+    /// The CUI of the concept
+    pub cui: Option<String>,
+
+    /// Only show attributes with this name (ATN), e.g. NDC
+    #[clap(short = 'n', long = "name")]
+    pub names: Vec<SmolStr>,
+
+    /// Instead of showing a concept's attributes, find the concepts with this value for the
+    /// attribute given in --name
+    #[clap(long = "value", conflicts_with = "cui")]
+    pub value: Option<SmolStr>,
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: AttributesArgs) -> Result<()> {
+    let index = Index::new(&base_dir.join("index"))?;
+
+    match (args.cui, args.value) {
+        (None, Some(value)) => {
+            let [name] = args.names.as_slice() else {
+                return Err(eyre!("--value requires a single --name"));
+            };
+
+            let concepts = index.find_by_attribute(name, &value);
+            if concepts.is_empty() {
+                println!("Not found");
+            }
+
+            for id in concepts {
+                let concept = index.concept(id);
+                println!("{} - {}", concept.cui(), concept.preferred_name());
+            }
+        }
+        (Some(cui), None) => {
+            let id = index
+                .find_cui(&cui)
+                .ok_or_else(|| eyre!("Concept {cui} not found"))?;
+
+            for attribute in index.concept(id).attributes() {
+                if !args.names.is_empty() && !args.names.contains(&attribute.name) {
+                    continue;
+                }
+
+                println!(
+                    "{}: {} ({} {})",
+                    attribute.name, attribute.value, attribute.source, attribute.code
+                );
+            }
+        }
+        _ => return Err(eyre!("Pass either a CUI, or --name and --value")),
+    }
+
+    Ok(())
+}
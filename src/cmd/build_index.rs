@@ -5,7 +5,10 @@ use eyre::Result;
 use smol_str::SmolStr;
 use umls::{
     files::Files,
-    index::build::{build_index, IndexBuilderOptions},
+    index::{
+        build::{build_index, IndexBuilderOptions},
+        RelationKinds,
+    },
 };
 
 #[derive(Args, Debug)]
@@ -26,10 +29,25 @@ pub struct BuildIndexArgs {
     #[arg(short, long, env)]
     pub languages: Vec<SmolStr>,
 
-    /// The semantic type tree values (and their children) to include in the index. If empty, all
-    /// semantic types are included.
+    /// The semantic type tree values (and their children) or semantic groups, such as DISO, to
+    /// include in the index. If empty, all semantic types are included.
     #[arg(short = 't', long = "types", env)]
     pub semantic_types: Vec<SmolStr>,
+
+    /// Precompute every concept's ancestors over these relationship kinds (parent, broader, or
+    /// all) for fast subsumption checks. This can make the index much larger.
+    #[arg(long, env)]
+    pub closure: Option<RelationKinds>,
+
+    /// Only keep definitions from these sources, preferring them in the given order. If empty,
+    /// definitions from all included sources are kept.
+    #[arg(long, env)]
+    pub definition_sources: Vec<SmolStr>,
+
+    /// The MRSAT attribute names (ATN field) to include, such as NDC. If empty, no attributes
+    /// are included.
+    #[arg(long, env)]
+    pub attributes: Vec<SmolStr>,
 }
 
 pub fn run(base_dir: &Path, files: Files, args: BuildIndexArgs) -> Result<()> {
@@ -47,6 +65,9 @@ pub fn run(base_dir: &Path, files: Files, args: BuildIndexArgs) -> Result<()> {
         languages: args.languages,
         sources: args.sources,
         semantic_types: args.semantic_types,
+        closure: args.closure,
+        definition_sources: args.definition_sources,
+        attributes: args.attributes,
     })?;
 
     Ok(())
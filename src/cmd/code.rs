@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use clap::Args;
+use eyre::Result;
+use smol_str::SmolStr;
+use umls::{files::Files, index::Index};
+
+use super::search::print_concept;
+
+#[derive(Args, Debug)]
+pub struct CodeArgs {
+    /// The source of the code, e.g. RXNORM
+    pub source: SmolStr,
+
+    /// The code to look up
+    pub code: SmolStr,
+
+    /// Show output in long format
+    #[clap(short = 'l', long = "long")]
+    pub long: bool,
+
+    /// Show these code sources
+    #[clap(short = 'c', long = "code-source")]
+    pub code_types: Vec<SmolStr>,
+
+    /// Show the code's positions in its source's own hierarchy
+    #[clap(long = "hierarchy")]
+    pub hierarchy: bool,
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: CodeArgs) -> Result<()> {
+    let index = Index::new(&base_dir.join("index"))?;
+
+    let concepts = index.find_by_code(&args.source, &args.code);
+    if concepts.is_empty() {
+        println!("Not found");
+        return Ok(());
+    }
+
+    for id in concepts {
+        print_concept(&index, id as u64, args.long, &args.code_types);
+    }
+
+    if args.hierarchy {
+        for path in index.hierarchy(&args.source, &args.code) {
+            let hcd = if path.hcd.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", path.hcd)
+            };
+            println!("Hierarchy{hcd}:");
+            if !path.complete {
+                println!("  (some ancestors are not in the index)");
+            }
+            for (depth, node) in path.nodes.iter().enumerate() {
+                println!(
+                    "{:indent$}{} - {}",
+                    "",
+                    node.code,
+                    node.name,
+                    indent = depth * 2 + 2
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use umls::{
+    files::Files,
+    index::{CrosswalkOptions, Index},
+};
+
+#[derive(Args, Debug)]
+pub struct CrosswalkArgs {
+    /// The source of the code to map, e.g. SNOMEDCT_US
+    pub source: Option<SmolStr>,
+
+    /// The code to map
+    pub code: Option<SmolStr>,
+
+    /// The sources to map the code into. If omitted, codes from all other sources are returned.
+    #[clap(short = 't', long = "target")]
+    pub targets: Vec<SmolStr>,
+
+    /// Also return the codes of descendant concepts
+    #[clap(long = "descendants")]
+    pub descendants: bool,
+
+    /// Map every code in this CSV file, which must have `source` and `code` columns, and write
+    /// the results as CSV. Use - to read from stdin.
+    #[clap(short = 'b', long = "batch")]
+    pub batch: Option<PathBuf>,
+
+    /// Write the batch results to this file instead of stdout
+    #[clap(short = 'o', long = "output", requires = "batch")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct BatchInput {
+    source: SmolStr,
+    code: SmolStr,
+}
+
+#[derive(Serialize)]
+struct BatchOutput<'a> {
+    source: &'a str,
+    code: &'a str,
+    cui: &'a str,
+    target_source: &'a str,
+    target_code: &'a str,
+    target_cui: &'a str,
+    descendant: Option<bool>,
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: CrosswalkArgs) -> Result<()> {
+    let index = Index::new(&base_dir.join("index"))?;
+    let options = CrosswalkOptions {
+        target_sources: args.targets,
+        include_descendants: args.descendants,
+    };
+
+    match (args.batch, args.source, args.code) {
+        (Some(input), None, None) => run_batch(&index, &options, &input, args.output.as_deref()),
+        (None, Some(source), Some(code)) => {
+            let matches = index.crosswalk(&source, &code, &options);
+            if matches.is_empty() {
+                println!("No codes found for {source} {code}");
+            }
+
+            for m in matches {
+                let name = index
+                    .find_cui(&m.target_cui)
+                    .map(|id| index.concept(id).preferred_name())
+                    .unwrap_or_default();
+                let descendant = if m.descendant { " (descendant)" } else { "" };
+                println!(
+                    "{} {} - {} {}{descendant}",
+                    m.target_source, m.target_code, m.target_cui, name
+                );
+            }
+
+            Ok(())
+        }
+        _ => Err(eyre!("Pass either a source and code, or --batch")),
+    }
+}
+
+fn run_batch(
+    index: &Index,
+    options: &CrosswalkOptions,
+    input: &Path,
+    output: Option<&Path>,
+) -> Result<()> {
+    let reader: Box<dyn std::io::Read> = if input == Path::new("-") {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(std::fs::File::open(input)?)
+    };
+    let writer: Box<dyn std::io::Write> = match output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut writer = csv::Writer::from_writer(writer);
+
+    for row in reader.deserialize() {
+        let row: BatchInput = row?;
+        let matches = index.crosswalk(&row.source, &row.code, options);
+
+        // Write a row even when nothing matched, so that every input code appears in the output.
+        if matches.is_empty() {
+            writer.serialize(BatchOutput {
+                source: &row.source,
+                code: &row.code,
+                cui: "",
+                target_source: "",
+                target_code: "",
+                target_cui: "",
+                descendant: None,
+            })?;
+        }
+
+        for m in &matches {
+            writer.serialize(BatchOutput {
+                source: &row.source,
+                code: &row.code,
+                cui: &m.cui,
+                target_source: &m.target_source,
+                target_code: &m.target_code,
+                target_cui: &m.target_cui,
+                descendant: Some(m.descendant),
+            })?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
@@ -5,7 +5,7 @@ use eyre::Result;
 use itertools::Itertools;
 use umls::{
     files::Files,
-    index::{build::read_semantic_types, Index},
+    index::{build::read_semantic_types, semantic_group, Index},
 };
 
 #[derive(Debug, Args)]
@@ -26,7 +26,12 @@ pub fn run(base_dir: &Path, files: Files, args: ListTypesArgs) -> Result<()> {
     types
         .into_values()
         .sorted_by(|a, b| a.tree_number.cmp(&b.tree_number))
-        .for_each(|t| println!("{} - {}", t.tree_number, t.name));
+        .for_each(|t| {
+            let group = semantic_group(&t.tui)
+                .map(|g| g.abbreviation)
+                .unwrap_or("-");
+            println!("{} - {} ({group})", t.tree_number, t.name)
+        });
 
     Ok(())
 }
@@ -0,0 +1,49 @@
+use std::path::Path;
+
+use clap::Args;
+use eyre::Result;
+use smol_str::SmolStr;
+use umls::{files::Files, index::Index};
+
+#[derive(Args, Debug)]
+pub struct MappingsArgs {
+    /// The source of the code to map, e.g. SNOMEDCT_US
+    pub source: SmolStr,
+
+    /// The code to map
+    pub code: SmolStr,
+
+    /// Only show mappings into this source, e.g. ICD10CM
+    #[clap(short = 't', long = "target")]
+    pub target: Option<SmolStr>,
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: MappingsArgs) -> Result<()> {
+    let index = Index::new(&base_dir.join("index"))?;
+
+    let mappings = index.mappings(&args.source, &args.code, args.target.as_deref());
+    if mappings.is_empty() {
+        println!("No mappings found for {} {}", args.source, args.code);
+    }
+
+    for m in mappings {
+        let target = if m.target_code.is_empty() {
+            "(no target)"
+        } else {
+            &m.target_code
+        };
+        if m.group > 0 {
+            print!("Group {}, priority {}: ", m.group, m.priority);
+        }
+        println!("{} {target} (map set {})", m.target_source, m.map_set);
+
+        if !m.rule.is_empty() {
+            println!("  Rule: {}", m.rule);
+        }
+        if !m.advice.is_empty() {
+            println!("  Advice: {}", m.advice);
+        }
+    }
+
+    Ok(())
+}
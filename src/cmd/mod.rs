@@ -1,10 +1,22 @@
+mod annotate;
+mod attributes;
 mod build_index;
+mod code;
+mod crosswalk;
 mod extract;
 mod list_files;
 mod list_sources;
 mod list_types;
+mod mappings;
+mod path;
+mod relations;
+mod resolve_cui;
 mod search;
+mod serve;
+mod similarity;
 mod stats;
+mod subset;
+mod verify;
 
 use std::path::PathBuf;
 
@@ -28,9 +40,23 @@ pub enum Command {
     ListSources(list_sources::ListSourcesArgs),
     ListTypes(list_types::ListTypesArgs),
     Extract(extract::ExtractArgs),
+    Subset(subset::SubsetArgs),
     BuildIndex(build_index::BuildIndexArgs),
     Search(search::SearchArgs),
+    Code(code::CodeArgs),
+    ResolveCui(resolve_cui::ResolveCuiArgs),
+    Crosswalk(crosswalk::CrosswalkArgs),
+    Mappings(mappings::MappingsArgs),
+    Attributes(attributes::AttributesArgs),
+    Path(path::PathArgs),
+    Relations(relations::RelationsArgs),
+    Similarity(similarity::SimilarityArgs),
+    Annotate(annotate::AnnotateArgs),
+    Serve(serve::ServeArgs),
     Stats,
+    /// Check the release files against the row and byte counts in MRFILES, and any MD5
+    /// checksum files
+    Verify,
 }
 
 pub fn run(args: Args) -> Result<()> {
@@ -45,9 +71,21 @@ pub fn run(args: Args) -> Result<()> {
         Command::ListFiles(a) => list_files::run(files, a),
         Command::ListSources(a) => list_sources::run(files, a),
         Command::ListTypes(a) => list_types::run(&dir, files, a),
+        Command::Subset(a) => subset::run(files, a),
         Command::BuildIndex(a) => build_index::run(&dir, files, a),
         Command::Search(a) => search::run(&dir, files, a),
+        Command::Code(a) => code::run(&dir, files, a),
+        Command::ResolveCui(a) => resolve_cui::run(&dir, files, a),
+        Command::Crosswalk(a) => crosswalk::run(&dir, files, a),
+        Command::Mappings(a) => mappings::run(&dir, files, a),
+        Command::Attributes(a) => attributes::run(&dir, files, a),
+        Command::Path(a) => path::run(&dir, files, a),
+        Command::Relations(a) => relations::run(&dir, files, a),
+        Command::Similarity(a) => similarity::run(&dir, files, a),
+        Command::Annotate(a) => annotate::run(&dir, files, a),
+        Command::Serve(a) => serve::run(&dir, files, a),
         Command::Stats => stats::run(&dir, files),
+        Command::Verify => verify::run(files),
         Command::Extract(_) => unreachable!(),
     }
 }
@@ -0,0 +1,75 @@
+use std::path::Path;
+
+use clap::Args;
+use eyre::{eyre, Result};
+use umls::{
+    files::Files,
+    index::{Index, RelationKinds, TraversalOptions},
+};
+
+#[derive(Args, Debug)]
+pub struct PathArgs {
+    /// The CUI to start from
+    pub from: String,
+
+    /// The CUI to find a path to
+    pub to: String,
+
+    /// The kinds of relationship to follow: parent, broader, or all
+    #[clap(short = 'k', long = "kind", default_value = "all")]
+    pub kind: RelationKinds,
+
+    /// The maximum number of steps up from each concept
+    #[clap(long = "max-depth")]
+    pub max_depth: Option<u32>,
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: PathArgs) -> Result<()> {
+    let index = Index::new(&base_dir.join("index"))?;
+    let find = |cui: &str| {
+        index
+            .find_cui(cui)
+            .ok_or_else(|| eyre!("Concept {cui} not found"))
+    };
+    let from = find(&args.from)?;
+    let to = find(&args.to)?;
+
+    let options = TraversalOptions {
+        max_depth: args.max_depth,
+        kinds: args.kind,
+        max_results: None,
+    };
+
+    let Some(path) = index.path_between(from, to, &options) else {
+        println!("No path found");
+        return Ok(());
+    };
+
+    let first = index.concept(from);
+    println!("{} - {}", first.cui(), first.preferred_name());
+    for step in path.windows(2) {
+        let concept = index.concept(step[1]);
+        let relation = if index
+            .parents_of(step[0], options.kinds)
+            .any(|p| p == step[1])
+        {
+            "is a"
+        } else {
+            "has child"
+        };
+        println!(
+            "  {relation} {} - {}",
+            concept.cui(),
+            concept.preferred_name()
+        );
+    }
+
+    println!();
+    println!("Lowest common ancestors:");
+    for id in index.lowest_common_ancestors(from, to, &options) {
+        let concept = index.concept(id);
+        println!("  {} - {}", concept.cui(), concept.preferred_name());
+    }
+
+    Ok(())
+}
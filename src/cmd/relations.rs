@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use clap::Args;
+use eyre::{eyre, Result};
+use smol_str::SmolStr;
+use umls::{
+    files::Files,
+    index::{Index, RelationFilter},
+};
+
+#[derive(Args, Debug)]
+pub struct RelationsArgs {
+    /// The CUI of the concept
+    pub cui: String,
+
+    /// Only show relationships with this REL, e.g. RO
+    #[clap(short = 'r', long = "rel")]
+    pub rels: Vec<SmolStr>,
+
+    /// Only show relationships with this RELA, e.g. may_treat
+    #[clap(short = 'a', long = "rela")]
+    pub relas: Vec<SmolStr>,
+
+    /// Only show relationships asserted by this source
+    #[clap(short = 's', long = "source")]
+    pub sources: Vec<SmolStr>,
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: RelationsArgs) -> Result<()> {
+    let index = Index::new(&base_dir.join("index"))?;
+    let id = index
+        .find_cui(&args.cui)
+        .ok_or_else(|| eyre!("Concept {} not found", args.cui))?;
+
+    let filter = RelationFilter {
+        rels: args.rels,
+        relas: args.relas,
+        sources: args.sources,
+    };
+
+    let relations = index.relations(id, &filter);
+    if relations.is_empty() {
+        println!("No relationships found");
+    }
+
+    // Relationships read from the other concept, e.g. "metformin may_treat diabetes".
+    for relation in relations {
+        let other = index.concept(relation.concept_id);
+        let label = if relation.rela.is_empty() {
+            &relation.rel
+        } else {
+            &relation.rela
+        };
+        println!(
+            "{} - {} {label} ({})",
+            other.cui(),
+            other.preferred_name(),
+            relation.source
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use std::{
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use eyre::{eyre, Result};
+use itertools::Itertools;
+use serde::Serialize;
+use umls::{
+    files::Files,
+    index::{CuiResolution, Index},
+};
+
+#[derive(Args, Debug)]
+pub struct ResolveCuiArgs {
+    /// The CUIs to resolve
+    pub cuis: Vec<String>,
+
+    /// Resolve every CUI in this file, one per line, and write the results as CSV. Use - to read
+    /// from stdin.
+    #[clap(short = 'b', long = "batch", conflicts_with = "cuis")]
+    pub batch: Option<PathBuf>,
+
+    /// Write the batch results to this file instead of stdout
+    #[clap(short = 'o', long = "output", requires = "batch")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct BatchOutput<'a> {
+    input: &'a str,
+    cui: &'a str,
+    reason: &'a str,
+    version: &'a str,
+    path: &'a str,
+}
+
+/// A short description of how the CUI was resolved: "current" for a CUI that is still in use,
+/// and otherwise the last change to it.
+fn reason(resolution: &CuiResolution) -> &'static str {
+    resolution
+        .changes
+        .last()
+        .map(|change| change.kind.name())
+        .unwrap_or("current")
+}
+
+/// The CUIs that the history passed through, separated by `>`.
+fn path(resolution: &CuiResolution) -> String {
+    resolution
+        .changes
+        .iter()
+        .map(|change| change.from.as_str())
+        .chain(resolution.cui.as_deref())
+        .join(">")
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: ResolveCuiArgs) -> Result<()> {
+    let index = Index::new(&base_dir.join("index"))?;
+
+    if let Some(input) = args.batch {
+        return run_batch(&index, &input, args.output.as_deref());
+    }
+
+    if args.cuis.is_empty() {
+        return Err(eyre!("Pass one or more CUIs, or --batch"));
+    }
+
+    for cui in &args.cuis {
+        let resolutions = index.resolve_cui(cui);
+        if resolutions.is_empty() {
+            println!("{cui}: Not found");
+        }
+
+        for resolution in resolutions {
+            let version = resolution
+                .changes
+                .last()
+                .map(|change| change.version.as_str())
+                .filter(|version| !version.is_empty())
+                .map(|version| format!(" in {version}"))
+                .unwrap_or_default();
+            match &resolution.cui {
+                Some(current) => {
+                    let name = index
+                        .find_cui(current)
+                        .map(|id| index.concept(id).preferred_name())
+                        .unwrap_or_default();
+                    println!(
+                        "{cui}: {current} - {name} ({}{version}, {})",
+                        reason(&resolution),
+                        path(&resolution)
+                    );
+                }
+                None => println!(
+                    "{cui}: No current CUI ({}{version}, {})",
+                    reason(&resolution),
+                    path(&resolution)
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_batch(index: &Index, input: &Path, output: Option<&Path>) -> Result<()> {
+    let reader: Box<dyn BufRead> = if input == Path::new("-") {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(std::io::BufReader::new(std::fs::File::open(input)?))
+    };
+    let writer: Box<dyn std::io::Write> = match output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut writer = csv::Writer::from_writer(writer);
+
+    for line in reader.lines() {
+        let line = line?;
+        let input = line.trim();
+        if input.is_empty() {
+            continue;
+        }
+
+        let resolutions = index.resolve_cui(input);
+        // Write a row even when nothing matched, so that every input CUI appears in the output.
+        if resolutions.is_empty() {
+            writer.serialize(BatchOutput {
+                input,
+                cui: "",
+                reason: "not_found",
+                version: "",
+                path: "",
+            })?;
+        }
+
+        for resolution in &resolutions {
+            writer.serialize(BatchOutput {
+                input,
+                cui: resolution.cui.as_deref().unwrap_or_default(),
+                reason: reason(resolution),
+                version: resolution
+                    .changes
+                    .last()
+                    .map(|change| change.version.as_str())
+                    .unwrap_or_default(),
+                path: &path(resolution),
+            })?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
@@ -2,12 +2,11 @@ use std::path::Path;
 
 use clap::Args;
 use eyre::Result;
-use fst::Streamer;
 use itertools::Itertools;
 use smol_str::SmolStr;
 use umls::{
     files::Files,
-    index::{score::jaccard_trigram_distance, Index},
+    index::{Index, SearchOptions, Suppress},
 };
 
 #[derive(Args, Debug)]
@@ -30,6 +29,19 @@ pub struct SearchArgs {
     /// The minimum score, using Jaccard trigram similarity, when performing fuzzy search
     #[clap(short = 't', long = "score-threshold", default_value_t = 0.7)]
     pub score_threshold: f32,
+
+    /// The maximum number of results to show
+    #[clap(short = 'n', long = "limit")]
+    pub limit: Option<usize>,
+
+    /// Only show concepts with atoms from these sources
+    #[clap(short = 's', long = "source")]
+    pub sources: Vec<SmolStr>,
+
+    /// Only show concepts with these semantic type tree numbers (and their children)
+    /// or semantic groups, such as DISO
+    #[clap(long = "type")]
+    pub semantic_types: Vec<SmolStr>,
 }
 
 fn print_sorted_concept_list(label: &str, ids: &[u32], index: &Index) {
@@ -40,113 +52,135 @@ fn print_sorted_concept_list(label: &str, ids: &[u32], index: &Index) {
     println!("{label}:");
 
     ids.iter()
-        .map(|&id| &index.concepts[id as usize])
-        .sorted_by_key(|c| &c.cui)
+        .map(|&id| index.concept(id))
+        .sorted_by_key(|c| c.cui())
         .for_each(|concept| {
-            println!("  {} - {}", concept.cui, concept.preferred_name);
+            println!("  {} - {}", concept.cui(), concept.preferred_name());
         });
 }
 
+pub(crate) fn print_concept(index: &Index, id: u64, long: bool, code_types: &[SmolStr]) {
+    let concept = index.concept_id(id);
+    if long {
+        println!("{} - {}", concept.cui(), concept.preferred_name());
+
+        let mut definitions = concept.definitions().peekable();
+        if definitions.peek().is_some() {
+            println!("Definitions:");
+            for definition in definitions {
+                println!("  {}: {}", definition.source, definition.text);
+            }
+        }
+
+        println!("Semantic Types:");
+        for id in concept.types() {
+            if let Some(type_data) = index.semantic_types.get(&id) {
+                println!("  {} - {}", type_data.tree_number, type_data.name);
+            }
+        }
+
+        if concept.has_codes() {
+            println!("Codes:");
+            for (code_concept_id, code) in index.downstream_codes(id as u32, code_types) {
+                let code_concept = index.concept(code_concept_id as u32);
+                println!("  {} {}: {}", code_concept.cui(), code.source, code.code);
+            }
+        }
+
+        let mut atoms = concept.atoms().peekable();
+        if atoms.peek().is_some() {
+            println!("Also Known As:");
+            for atom in atoms {
+                let suppressed = if atom.suppress == Suppress::No {
+                    ""
+                } else {
+                    " (suppressed)"
+                };
+                println!(
+                    "  {} - {} {}{}",
+                    atom.string, atom.source, atom.tty, suppressed
+                );
+            }
+        }
+
+        print_sorted_concept_list("Parents", concept.parents(), index);
+        print_sorted_concept_list("Children", concept.children(), index);
+        print_sorted_concept_list("Similar", concept.similar(), index);
+        print_sorted_concept_list("Synonyms", concept.synonym(), index);
+        print_sorted_concept_list(
+            "Related, possibly synonymous",
+            concept.related_possibly_synonymous(),
+            index,
+        );
+        print_sorted_concept_list("Allowed Qualifiers", concept.allowed_qualifier(), index);
+        print_sorted_concept_list("Qualified By", concept.qualified_by(), index);
+        print_sorted_concept_list("Other Relationship", concept.other_relationship(), index);
+    } else {
+        println!("{} - {}", concept.cui(), concept.preferred_name());
+        if concept.has_codes() {
+            print!("Codes:");
+            for code in concept.codes() {
+                print!(" ({}, {})", code.source, code.code);
+            }
+        }
+    }
+
+    println!();
+}
+
 pub fn run(base_dir: &Path, _files: Files, args: SearchArgs) -> Result<()> {
     let dir = base_dir.join("index");
     let index = umls::index::Index::new(&dir)?;
 
+    let options = SearchOptions {
+        max_edits: args.fuzzy,
+        score_threshold: args.score_threshold,
+        limit: args.limit,
+        sources: args.sources.clone(),
+        semantic_types: args.semantic_types.clone(),
+    };
+
     let start_time = std::time::Instant::now();
+    let results = index.search_ranked(&args.word, &options)?;
+    let duration = start_time.elapsed();
+
     if args.fuzzy == 0 {
-        match index.search(&args.word)? {
-            Some(w) => {
-                let duration = start_time.elapsed();
-                let concept = index.concept_id(w);
-                if args.long {
-                    println!(
-                        "Found in {}us\n{} - {}",
-                        duration.as_micros(),
-                        concept.cui,
-                        concept.preferred_name
-                    );
-
-                    println!("Semantic Types:");
-                    for id in &concept.types {
-                        if let Some(type_data) = index.semantic_types.get(id) {
-                            println!("  {} - {}", type_data.tree_number, type_data.name);
-                        }
-                    }
-
-                    if !concept.codes.is_empty() {
-                        println!("Codes:");
-                        for (code_concept_id, code) in
-                            index.downstream_codes(w as u32, &args.code_types)
-                        {
-                            let code_concept = &index.concepts[code_concept_id];
-                            println!("  {} {}: {}", code_concept.cui, code.source, code.code);
-                        }
-                    }
-
-                    print_sorted_concept_list("Parents", &concept.parents, &index);
-                    print_sorted_concept_list("Children", &concept.children, &index);
-                    print_sorted_concept_list("Similar", &concept.similar, &index);
-                    print_sorted_concept_list("Synonyms", &concept.synonym, &index);
-                    print_sorted_concept_list(
-                        "Related, possibly synonymous",
-                        &concept.related_possibly_synonymous,
-                        &index,
-                    );
-                    print_sorted_concept_list(
-                        "Allowed Qualifiers",
-                        &concept.allowed_qualifier,
-                        &index,
-                    );
-                    print_sorted_concept_list("Qualified By", &concept.qualified_by, &index);
-                    print_sorted_concept_list(
-                        "Other Relationship",
-                        &concept.other_relationship,
-                        &index,
-                    );
-                } else {
-                    println!(
-                        "Found ({}us) {} - {}",
-                        duration.as_micros(),
-                        concept.cui,
-                        concept.preferred_name
-                    );
-                    if !concept.codes.is_empty() {
-                        print!("Codes:");
-                        for code in &concept.codes {
-                            print!(" ({}, {})", code.source, code.code);
-                        }
-                    }
+        if results.is_empty() {
+            println!("Not found");
+            for resolution in index.resolve_cui(&args.word) {
+                if let Some(cui) = resolution.cui {
+                    println!("{} has been replaced by {cui}", args.word);
                 }
-
-                println!();
             }
-            None => println!("Not found"),
-        }
-    } else {
-        let mut output = index.fuzzy_search(&args.word, args.fuzzy)?;
-        let mut results = Vec::new();
-        while let Some((s, id, _)) = output.next() {
-            let found = std::str::from_utf8(s)?.to_string();
-            let score = jaccard_trigram_distance(&args.word, &found);
-            if score >= args.score_threshold {
-                results.push((score, id, found));
+        } else {
+            println!(
+                "Found {} concept{} in {}us",
+                results.len(),
+                if results.len() == 1 { "" } else { "s" },
+                duration.as_micros()
+            );
+
+            for hit in results {
+                print_concept(&index, hit.concept_id as u64, args.long, &args.code_types);
             }
         }
-
-        results.sort_by(|(ascore, _, _), (bscore, _, _)| bscore.total_cmp(ascore));
-        let duration = start_time.elapsed();
+    } else {
         println!("Search completed in {}us", duration.as_micros());
 
         if results.is_empty() {
             println!("No results found");
         } else {
-            for (score, id, s) in results {
-                let concept = index.concept_id(id);
+            for hit in results {
+                let concept = index.concept(hit.concept_id);
                 println!(
-                    "{s} ({score:.2}) - {} - {}",
-                    concept.cui, concept.preferred_name
+                    "{} ({:.2}) - {} - {}",
+                    hit.string,
+                    hit.score,
+                    concept.cui(),
+                    concept.preferred_name()
                 );
-                if !concept.codes.is_empty() {
-                    println!("  Codes: {:?}", concept.codes);
+                if concept.has_codes() {
+                    println!("  Codes: {:?}", concept.codes().collect::<Vec<_>>());
                 }
             }
         }
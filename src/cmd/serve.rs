@@ -0,0 +1,33 @@
+use std::{path::Path, sync::Arc};
+
+use clap::Args;
+use eyre::Result;
+use umls::{files::Files, index::Index, server::serve};
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// The address to listen on
+    #[clap(
+        short = 'a',
+        long = "address",
+        env = "UMLS_ADDRESS",
+        default_value = "127.0.0.1:8080"
+    )]
+    pub address: String,
+
+    /// The number of worker threads used to handle requests. Defaults to the number of CPUs.
+    #[clap(short = 't', long = "threads")]
+    pub threads: Option<usize>,
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: ServeArgs) -> Result<()> {
+    let index = Arc::new(Index::new(&base_dir.join("index"))?);
+    let threads = args.threads.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4)
+    });
+
+    println!("Listening on http://{}", args.address);
+    serve(index, args.address.as_str(), threads)
+}
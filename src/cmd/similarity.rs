@@ -0,0 +1,61 @@
+use std::path::Path;
+
+use clap::Args;
+use eyre::{eyre, Result};
+use umls::{
+    files::Files,
+    index::{Index, RelationKinds, Similarity, SimilarityMeasure, TraversalOptions},
+};
+
+#[derive(Args, Debug)]
+pub struct SimilarityArgs {
+    /// The first CUI
+    pub a: String,
+
+    /// The second CUI
+    pub b: String,
+
+    /// The measures to calculate: path, wup, lch, resnik, lin, or jcn. If omitted, all measures
+    /// are shown.
+    #[clap(short = 'm', long = "measure")]
+    pub measures: Vec<SimilarityMeasure>,
+
+    /// The kinds of relationship that make up the hierarchy: parent, broader, or all
+    #[clap(short = 'k', long = "kind", default_value = "all")]
+    pub kind: RelationKinds,
+}
+
+pub fn run(base_dir: &Path, _files: Files, args: SimilarityArgs) -> Result<()> {
+    let index = Index::new(&base_dir.join("index"))?;
+    let find = |cui: &str| {
+        index
+            .find_cui(cui)
+            .ok_or_else(|| eyre!("Concept {cui} not found"))
+    };
+    let a = find(&args.a)?;
+    let b = find(&args.b)?;
+
+    let similarity = Similarity::new(
+        &index,
+        TraversalOptions {
+            max_depth: None,
+            kinds: args.kind,
+            max_results: None,
+        },
+    );
+
+    let measures = if args.measures.is_empty() {
+        SimilarityMeasure::ALL.to_vec()
+    } else {
+        args.measures
+    };
+
+    for measure in measures {
+        match similarity.similarity(a, b, measure) {
+            Some(value) => println!("{:<8} {value:.4}", measure.name()),
+            None => println!("{:<8} -", measure.name()),
+        }
+    }
+
+    Ok(())
+}
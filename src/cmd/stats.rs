@@ -9,10 +9,9 @@ pub fn run(dir: &Path, _files: Files) -> Result<()> {
     let index = Index::new(&dir)?;
 
     index
-        .concepts
-        .iter()
-        .flat_map(|c| c.codes.iter())
-        .counts_by(|c| &c.source)
+        .concepts()
+        .flat_map(|c| c.codes())
+        .counts_by(|c| c.source)
         .into_iter()
         .sorted_by(|(aname, _), (bname, _)| aname.cmp(bname))
         .for_each(|c| println!("{:?}", c));
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use eyre::Result;
+use smol_str::SmolStr;
+use umls::files::{Files, SubsetOptions};
+
+#[derive(Debug, Args)]
+pub struct SubsetArgs {
+    /// The directory to write the subset to. The files are written to META and NET directories
+    /// inside it, like a release.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// The source abbreviations (SAB field) to include. If empty, all sources are included.
+    #[arg(short, long)]
+    pub sources: Vec<SmolStr>,
+
+    /// Remove sources with a restriction level (SRL in MRSAB) above this. Level 0 sources have
+    /// no additional restrictions beyond the UMLS license.
+    #[arg(long)]
+    pub max_restriction_level: Option<u8>,
+
+    /// The languages (LAT field) to include. If empty, all languages are included.
+    #[arg(short, long)]
+    pub languages: Vec<SmolStr>,
+
+    /// Remove obsolete and suppressible content
+    #[arg(long)]
+    pub remove_suppressible: bool,
+
+    /// The semantic type tree values (and their children) or semantic groups, such as DISO, to
+    /// include. If empty, all semantic types are included.
+    #[arg(short = 't', long = "types")]
+    pub semantic_types: Vec<SmolStr>,
+}
+
+pub fn run(files: Files, args: SubsetArgs) -> Result<()> {
+    let options = SubsetOptions {
+        sources: args.sources,
+        max_restriction_level: args.max_restriction_level,
+        languages: args.languages,
+        remove_suppressible: args.remove_suppressible,
+        semantic_types: args.semantic_types,
+    };
+
+    let written = files.write_subset(&args.output, &options)?;
+    for file in written {
+        println!(
+            "{} - {} rows, {} bytes",
+            file.filename, file.rows, file.bytes
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use eyre::{eyre, Result};
+use umls::files::Files;
+
+pub fn run(files: Files) -> Result<()> {
+    let problems = files.verify()?;
+    for problem in &problems {
+        println!("{problem}");
+    }
+
+    if !problems.is_empty() {
+        return Err(eyre!("Found {} problems in the release", problems.len()));
+    }
+
+    println!("All files match MRFILES");
+    Ok(())
+}
@@ -17,7 +17,7 @@ pub fn extract_metathesaurus<'a>(mut input_path: &'a Path, output_path: &'a Path
 
     // Extract the data out to get the GZ files.
     for container in containers {
-        let f = std::fs::File::open(&container.path())?;
+        let f = std::fs::File::open(container.path())?;
         let mut zip = zip::ZipArchive::new(f)?;
         zip.extract(output_path)?;
     }
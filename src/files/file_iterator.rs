@@ -7,8 +7,7 @@ use smol_str::SmolStr;
 
 use super::{CarryOverColumns, MAX_CARRYOVER_VALUES};
 
-pub type RrfReader =
-    concat_reader::ConcatReader<Vec<flate2::bufread::GzDecoder<std::io::BufReader<std::fs::File>>>>;
+pub type RrfReader = concat_reader::ConcatReader<Vec<Box<dyn std::io::Read + Send>>>;
 pub type RrfCsvReader = csv::Reader<RrfReader>;
 
 pub struct File {
@@ -27,7 +26,7 @@ impl File {
         })
     }
 
-    pub fn records(&mut self) -> RrfRecordCarryover {
+    pub fn records(&mut self) -> RrfRecordCarryover<'_> {
         RrfRecordCarryover::new(self.reader.records(), self.carry_over_columns.clone())
     }
 }
@@ -36,9 +35,7 @@ type CarryOverValues = SmallVec<[(u8, SmolStr); MAX_CARRYOVER_VALUES]>;
 
 pub struct RrfRecordCarryover<'a> {
     records: csv::StringRecordsIter<'a, RrfReader>,
-    carry_over_columns: CarryOverColumns,
-    last_values: CarryOverValues,
-    last_ptr: SmolStr,
+    state: CarryOverState,
 }
 
 impl<'a> RrfRecordCarryover<'a> {
@@ -47,13 +44,95 @@ impl<'a> RrfRecordCarryover<'a> {
         carry_over_columns: CarryOverColumns,
     ) -> Self {
         Self {
-            carry_over_columns,
             records,
+            state: CarryOverState::new(carry_over_columns),
+        }
+    }
+}
+
+impl<'a> Iterator for RrfRecordCarryover<'a> {
+    type Item = Result<RrfRecord, csv::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.records.next() {
+            Some(Ok(r)) => r,
+            Some(Err(e)) => return Some(Err(e)),
+            None => return None,
+        };
+
+        Some(Ok(self.state.apply(record)))
+    }
+}
+
+/// The values that rows carry over from the last full row of a file.
+#[derive(Clone)]
+pub(super) struct CarryOverState {
+    carry_over_columns: CarryOverColumns,
+    last_values: CarryOverValues,
+    last_ptr: SmolStr,
+}
+
+impl CarryOverState {
+    pub(super) fn new(carry_over_columns: CarryOverColumns) -> Self {
+        Self {
+            carry_over_columns,
             last_ptr: SmolStr::default(),
             last_values: SmallVec::new(),
         }
     }
 
+    /// Check if the record leaves its carry-over columns empty, and so takes their values from
+    /// the last full row.
+    pub(super) fn is_carried_over(&self, record: &csv::StringRecord) -> bool {
+        self.carry_over_columns
+            .columns
+            .first()
+            .is_some_and(|&idx| record.get(idx as usize).unwrap_or_default().is_empty())
+    }
+
+    /// Fill in the carried-over values of the record, or save its values if it is a full row.
+    pub(super) fn apply(&mut self, record: csv::StringRecord) -> RrfRecord {
+        if self.carry_over_columns.columns.is_empty() {
+            return RrfRecord {
+                carryover: SmallVec::new(),
+                record,
+            };
+        }
+
+        if self.is_carried_over(&record) {
+            // This row should use the carried over values.
+            let mut last_values = self.last_values.clone();
+
+            if let Some(ptr) = self.calculate_row_ptr_values(&record) {
+                last_values.push(ptr);
+            };
+
+            RrfRecord {
+                carryover: last_values,
+                record,
+            }
+        } else {
+            // This row is a full row so we should save the new values.
+            let carryover = self
+                .carry_over_columns
+                .columns
+                .iter()
+                .map(|&idx| {
+                    let value = SmolStr::from(record.get(idx as usize).unwrap_or_default());
+                    (idx, value)
+                })
+                .collect();
+
+            self.last_values = carryover;
+            self.save_ptr_values(&record);
+
+            RrfRecord {
+                carryover: SmallVec::new(),
+                record,
+            }
+        }
+    }
+
     /// The PTR field uses a carryover compression method where it inherits the first two values of
     /// this hierarchy from the previous rows, but replaces the later segments. This function saves
     /// the first two segments using the following decisions:
@@ -93,71 +172,19 @@ impl<'a> RrfRecordCarryover<'a> {
     }
 
     fn calculate_row_ptr_values(&self, record: &csv::StringRecord) -> Option<(u8, SmolStr)> {
-        let Some(ptr_idx) = self.carry_over_columns.ptr_column else {
-            return None;
-        };
+        let ptr_idx = self.carry_over_columns.ptr_column?;
 
+        // The token starts with two dots in place of the carried-over segments. A short token
+        // adds nothing to them.
         let token = record.get(ptr_idx as usize).unwrap_or_default();
-        let ptr_value = SmolStr::from(format!("{}.{}", self.last_ptr, &token[2..]));
-
-        Some((ptr_idx, ptr_value))
-    }
-}
-
-impl<'a> Iterator for RrfRecordCarryover<'a> {
-    type Item = Result<RrfRecord, csv::Error>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let record = match self.records.next() {
-            Some(Ok(r)) => r,
-            Some(Err(e)) => return Some(Err(e)),
-            None => return None,
-        };
-
-        if self.carry_over_columns.columns.is_empty() {
-            return Some(Ok(RrfRecord {
-                carryover: SmallVec::new(),
-                record,
-            }));
-        }
-
-        // If we get here we need to process the carryover.
-        if record
-            .get(self.carry_over_columns.columns[0] as usize)
-            .unwrap_or_default()
-            .is_empty()
-        {
-            // This row should use the carried over values.
-            let mut last_values = self.last_values.clone();
-
-            if let Some(ptr) = self.calculate_row_ptr_values(&record) {
-                last_values.push(ptr);
-            };
-
-            Some(Ok(RrfRecord {
-                carryover: last_values,
-                record,
-            }))
+        let rest = token.get(2..).unwrap_or_default();
+        let ptr_value = if rest.is_empty() {
+            self.last_ptr.clone()
         } else {
-            // This row is a full row so we should save the new values.
-            let carryover = self
-                .carry_over_columns
-                .columns
-                .iter()
-                .map(|&idx| {
-                    let value = SmolStr::from(record.get(idx as usize).unwrap_or_default());
-                    (idx, value)
-                })
-                .collect();
-
-            self.last_values = carryover;
-            self.save_ptr_values(&record);
+            SmolStr::from(format!("{}.{}", self.last_ptr, rest))
+        };
 
-            Some(Ok(RrfRecord {
-                carryover: SmallVec::new(),
-                record,
-            }))
-        }
+        Some((ptr_idx, ptr_value))
     }
 }
 
@@ -174,22 +201,48 @@ impl RrfRecord {
 
         self.record.get(i)
     }
+
+    /// Deserialize the record, including its carried-over values, matching the fields to
+    /// `headers` by name.
+    pub fn deserialize<T: serde::de::DeserializeOwned>(
+        &self,
+        headers: &csv::StringRecord,
+    ) -> Result<T, csv::Error> {
+        if self.carryover.is_empty() {
+            return self.record.deserialize(Some(headers));
+        }
+
+        let record = (0..self.record.len())
+            .map(|i| self.get(i).unwrap_or_default())
+            .collect::<csv::StringRecord>();
+        record.deserialize(Some(headers))
+    }
 }
 
-/// Create a CSV decoder stream that concatenates the decompressed output from the
-/// list of .gz files.
-fn create_read_stream(path: &[PathBuf]) -> Result<RrfCsvReader> {
+/// Create a stream that concatenates the contents of the list of files. Files ending in .gz are
+/// decompressed, and the rest are read as plain text.
+pub(super) fn create_raw_stream(path: &[PathBuf]) -> Result<RrfReader> {
     let readers = path
         .iter()
         .map(|path| {
             let file = std::fs::File::open(path)?;
             let bufreader = std::io::BufReader::new(file);
-            let decomp = flate2::bufread::GzDecoder::new(bufreader);
-            Ok(decomp)
+            let reader: Box<dyn std::io::Read + Send> =
+                if path.extension().is_some_and(|ext| ext == "gz") {
+                    Box::new(flate2::bufread::GzDecoder::new(bufreader))
+                } else {
+                    Box::new(bufreader)
+                };
+            Ok(reader)
         })
         .collect::<Result<Vec<_>>>()?;
 
-    Ok(create_csv_reader(ConcatReader::new(readers)))
+    Ok(ConcatReader::new(readers))
+}
+
+/// Create a CSV decoder stream over the concatenated contents of the list of files.
+fn create_read_stream(path: &[PathBuf]) -> Result<RrfCsvReader> {
+    Ok(create_csv_reader(create_raw_stream(path)?))
 }
 
 pub(crate) fn create_csv_reader<R: std::io::Read>(r: R) -> csv::Reader<R> {
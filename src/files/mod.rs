@@ -1,6 +1,10 @@
 mod file_iterator;
 mod find_files;
+mod parallel;
+mod rows;
 mod schema;
+mod subset;
+mod verify;
 
 use std::path::{Path, PathBuf};
 
@@ -10,7 +14,10 @@ use itertools::Itertools;
 use smallvec::{smallvec, SmallVec};
 
 pub(crate) use file_iterator::create_csv_reader;
+pub use rows::*;
 pub use schema::*;
+pub use subset::{SubsetFile, SubsetOptions};
+pub use verify::VerifyProblem;
 
 // This should be one more than the maximum number of columns in get_carry_over_columns,
 // plus one to account for the PTR column.
@@ -36,14 +43,22 @@ impl Files {
 
         let mut files = HashMap::new();
 
-        let entries = glob::glob(&format!("{}/*/*.gz", dir.display()))?;
+        // The CHANGE directory holds the files that track changes from the previous release,
+        // such as DELETEDCUI. Their names don't clash with the main files.
+        let entries = glob::glob(&format!("{}/*/*", dir.display()))?
+            .chain(glob::glob(&format!("{}/*/CHANGE/*", dir.display()))?);
         for file in entries {
             let file = file?;
-            if !file.metadata()?.is_file() {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            if !is_data_file(&name) || !file.metadata()?.is_file() {
+                continue;
+            }
+
+            // Prefer a decompressed copy of a file when both are present.
+            if name.ends_with(".gz") && file.with_extension("").exists() {
                 continue;
             }
 
-            let name = file.file_name().unwrap_or_default().to_string_lossy();
             let base_name = name.split('.').next().unwrap_or_default().to_string();
 
             files
@@ -53,8 +68,15 @@ impl Files {
                 .push(file);
         }
 
-        // read_dir may not return the files in order, so sort them.
-        for (_, file) in files.iter_mut() {
+        for (base_name, file) in files.iter_mut() {
+            // A whole plain file holds the same rows as the parts of a split file, so only read
+            // it when both are present.
+            let whole = format!("{base_name}.RRF");
+            if let Some(plain) = file.locations.iter().find(|l| l.ends_with(&whole)) {
+                file.locations = vec![plain.clone()];
+            }
+
+            // read_dir may not return the files in order, so sort them.
             file.locations.sort_unstable();
         }
 
@@ -67,6 +89,11 @@ impl Files {
         Ok(slf)
     }
 
+    /// Check if the release contains a file. Some files, such as MRHIER, are optional.
+    pub fn has_file(&self, filename: &str) -> bool {
+        self.files.contains_key(filename)
+    }
+
     pub fn get_file_stream(&self, filename: &str) -> Result<File> {
         let locations = self
             .files
@@ -80,8 +107,7 @@ impl Files {
         let mut mrfiles = self.get_file_stream("MRFILES")?;
         for line in mrfiles.records() {
             let line = line?;
-            let filename = line.get(0).unwrap_or_default();
-            let basename = filename.split('.').next().unwrap_or_default();
+            let basename = file_basename(line.get(0).unwrap_or_default());
             let columns = line.get(2).unwrap_or_default();
 
             let columns = columns
@@ -99,6 +125,27 @@ impl Files {
     }
 }
 
+/// Get the name that [Files] uses for a file listed in MRFILES, stripping the directory from
+/// files like CHANGE/DELETEDCUI.RRF along with the extension.
+fn file_basename(filename: &str) -> &str {
+    let filename = filename.rsplit('/').next().unwrap_or_default();
+    filename.split('.').next().unwrap_or_default()
+}
+
+/// Check if a file name looks like a data file. The files can be plain (`MRCONSO.RRF`), split into
+/// parts (`MRCONSO.RRF.aa`), or compressed with gzip (`MRCONSO.RRF.aa.gz`).
+fn is_data_file(name: &str) -> bool {
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    let Some((_, suffix)) = name.split_once(".RRF") else {
+        return false;
+    };
+
+    suffix.is_empty()
+        || (suffix.len() == 3
+            && suffix.starts_with('.')
+            && suffix[1..].bytes().all(|b| b.is_ascii_lowercase()))
+}
+
 #[derive(Clone, Default)]
 struct CarryOverColumns {
     ptr_column: Option<u8>,
@@ -155,3 +202,65 @@ fn get_carry_over_columns(basename: &str, columns: &[String]) -> CarryOverColumn
                         prevPtr2="";
                     }
 */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    #[test]
+    fn data_file_names() {
+        assert!(is_data_file("MRCONSO.RRF"));
+        assert!(is_data_file("MRCONSO.RRF.aa"));
+        assert!(is_data_file("MRCONSO.RRF.ab.gz"));
+        assert!(is_data_file("MRFILES.RRF.gz"));
+        assert!(!is_data_file("MRCONSO.RRF.md5"));
+        assert!(!is_data_file("release.dat"));
+    }
+
+    #[test]
+    fn plain_files() {
+        let data = TestData::new();
+        let files = Files::new(data.dir.path()).unwrap();
+        let output = tempfile::tempdir().unwrap();
+        files
+            .write_subset(output.path(), &SubsetOptions::default())
+            .unwrap();
+
+        // Split MRCONSO into parts, as the release does.
+        let meta = output.path().join("META");
+        let mrconso = std::fs::read_to_string(meta.join("MRCONSO.RRF")).unwrap();
+        let lines = mrconso.lines().collect::<Vec<_>>();
+        let (first, second) = lines.split_at(lines.len() / 2);
+        std::fs::write(meta.join("MRCONSO.RRF.aa"), first.join("\n") + "\n").unwrap();
+        std::fs::write(meta.join("MRCONSO.RRF.ab"), second.join("\n") + "\n").unwrap();
+        std::fs::remove_file(meta.join("MRCONSO.RRF")).unwrap();
+
+        let plain = Files::new(output.path()).unwrap();
+        let mut stream = plain.get_file_stream("MRCONSO").unwrap();
+        assert_eq!(stream.records().count(), lines.len());
+        assert!(plain.has_file("MERGEDCUI"));
+
+        // A whole file next to the parts, and a compressed copy of a part, are not read twice.
+        std::fs::write(meta.join("MRCONSO.RRF"), &mrconso).unwrap();
+        let gz = std::fs::File::create(meta.join("MRCONSO.RRF.ab.gz")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(gz, flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, (second.join("\n") + "\n").as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        let both = Files::new(output.path()).unwrap();
+        let mut stream = both.get_file_stream("MRCONSO").unwrap();
+        assert_eq!(stream.records().count(), lines.len());
+        std::fs::remove_file(meta.join("MRCONSO.RRF")).unwrap();
+        let parts = Files::new(output.path()).unwrap();
+        let mut stream = parts.get_file_stream("MRCONSO").unwrap();
+        assert_eq!(stream.records().count(), lines.len());
+
+        let mut mrsat = plain.get_file_stream("MRSAT").unwrap();
+        let atv_idx = mrsat.columns.iter().position(|c| c == "ATV").unwrap();
+        let values = mrsat
+            .records()
+            .map(|r| r.unwrap().get(atv_idx).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(values[1], "00093-1049-01");
+    }
+}
@@ -0,0 +1,321 @@
+//! Reading the parts of a split file in parallel. Large files such as MRCONSO and MRREL come split
+//! into parts (`MRREL.RRF.aa.gz`, `MRREL.RRF.ab.gz`, ...), and decompressing and parsing them
+//! is most of the work of reading them, so each part is read on its own thread.
+//!
+//! The parts are split at arbitrary bytes, so a row can start at the end of one part and finish
+//! at the start of the next, and a part can start with rows that carry over values from the end
+//! of the previous part. Those rows are set aside while the part is read, and then put together
+//! and filled in once the previous parts are done.
+//!
+//! [Files::par_fold_rows] folds the rows of each part into its own accumulator, so that callers
+//! can build their structures from each part without holding every row in memory, and then merge
+//! the accumulators in order.
+
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use eyre::{eyre, Result};
+use rayon::prelude::*;
+
+use super::{
+    create_csv_reader,
+    file_iterator::{create_raw_stream, CarryOverState, RrfRecord},
+    rows::row_headers,
+    FileMetadata, Files, RrfRow,
+};
+
+/// The results from reading one part of a file.
+struct Part<A> {
+    /// The bytes before the first newline in the part, which finish the last row of the
+    /// previous part. This is empty for the first part.
+    head: Vec<u8>,
+    /// The bytes after the last newline in the part, which start a row that finishes in the
+    /// next part
+    tail: Vec<u8>,
+    /// Rows at the start of the part that carry over values from the previous part
+    leading_rows: Vec<csv::StringRecord>,
+    /// The rows of the part, folded into its accumulator
+    output: A,
+    /// The carry-over state at the end of the part, or `None` if the part had no full rows
+    state: Option<CarryOverState>,
+}
+
+impl Files {
+    /// Read the rows of a file as `R`, reading the parts of the file in parallel, and return the
+    /// results of `f` for each row in the order of the file. Rows for which `f` returns `None`
+    /// are skipped.
+    ///
+    /// `f` runs on many threads at once, so filtering and converting the rows in `f` also
+    /// spreads that work across the threads, and keeps unneeded rows out of memory.
+    pub fn par_map_rows<R, T, F>(&self, f: F) -> Result<Vec<T>>
+    where
+        R: RrfRow,
+        T: Send,
+        F: Fn(R) -> Option<T> + Sync,
+    {
+        let parts = self.par_fold_rows(Vec::new, |output, row: R| {
+            if let Some(value) = f(row) {
+                output.push(value);
+            }
+        })?;
+
+        let mut output = Vec::with_capacity(parts.iter().map(|p| p.len()).sum());
+        for part in parts {
+            output.extend(part);
+        }
+        Ok(output)
+    }
+
+    /// Read the rows of a file as `R`, reading the parts of the file in parallel. Each part
+    /// starts with an accumulator from `init`, and `fold` adds each row of the part to it. The
+    /// accumulators are returned in the order of the parts, and together they hold the rows in
+    /// the order of the file.
+    ///
+    /// Rows that are split across parts or carry over values from the previous part are folded
+    /// into the accumulator of the previous part once it is done, so an accumulator may end with
+    /// some rows from the start of the next part.
+    pub fn par_fold_rows<R, A, I, F>(&self, init: I, fold: F) -> Result<Vec<A>>
+    where
+        R: RrfRow,
+        A: Send,
+        I: Fn() -> A + Sync,
+        F: Fn(&mut A, R) + Sync,
+    {
+        let file = self
+            .files
+            .get(R::FILE)
+            .ok_or_else(|| eyre!("No file named {}", R::FILE))?;
+        let headers = row_headers::<R>(&file.columns)?;
+
+        let parts = file
+            .locations
+            .par_iter()
+            .enumerate()
+            .map(|(i, path)| read_part(file, path, i == 0, &headers, init(), &fold))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut output: Vec<A> = Vec::with_capacity(parts.len());
+        let mut state = CarryOverState::new(file.carry_over_columns.clone());
+        let apply = |record: csv::StringRecord,
+                     state: &mut CarryOverState,
+                     output: &mut Vec<A>|
+         -> Result<()> {
+            let row = deserialize::<R>(&state.apply(record), &headers)?;
+            let previous = output
+                .last_mut()
+                .expect("The first part has no rows from before it");
+            fold(previous, row);
+            Ok(())
+        };
+
+        // A row split across parts. This can span several parts if a part has no newlines.
+        let mut split_row = Vec::new();
+        for part in parts {
+            split_row.extend_from_slice(&part.head);
+            if split_row.ends_with(b"\n") {
+                for record in create_csv_reader(split_row.as_slice()).records() {
+                    apply(record?, &mut state, &mut output)?;
+                }
+                split_row.clear();
+            }
+
+            for record in part.leading_rows {
+                apply(record, &mut state, &mut output)?;
+            }
+
+            output.push(part.output);
+            if let Some(part_state) = part.state {
+                state = part_state;
+            }
+
+            split_row.extend_from_slice(&part.tail);
+        }
+
+        // The last row of the file may not end with a newline.
+        for record in create_csv_reader(split_row.as_slice()).records() {
+            apply(record?, &mut state, &mut output)?;
+        }
+
+        Ok(output)
+    }
+}
+
+fn read_part<R, A, F>(
+    file: &FileMetadata,
+    path: &Path,
+    first: bool,
+    headers: &csv::StringRecord,
+    mut output: A,
+    fold: &F,
+) -> Result<Part<A>>
+where
+    R: RrfRow,
+    F: Fn(&mut A, R),
+{
+    let mut stream = BufReader::new(create_raw_stream(&[path.to_path_buf()])?);
+    let mut head = Vec::new();
+    if !first {
+        stream.read_until(b'\n', &mut head)?;
+    }
+
+    let mut reader = create_csv_reader(WholeLines::new(stream));
+    let mut state = CarryOverState::new(file.carry_over_columns.clone());
+    let mut leading_rows = Vec::new();
+    // Nothing comes before the first part, so its rows never carry over from elsewhere.
+    let mut seen_full_row = first;
+
+    for record in reader.records() {
+        let record = record?;
+        if !seen_full_row && state.is_carried_over(&record) {
+            leading_rows.push(record);
+            continue;
+        }
+
+        seen_full_row = true;
+        fold(
+            &mut output,
+            deserialize::<R>(&state.apply(record), headers)?,
+        );
+    }
+
+    Ok(Part {
+        head,
+        tail: reader.into_inner().rest,
+        leading_rows,
+        output,
+        state: seen_full_row.then_some(state),
+    })
+}
+
+/// A reader that only returns whole lines from `inner`, keeping the bytes after the last newline
+/// in `rest`.
+struct WholeLines<T> {
+    inner: T,
+    lines: Vec<u8>,
+    pos: usize,
+    rest: Vec<u8>,
+}
+
+impl<T: Read> WholeLines<T> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            lines: Vec::new(),
+            pos: 0,
+            rest: Vec::new(),
+        }
+    }
+}
+
+impl<T: Read> Read for WholeLines<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut chunk = [0; 1 << 16];
+        while self.pos == self.lines.len() {
+            let len = self.inner.read(&mut chunk)?;
+            if len == 0 {
+                return Ok(0);
+            }
+
+            let chunk = &chunk[..len];
+            match chunk.iter().rposition(|&b| b == b'\n') {
+                Some(end) => {
+                    self.lines.clear();
+                    self.lines.append(&mut self.rest);
+                    self.lines.extend_from_slice(&chunk[..=end]);
+                    self.rest.extend_from_slice(&chunk[end + 1..]);
+                    self.pos = 0;
+                }
+                None => self.rest.extend_from_slice(chunk),
+            }
+        }
+
+        let len = buf.len().min(self.lines.len() - self.pos);
+        buf[..len].copy_from_slice(&self.lines[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+fn deserialize<R: RrfRow>(record: &RrfRecord, headers: &csv::StringRecord) -> Result<R> {
+    record
+        .deserialize(headers)
+        .map_err(|e| eyre!("Failed to read {}: {e}", R::FILE))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{files::MrsatRow, test_data::TestData};
+
+    #[test]
+    fn carry_over_across_parts() {
+        let data = TestData::new();
+        let files = Files::new(data.dir.path()).unwrap();
+        let expected = files
+            .mrsat()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        // Split MRSAT at every byte, so that rows and the runs of rows that carry over values
+        // are split across the parts.
+        let meta = data.dir.path().join("META");
+        let mut contents = Vec::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(meta.join("MRSAT.RRF.gz")).unwrap())
+            .read_to_end(&mut contents)
+            .unwrap();
+        std::fs::remove_file(meta.join("MRSAT.RRF.gz")).unwrap();
+
+        let split = |points: &[usize]| {
+            for part in glob::glob(&format!("{}/MRSAT.RRF.*", meta.display())).unwrap() {
+                std::fs::remove_file(part.unwrap()).unwrap();
+            }
+
+            let mut start = 0;
+            for (i, &end) in points.iter().chain([&contents.len()]).enumerate() {
+                let name = format!("MRSAT.RRF.a{}", (b'a' + i as u8) as char);
+                std::fs::write(meta.join(name), &contents[start..end]).unwrap();
+                start = end;
+            }
+
+            Files::new(data.dir.path()).unwrap()
+        };
+
+        for i in 1..contents.len() {
+            let files = split(&[i]);
+            let parallel = files.par_map_rows::<MrsatRow, _, _>(Some).unwrap();
+            assert_eq!(parallel, expected, "split at {i}");
+
+            let counts = files
+                .par_fold_rows(|| 0, |count, _: MrsatRow| *count += 1)
+                .unwrap();
+            assert_eq!(counts.len(), 2);
+            assert_eq!(counts.iter().sum::<usize>(), expected.len());
+        }
+
+        // A part in the middle of a row, with no newlines.
+        let first_row = contents.iter().position(|&b| b == b'\n').unwrap();
+        let files = split(&[10, 20, first_row + 5, first_row + 6, first_row + 30]);
+        assert_eq!(
+            files.par_map_rows::<MrsatRow, _, _>(Some).unwrap(),
+            expected
+        );
+        assert_eq!(
+            files
+                .mrsat()
+                .unwrap()
+                .rows()
+                .collect::<Result<Vec<_>>>()
+                .unwrap(),
+            expected
+        );
+
+        let values = files
+            .par_map_rows(|row: MrsatRow| (row.sab == "RXNORM").then_some(row.atv))
+            .unwrap();
+        assert_eq!(values, ["00093-1048-01", "00093-1049-01"]);
+    }
+}
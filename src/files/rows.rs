@@ -0,0 +1,657 @@
+//! Typed rows for the RRF files. The columns are matched by name, using the column list for each
+//! file in MRFILES, so the rows don't depend on the order of the columns in a release.
+
+use std::marker::PhantomData;
+
+use eyre::{eyre, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use smol_str::SmolStr;
+
+use super::{file_iterator::File, Files};
+
+/// A row of one of the RRF files, which can be read with [Files::rows].
+pub trait RrfRow: DeserializeOwned {
+    /// The name of the file, such as `MRCONSO`
+    const FILE: &'static str;
+    /// The columns that must be present to read the row. Columns that are missing from some
+    /// releases, such as CVF, are left out and default to empty.
+    const COLUMNS: &'static [&'static str];
+}
+
+/// A file being read as typed rows. See [Files::rows].
+pub struct RowFile<T> {
+    file: File,
+    headers: csv::StringRecord,
+    row: PhantomData<T>,
+}
+
+impl<T: RrfRow> RowFile<T> {
+    /// The rows of the file, with any carried-over values filled in.
+    pub fn rows(&mut self) -> impl Iterator<Item = Result<T>> + '_ {
+        let headers = &self.headers;
+        self.file.records().map(move |record| {
+            record?
+                .deserialize(headers)
+                .map_err(|e| eyre!("Failed to read {}: {e}", T::FILE))
+        })
+    }
+}
+
+impl Files {
+    /// Open a file to read its rows as `T`. This fails if the file is missing, or if it lacks
+    /// any of the columns in [RrfRow::COLUMNS].
+    pub fn rows<T: RrfRow>(&self) -> Result<RowFile<T>> {
+        let file = self.get_file_stream(T::FILE)?;
+        let headers = row_headers::<T>(&file.columns)?;
+        Ok(RowFile {
+            file,
+            headers,
+            row: PhantomData,
+        })
+    }
+
+    pub fn mrconso(&self) -> Result<RowFile<MrconsoRow>> {
+        self.rows()
+    }
+
+    pub fn mrrel(&self) -> Result<RowFile<MrrelRow>> {
+        self.rows()
+    }
+
+    pub fn mrsty(&self) -> Result<RowFile<MrstyRow>> {
+        self.rows()
+    }
+
+    pub fn mrsab(&self) -> Result<RowFile<MrsabRow>> {
+        self.rows()
+    }
+
+    pub fn mrdef(&self) -> Result<RowFile<MrdefRow>> {
+        self.rows()
+    }
+
+    pub fn mrsat(&self) -> Result<RowFile<MrsatRow>> {
+        self.rows()
+    }
+
+    pub fn mrrank(&self) -> Result<RowFile<MrrankRow>> {
+        self.rows()
+    }
+
+    pub fn mrcui(&self) -> Result<RowFile<MrcuiRow>> {
+        self.rows()
+    }
+
+    pub fn mergedcui(&self) -> Result<RowFile<MergedCuiRow>> {
+        self.rows()
+    }
+
+    pub fn deletedcui(&self) -> Result<RowFile<DeletedCuiRow>> {
+        self.rows()
+    }
+
+    pub fn mrhier(&self) -> Result<RowFile<MrhierRow>> {
+        self.rows()
+    }
+
+    pub fn mrmap(&self) -> Result<RowFile<MrmapRow>> {
+        self.rows()
+    }
+
+    pub fn mrsmap(&self) -> Result<RowFile<MrsmapRow>> {
+        self.rows()
+    }
+}
+
+/// Get the headers used to deserialize `T` from a file with `columns`, checking that none of the
+/// columns in [RrfRow::COLUMNS] are missing.
+pub(super) fn row_headers<T: RrfRow>(columns: &[String]) -> Result<csv::StringRecord> {
+    let missing = T::COLUMNS
+        .iter()
+        .filter(|c| !columns.iter().any(|f| f == *c))
+        .copied()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(eyre!(
+            "{} is missing the columns {}",
+            T::FILE,
+            missing.join(", ")
+        ));
+    }
+
+    Ok(csv::StringRecord::from(columns.to_vec()))
+}
+
+/// An atom from MRCONSO
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrconsoRow {
+    pub cui: SmolStr,
+    pub lat: SmolStr,
+    /// Term status, `P` for the preferred LUI of the concept
+    pub ts: SmolStr,
+    pub lui: SmolStr,
+    /// String type, `PF` for the preferred form of the term
+    pub stt: SmolStr,
+    pub sui: SmolStr,
+    /// `Y` if this is the preferred atom of the string
+    pub ispref: SmolStr,
+    pub aui: SmolStr,
+    pub saui: SmolStr,
+    pub scui: SmolStr,
+    pub sdui: SmolStr,
+    pub sab: SmolStr,
+    pub tty: SmolStr,
+    pub code: SmolStr,
+    pub str: String,
+    /// The source restriction level
+    pub srl: u8,
+    pub suppress: SmolStr,
+    #[serde(default)]
+    pub cvf: SmolStr,
+}
+
+impl RrfRow for MrconsoRow {
+    const FILE: &'static str = "MRCONSO";
+    const COLUMNS: &'static [&'static str] = &[
+        "CUI", "LAT", "TS", "LUI", "STT", "SUI", "ISPREF", "AUI", "SAUI", "SCUI", "SDUI", "SAB",
+        "TTY", "CODE", "STR", "SRL", "SUPPRESS",
+    ];
+}
+
+/// A relationship from MRREL
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrrelRow {
+    pub cui1: SmolStr,
+    pub aui1: SmolStr,
+    pub stype1: SmolStr,
+    pub rel: SmolStr,
+    pub cui2: SmolStr,
+    pub aui2: SmolStr,
+    pub stype2: SmolStr,
+    pub rela: SmolStr,
+    pub rui: SmolStr,
+    pub srui: SmolStr,
+    pub sab: SmolStr,
+    /// The source of the relationship label
+    pub sl: SmolStr,
+    pub rg: SmolStr,
+    /// `Y` if the source asserted the relationship in this direction, `N` if not, and empty
+    /// if the source doesn't say
+    pub dir: SmolStr,
+    pub suppress: SmolStr,
+    #[serde(default)]
+    pub cvf: SmolStr,
+}
+
+impl RrfRow for MrrelRow {
+    const FILE: &'static str = "MRREL";
+    const COLUMNS: &'static [&'static str] = &[
+        "CUI1", "AUI1", "STYPE1", "REL", "CUI2", "AUI2", "STYPE2", "RELA", "RUI", "SRUI", "SAB",
+        "SL", "RG", "DIR", "SUPPRESS",
+    ];
+}
+
+/// A semantic type of a concept, from MRSTY
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrstyRow {
+    pub cui: SmolStr,
+    pub tui: SmolStr,
+    /// The tree number of the semantic type
+    pub stn: SmolStr,
+    /// The name of the semantic type
+    pub sty: SmolStr,
+    pub atui: SmolStr,
+    #[serde(default)]
+    pub cvf: SmolStr,
+}
+
+impl RrfRow for MrstyRow {
+    const FILE: &'static str = "MRSTY";
+    const COLUMNS: &'static [&'static str] = &["CUI", "TUI", "STN", "STY", "ATUI"];
+}
+
+/// A source, from MRSAB
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrsabRow {
+    pub vcui: SmolStr,
+    pub rcui: SmolStr,
+    /// The versioned source abbreviation, such as `MSH2024`
+    pub vsab: SmolStr,
+    /// The root source abbreviation, such as `MSH`, which is used in the SAB column of the
+    /// other files
+    pub rsab: SmolStr,
+    /// The official name of the source
+    pub son: String,
+    /// The source family
+    pub sf: SmolStr,
+    pub sver: SmolStr,
+    pub vstart: SmolStr,
+    pub vend: SmolStr,
+    pub imeta: SmolStr,
+    pub rmeta: SmolStr,
+    pub slc: String,
+    pub scc: String,
+    /// The source restriction level, from 0 to 9
+    pub srl: u8,
+    pub tfr: SmolStr,
+    pub cfr: SmolStr,
+    pub cxty: SmolStr,
+    /// The term types in the source
+    pub ttyl: String,
+    /// The attribute names in the source
+    pub atnl: String,
+    pub lat: SmolStr,
+    pub cenc: SmolStr,
+    /// `Y` if this is the current version of the source
+    pub curver: SmolStr,
+    /// `Y` if the source is in this release
+    pub sabin: SmolStr,
+    pub ssn: String,
+    pub scit: String,
+}
+
+impl RrfRow for MrsabRow {
+    const FILE: &'static str = "MRSAB";
+    const COLUMNS: &'static [&'static str] = &[
+        "VCUI", "RCUI", "VSAB", "RSAB", "SON", "SF", "SVER", "VSTART", "VEND", "IMETA", "RMETA",
+        "SLC", "SCC", "SRL", "TFR", "CFR", "CXTY", "TTYL", "ATNL", "LAT", "CENC", "CURVER",
+        "SABIN", "SSN", "SCIT",
+    ];
+}
+
+/// A definition, from MRDEF
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrdefRow {
+    pub cui: SmolStr,
+    pub aui: SmolStr,
+    pub atui: SmolStr,
+    pub satui: SmolStr,
+    pub sab: SmolStr,
+    pub def: String,
+    pub suppress: SmolStr,
+    #[serde(default)]
+    pub cvf: SmolStr,
+}
+
+impl RrfRow for MrdefRow {
+    const FILE: &'static str = "MRDEF";
+    const COLUMNS: &'static [&'static str] =
+        &["CUI", "AUI", "ATUI", "SATUI", "SAB", "DEF", "SUPPRESS"];
+}
+
+/// An attribute, from MRSAT
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrsatRow {
+    pub cui: SmolStr,
+    pub lui: SmolStr,
+    pub sui: SmolStr,
+    /// The identifier that the attribute belongs to, such as an AUI, or a code
+    pub metaui: SmolStr,
+    /// The type of `metaui`, such as `AUI` or `CODE`
+    pub stype: SmolStr,
+    pub code: SmolStr,
+    pub atui: SmolStr,
+    pub satui: SmolStr,
+    /// The attribute name
+    pub atn: SmolStr,
+    pub sab: SmolStr,
+    /// The attribute value
+    pub atv: String,
+    pub suppress: SmolStr,
+    #[serde(default)]
+    pub cvf: SmolStr,
+}
+
+impl RrfRow for MrsatRow {
+    const FILE: &'static str = "MRSAT";
+    const COLUMNS: &'static [&'static str] = &[
+        "CUI", "LUI", "SUI", "METAUI", "STYPE", "CODE", "ATUI", "SATUI", "ATN", "SAB", "ATV",
+        "SUPPRESS",
+    ];
+}
+
+/// The rank of a term type, from MRRANK. Higher ranks are preferred when choosing a concept's
+/// name.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrrankRow {
+    pub rank: u32,
+    pub sab: SmolStr,
+    pub tty: SmolStr,
+    pub suppress: SmolStr,
+}
+
+impl RrfRow for MrrankRow {
+    const FILE: &'static str = "MRRANK";
+    const COLUMNS: &'static [&'static str] = &["RANK", "SAB", "TTY", "SUPPRESS"];
+}
+
+/// A position of an atom in its source's hierarchy, from MRHIER
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrhierRow {
+    pub cui: SmolStr,
+    pub aui: SmolStr,
+    /// The context number, counting the positions of the atom
+    pub cxn: u32,
+    /// The AUI of the atom's parent in this context
+    pub paui: SmolStr,
+    pub sab: SmolStr,
+    pub rela: SmolStr,
+    /// The AUIs from the root of the hierarchy down to the parent, separated by dots
+    pub ptr: String,
+    /// The source's own hierarchical code, such as a MeSH tree number
+    pub hcd: SmolStr,
+    #[serde(default)]
+    pub cvf: SmolStr,
+}
+
+impl RrfRow for MrhierRow {
+    const FILE: &'static str = "MRHIER";
+    const COLUMNS: &'static [&'static str] =
+        &["CUI", "AUI", "CXN", "PAUI", "SAB", "RELA", "PTR", "HCD"];
+}
+
+/// A change to a CUI from a previous release, from MRCUI
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrcuiRow {
+    /// The retired CUI
+    pub cui1: SmolStr,
+    /// The release in which the change happened
+    pub ver: SmolStr,
+    /// What happened to the CUI, such as `SY` for a merge or `DEL` for a deletion
+    pub rel: SmolStr,
+    pub rela: SmolStr,
+    pub mapreason: String,
+    /// The CUI that replaces `cui1`, or empty for a deletion
+    pub cui2: SmolStr,
+    /// `Y` if `cui2` is in the current release
+    pub mapin: SmolStr,
+}
+
+impl RrfRow for MrcuiRow {
+    const FILE: &'static str = "MRCUI";
+    const COLUMNS: &'static [&'static str] =
+        &["CUI1", "VER", "REL", "RELA", "MAPREASON", "CUI2", "MAPIN"];
+}
+
+/// A CUI merged into another since the previous release, from CHANGE/MERGEDCUI
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MergedCuiRow {
+    /// The CUI from the previous release
+    pub pcui: SmolStr,
+    /// The CUI that it was merged into
+    pub cui: SmolStr,
+}
+
+impl RrfRow for MergedCuiRow {
+    const FILE: &'static str = "MERGEDCUI";
+    const COLUMNS: &'static [&'static str] = &["PCUI", "CUI"];
+}
+
+/// A CUI deleted since the previous release, from CHANGE/DELETEDCUI
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct DeletedCuiRow {
+    /// The CUI from the previous release
+    pub pcui: SmolStr,
+    pub lat: SmolStr,
+    /// The preferred name of the CUI in the previous release
+    pub pstr: String,
+}
+
+impl RrfRow for DeletedCuiRow {
+    const FILE: &'static str = "DELETEDCUI";
+    const COLUMNS: &'static [&'static str] = &["PCUI", "LAT", "PSTR"];
+}
+
+/// An entry of a map set, from MRMAP
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrmapRow {
+    /// The CUI of the map set
+    pub mapsetcui: SmolStr,
+    pub mapsetsab: SmolStr,
+    /// The map group, or `None` if the map set doesn't use groups
+    pub mapsubsetid: Option<u32>,
+    /// The order in which to try the entries of a group, or `None` if the map set doesn't rank
+    /// its entries
+    pub maprank: Option<u32>,
+    pub mapid: SmolStr,
+    pub mapsid: SmolStr,
+    pub fromid: SmolStr,
+    pub fromsid: SmolStr,
+    /// The code being mapped from
+    pub fromexpr: SmolStr,
+    pub fromtype: SmolStr,
+    pub fromrule: String,
+    pub fromres: String,
+    pub rel: SmolStr,
+    pub rela: SmolStr,
+    pub toid: SmolStr,
+    pub tosid: SmolStr,
+    /// The code being mapped to
+    pub toexpr: SmolStr,
+    pub totype: SmolStr,
+    pub torule: String,
+    pub tores: String,
+    /// The rule for when to use the entry, such as `OTHERWISE TRUE`
+    pub maprule: String,
+    /// Advice on using the entry
+    pub mapres: String,
+    pub maptype: SmolStr,
+    pub mapatn: SmolStr,
+    pub mapatv: String,
+    #[serde(default)]
+    pub cvf: SmolStr,
+}
+
+impl RrfRow for MrmapRow {
+    const FILE: &'static str = "MRMAP";
+    const COLUMNS: &'static [&'static str] = &[
+        "MAPSETCUI",
+        "MAPSETSAB",
+        "MAPSUBSETID",
+        "MAPRANK",
+        "MAPID",
+        "MAPSID",
+        "FROMID",
+        "FROMSID",
+        "FROMEXPR",
+        "FROMTYPE",
+        "FROMRULE",
+        "FROMRES",
+        "REL",
+        "RELA",
+        "TOID",
+        "TOSID",
+        "TOEXPR",
+        "TOTYPE",
+        "TORULE",
+        "TORES",
+        "MAPRULE",
+        "MAPRES",
+        "MAPTYPE",
+        "MAPATN",
+        "MAPATV",
+    ];
+}
+
+/// An entry of a simple map set, from MRSMAP
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct MrsmapRow {
+    /// The CUI of the map set
+    pub mapsetcui: SmolStr,
+    pub mapsetsab: SmolStr,
+    pub mapid: SmolStr,
+    pub mapsid: SmolStr,
+    /// The code being mapped from
+    pub fromexpr: SmolStr,
+    pub fromtype: SmolStr,
+    pub rel: SmolStr,
+    pub rela: SmolStr,
+    /// The code being mapped to
+    pub toexpr: SmolStr,
+    pub totype: SmolStr,
+    #[serde(default)]
+    pub cvf: SmolStr,
+}
+
+impl RrfRow for MrsmapRow {
+    const FILE: &'static str = "MRSMAP";
+    const COLUMNS: &'static [&'static str] = &[
+        "MAPSETCUI",
+        "MAPSETSAB",
+        "MAPID",
+        "MAPSID",
+        "FROMEXPR",
+        "FROMTYPE",
+        "REL",
+        "RELA",
+        "TOEXPR",
+        "TOTYPE",
+    ];
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    #[test]
+    fn typed_rows() {
+        let data = TestData::new();
+        let files = Files::new(data.dir.path()).unwrap();
+
+        let atoms = files
+            .mrconso()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(atoms[0].cui, "C0012634");
+        assert_eq!(atoms[0].sab, "SNOMEDCT_US");
+        assert_eq!(atoms[0].srl, 9);
+
+        // The second row carries over its CUI, METAUI, STYPE and SAB from the first.
+        let attributes = files
+            .mrsat()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(attributes[1].cui, "C0025598");
+        assert_eq!(attributes[1].sab, "RXNORM");
+        assert_eq!(attributes[1].atv, "00093-1049-01");
+
+        let sources = files
+            .mrsab()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(sources[0].rsab, "SNOMEDCT_US");
+        assert_eq!(sources[0].srl, 9);
+
+        // The last rows carry over their CUI, AUI and the start of their PTR.
+        let contexts = files
+            .mrhier()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let last = contexts.last().unwrap();
+        assert_eq!((last.aui.as_str(), last.cxn), ("A0020", 3));
+        assert_eq!(last.ptr, "A9999.A0001");
+        assert_eq!(contexts[contexts.len() - 2].ptr, "A9999.A0001.A0030");
+
+        let changes = files
+            .mrcui()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(changes[1].cui1, "C0000002");
+        assert_eq!(changes[1].cui2, "C0011860");
+        assert_eq!(changes[1].ver, "2019AB");
+
+        let merged = files
+            .mergedcui()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(merged[0].pcui, "C0000005");
+        assert_eq!(merged[0].cui, "C0012634");
+
+        let deleted = files
+            .deletedcui()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(deleted[0].pstr, "Some old concept");
+
+        let map = files
+            .mrmap()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(map[0].mapsubsetid, Some(1));
+        assert_eq!(map[0].maprank, Some(2));
+        assert_eq!(map[0].toexpr, "I10");
+        assert_eq!(map[0].maprule, "OTHERWISE TRUE");
+
+        let simple_map = files
+            .mrsmap()
+            .unwrap()
+            .rows()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(simple_map[1].mapsetcui, "C9999998");
+        assert_eq!(simple_map[1].fromexpr, "D003924");
+    }
+
+    #[test]
+    fn map_numbers() {
+        let headers = csv::StringRecord::from(MrmapRow::COLUMNS.to_vec());
+        let row = |group: &str, rank: &str| {
+            let mut fields = vec![""; MrmapRow::COLUMNS.len()];
+            fields[2] = group;
+            fields[3] = rank;
+            csv::StringRecord::from(fields).deserialize::<MrmapRow>(Some(&headers))
+        };
+
+        let map = row("", "").unwrap();
+        assert_eq!((map.mapsubsetid, map.maprank), (None, None));
+        assert_eq!(row("2", "").unwrap().mapsubsetid, Some(2));
+        assert!(row("first", "1").is_err());
+        assert!(row("1", "-1").is_err());
+    }
+
+    #[derive(Deserialize)]
+    struct MissingColumnRow {}
+
+    impl RrfRow for MissingColumnRow {
+        const FILE: &'static str = "MRSTY";
+        const COLUMNS: &'static [&'static str] = &["CUI", "NOPE"];
+    }
+
+    #[test]
+    fn missing_column() {
+        let data = TestData::new();
+        let files = Files::new(data.dir.path()).unwrap();
+        let err = files.rows::<MissingColumnRow>().err().unwrap();
+        assert_eq!(err.to_string(), "MRSTY is missing the columns NOPE");
+    }
+}
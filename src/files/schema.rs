@@ -27,26 +27,16 @@ pub struct UmlsSource {
 
 impl Files {
     pub fn read_sources(&self) -> Result<Vec<UmlsSource>> {
-        let mut mrsab = self.get_file_stream("MRSAB")?;
+        let mut mrsab = self.mrsab()?;
         let mut sources = Vec::new();
 
-        let son_idx = mrsab.columns.iter().position(|c| c == "SON").unwrap();
-        let lat_idx = mrsab.columns.iter().position(|c| c == "LAT").unwrap();
-        let fam_idx = mrsab.columns.iter().position(|c| c == "SF").unwrap();
-        let rsab_idx = mrsab.columns.iter().position(|c| c == "RSAB").unwrap();
-
-        for line in mrsab.records() {
+        for line in mrsab.rows() {
             let line = line?;
-            let son = line.get(son_idx).unwrap();
-            let lat = line.get(lat_idx).unwrap();
-            let fam = line.get(fam_idx).unwrap();
-            let rsab = line.get(rsab_idx).unwrap();
-
             sources.push(UmlsSource {
-                name: son.to_string(),
-                family: fam.to_string(),
-                language: lat.to_string(),
-                abbreviation: rsab.to_string(),
+                name: line.son,
+                family: line.sf.to_string(),
+                language: line.lat.to_string(),
+                abbreviation: line.rsab.to_string(),
             })
         }
 
@@ -0,0 +1,446 @@
+use std::{io::Write, path::Path};
+
+use ahash::{HashSet, HashSetExt};
+use eyre::{eyre, Result};
+use smol_str::SmolStr;
+
+use super::{file_basename, file_iterator::RrfRecord, Files};
+use crate::index::{build::read_semantic_types, parse_tui, semantic_type_matches};
+
+/// Files that record the history of identifiers from earlier releases. These refer to retired
+/// CUIs and AUIs, so they are copied without filtering.
+const HISTORY_FILES: &[&str] = &["MRCUI", "MRAUI"];
+
+/// Options for [Files::write_subset]. Empty lists keep everything.
+#[derive(Debug, Clone, Default)]
+pub struct SubsetOptions {
+    /// The sources (SAB field) to keep. The SRC source, which holds the roots of the source
+    /// hierarchies, is always kept.
+    pub sources: Vec<SmolStr>,
+    /// Remove the sources with a restriction level (SRL in MRSAB) above this.
+    pub max_restriction_level: Option<u8>,
+    /// The languages (LAT field) to keep.
+    pub languages: Vec<SmolStr>,
+    /// Remove obsolete and suppressible content, keeping only rows with a SUPPRESS value of `N`.
+    pub remove_suppressible: bool,
+    /// Only keep concepts with these semantic types. This takes semantic tree number prefixes or
+    /// semantic groups, such as `DISO`.
+    pub semantic_types: Vec<SmolStr>,
+}
+
+/// A file written by [Files::write_subset].
+#[derive(Debug, Clone)]
+pub struct SubsetFile {
+    pub filename: String,
+    pub rows: usize,
+    pub bytes: usize,
+}
+
+/// The positions of the columns that decide whether a row is kept.
+#[derive(Default)]
+struct SubsetColumns {
+    cuis: Vec<usize>,
+    auis: Vec<usize>,
+    sources: Vec<usize>,
+    language: Option<usize>,
+    suppress: Option<usize>,
+    /// The METAUI and STYPE columns of MRSAT. The METAUI is only an AUI for some STYPEs.
+    metaui: Option<(usize, usize)>,
+}
+
+impl SubsetColumns {
+    fn new(columns: &[String]) -> SubsetColumns {
+        let position = |name: &str| columns.iter().position(|c| c == name);
+        let positions = |names: &[&str]| names.iter().filter_map(|n| position(n)).collect();
+
+        SubsetColumns {
+            cuis: positions(&["CUI", "CUI1", "CUI2"]),
+            // Only the atom's own AUI in MRHIER, since the parents can be SRC atoms.
+            auis: positions(&["AUI", "AUI1", "AUI2"]),
+            sources: positions(&["SAB", "RSAB", "MAPSETSAB"]),
+            language: position("LAT"),
+            suppress: position("SUPPRESS"),
+            metaui: position("METAUI").zip(position("STYPE")),
+        }
+    }
+}
+
+/// The content that is kept in the subset.
+struct SubsetFilter {
+    /// The sources to keep, or `None` to keep them all
+    sources: Option<HashSet<SmolStr>>,
+    languages: Vec<SmolStr>,
+    remove_suppressible: bool,
+    cuis: HashSet<u32>,
+    auis: HashSet<u32>,
+}
+
+impl SubsetFilter {
+    fn keep_source(&self, source: &str) -> bool {
+        self.sources.as_ref().is_none_or(|s| s.contains(source))
+    }
+
+    fn keep_language(&self, language: &str) -> bool {
+        self.languages.is_empty() || self.languages.iter().any(|l| l == language)
+    }
+
+    fn keep_suppress(&self, suppress: &str) -> bool {
+        !self.remove_suppressible || suppress.is_empty() || suppress == "N"
+    }
+
+    /// Check if a row of any file is kept. Empty values, such as the AUIs of relationships
+    /// between concepts, are always kept.
+    fn keep(&self, columns: &SubsetColumns, record: &RrfRecord) -> bool {
+        let value = |i: usize| record.get(i).unwrap_or_default();
+        let kept_ui = |set: &HashSet<u32>, ui: &str| {
+            ui.is_empty() || ui_number(ui).is_some_and(|n| set.contains(&n))
+        };
+
+        columns.cuis.iter().all(|&i| kept_ui(&self.cuis, value(i)))
+            && columns.auis.iter().all(|&i| kept_ui(&self.auis, value(i)))
+            && columns.sources.iter().all(|&i| {
+                let source = value(i);
+                source.is_empty() || self.keep_source(source)
+            })
+            && columns.language.is_none_or(|i| {
+                let language = value(i);
+                language.is_empty() || self.keep_language(language)
+            })
+            && columns
+                .suppress
+                .is_none_or(|i| self.keep_suppress(value(i)))
+            && columns.metaui.is_none_or(|(metaui, stype)| {
+                value(stype) != "AUI" || kept_ui(&self.auis, value(metaui))
+            })
+    }
+}
+
+/// Parse the number from a UMLS identifier such as a CUI or AUI.
+fn ui_number(ui: &str) -> Option<u32> {
+    ui.get(1..)?.parse().ok()
+}
+
+impl Files {
+    /// Write a subset of the release to `output_dir`, in the same layout as the release, in the
+    /// manner of MetamorphoSys. The atoms in MRCONSO are filtered first, and the other files only
+    /// keep rows that refer to the remaining atoms, concepts, and sources. The history files are
+    /// copied unchanged, and MRFILES is rewritten with the new row and byte counts.
+    ///
+    /// The files are written uncompressed. MRCOLS and MRSAB are filtered but their statistics are
+    /// not recalculated.
+    pub fn write_subset(
+        &self,
+        output_dir: &Path,
+        options: &SubsetOptions,
+    ) -> Result<Vec<SubsetFile>> {
+        let filter = self.subset_filter(options)?;
+        let meta_dir = output_dir.join("META");
+        std::fs::create_dir_all(&meta_dir)?;
+
+        let mut mrfiles = self.get_file_stream("MRFILES")?;
+        let mut file_rows = Vec::new();
+        let mut own_row = None;
+        let mut written = Vec::new();
+        for line in mrfiles.records() {
+            let line = line?;
+            let row = (0..6)
+                .map(|i| line.get(i).unwrap_or_default().to_string())
+                .collect::<Vec<_>>();
+
+            let filename = row[0].as_str();
+            let basename = file_basename(filename);
+            if basename == "MRFILES" {
+                own_row = Some(row);
+                continue;
+            }
+
+            if !self.has_file(basename) {
+                continue;
+            }
+
+            let history = filename.starts_with("CHANGE/") || HISTORY_FILES.contains(&basename);
+            let file = self.write_subset_file(
+                basename,
+                &meta_dir,
+                filename,
+                (!history).then_some(&filter),
+            )?;
+
+            file_rows.push(format!(
+                "{}|{}|{}|{}|{}|{}|\n",
+                row[0], row[1], row[2], row[3], file.rows, file.bytes
+            ));
+            written.push(file);
+        }
+
+        let mut contents = file_rows.concat();
+        if let Some(row) = own_row {
+            // The MRFILES row counts the bytes of MRFILES itself, including its own byte count.
+            let rows = file_rows.len() + 1;
+            let mut bytes = 0;
+            loop {
+                let own_line = format!(
+                    "{}|{}|{}|{}|{rows}|{bytes}|\n",
+                    row[0], row[1], row[2], row[3]
+                );
+                let total = contents.len() + own_line.len();
+                if total == bytes {
+                    contents.push_str(&own_line);
+                    break;
+                }
+                bytes = total;
+            }
+        }
+
+        std::fs::write(meta_dir.join("MRFILES.RRF"), &contents)?;
+        written.push(SubsetFile {
+            filename: "MRFILES.RRF".to_string(),
+            rows: contents.lines().count(),
+            bytes: contents.len(),
+        });
+
+        // The semantic network doesn't depend on the filters.
+        let net_dir = self.base_dir.join("NET");
+        if net_dir.is_dir() {
+            let output_net = output_dir.join("NET");
+            std::fs::create_dir_all(&output_net)?;
+            for entry in std::fs::read_dir(net_dir)? {
+                let entry = entry?;
+                if entry.metadata()?.is_file() {
+                    std::fs::copy(entry.path(), output_net.join(entry.file_name()))?;
+                }
+            }
+        }
+
+        Ok(written)
+    }
+
+    fn subset_filter(&self, options: &SubsetOptions) -> Result<SubsetFilter> {
+        let sources = self.subset_sources(options)?;
+        let semantic_type_cuis = self.subset_semantic_type_cuis(options)?;
+
+        let mut filter = SubsetFilter {
+            sources,
+            languages: options.languages.clone(),
+            remove_suppressible: options.remove_suppressible,
+            cuis: HashSet::new(),
+            auis: HashSet::new(),
+        };
+
+        let mut mrconso = self.mrconso()?;
+        for line in mrconso.rows() {
+            let line = line?;
+            let (Some(cui), Some(aui)) = (ui_number(&line.cui), ui_number(&line.aui)) else {
+                continue;
+            };
+
+            let keep = filter.keep_source(&line.sab)
+                && filter.keep_language(&line.lat)
+                && filter.keep_suppress(&line.suppress)
+                && semantic_type_cuis
+                    .as_ref()
+                    .is_none_or(|cuis| cuis.contains(&cui));
+
+            if keep {
+                filter.cuis.insert(cui);
+                filter.auis.insert(aui);
+            }
+        }
+
+        Ok(filter)
+    }
+
+    /// The sources to keep, or `None` if every source is kept.
+    fn subset_sources(&self, options: &SubsetOptions) -> Result<Option<HashSet<SmolStr>>> {
+        let Some(max_level) = options.max_restriction_level else {
+            if options.sources.is_empty() {
+                return Ok(None);
+            }
+
+            let mut sources = options.sources.iter().cloned().collect::<HashSet<_>>();
+            sources.insert("SRC".into());
+            return Ok(Some(sources));
+        };
+
+        if !self.has_file("MRSAB") {
+            return Err(eyre!("MRSAB is required to filter by restriction level"));
+        }
+
+        let mut mrsab = self.mrsab()?;
+        let mut sources = HashSet::new();
+        sources.insert(SmolStr::from("SRC"));
+        for line in mrsab.rows() {
+            let line = line?;
+            let listed = options.sources.is_empty() || options.sources.contains(&line.rsab);
+            if listed && line.srl <= max_level {
+                sources.insert(line.rsab);
+            }
+        }
+
+        Ok(Some(sources))
+    }
+
+    /// The CUIs that match the semantic type filter, or `None` if there is no filter.
+    fn subset_semantic_type_cuis(&self, options: &SubsetOptions) -> Result<Option<HashSet<u32>>> {
+        if options.semantic_types.is_empty() {
+            return Ok(None);
+        }
+
+        let type_defs = read_semantic_types(self)?;
+        let mut mrsty = self.mrsty()?;
+        let mut cuis = HashSet::new();
+        for line in mrsty.rows() {
+            let line = line?;
+            let Ok(tui) = parse_tui(&line.tui) else {
+                continue;
+            };
+
+            let matches = type_defs.get(&tui).is_some_and(|sty| {
+                options
+                    .semantic_types
+                    .iter()
+                    .any(|filter| semantic_type_matches(sty, filter))
+            });
+
+            if matches {
+                if let Some(cui) = ui_number(&line.cui) {
+                    cuis.insert(cui);
+                }
+            }
+        }
+
+        Ok(Some(cuis))
+    }
+
+    /// Write the rows of a file that pass the filter, or all of them if there is no filter. The
+    /// rows are written in full, without the carried-over values of the compressed format.
+    fn write_subset_file(
+        &self,
+        basename: &str,
+        meta_dir: &Path,
+        filename: &str,
+        filter: Option<&SubsetFilter>,
+    ) -> Result<SubsetFile> {
+        let path = meta_dir.join(filename);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut input = self.get_file_stream(basename)?;
+        let columns = SubsetColumns::new(&input.columns);
+        let num_columns = input.columns.len();
+
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let mut rows = 0;
+        let mut bytes = 0;
+        let mut line = String::new();
+        for record in input.records() {
+            let record = record?;
+            if filter.is_some_and(|f| !f.keep(&columns, &record)) {
+                continue;
+            }
+
+            line.clear();
+            for i in 0..num_columns {
+                line.push_str(record.get(i).unwrap_or_default());
+                line.push('|');
+            }
+            line.push('\n');
+
+            writer.write_all(line.as_bytes())?;
+            rows += 1;
+            bytes += line.len();
+        }
+        writer.flush()?;
+
+        Ok(SubsetFile {
+            filename: filename.to_string(),
+            rows,
+            bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    fn read_rows(dir: &Path, filename: &str) -> Vec<String> {
+        std::fs::read_to_string(dir.join("META").join(filename))
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn write_subset() {
+        let data = TestData::new();
+        let files = Files::new(data.dir.path()).unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let options = SubsetOptions {
+            max_restriction_level: Some(4),
+            remove_suppressible: true,
+            ..Default::default()
+        };
+        let written = files.write_subset(output.path(), &options).unwrap();
+
+        // SNOMED CT has a restriction level of 9.
+        let mrconso = read_rows(output.path(), "MRCONSO.RRF");
+        assert_eq!(mrconso.len(), 7);
+        assert!(mrconso.iter().all(|r| !r.contains("|SNOMEDCT_US|")));
+
+        // Every relationship includes a SNOMED CT atom.
+        assert!(read_rows(output.path(), "MRREL.RRF").is_empty());
+        let mrhier = read_rows(output.path(), "MRHIER.RRF");
+        assert_eq!(
+            mrhier,
+            [
+                "C0012634|A0002|1|A8888|MSH||A8888|C||",
+                "C0011849|A0013|1|A7777|MSH||A8888.A0002.A7777|C18.452.394.750||"
+            ]
+        );
+
+        // The obsolete definition is removed.
+        assert_eq!(read_rows(output.path(), "MRDEF.RRF").len(), 1);
+
+        // The carried-over values are written out in full.
+        let mrsat = read_rows(output.path(), "MRSAT.RRF");
+        assert_eq!(mrsat.len(), 2);
+        assert!(mrsat[1].starts_with("C0025598|||A0040|AUI|6809|AT101|"));
+
+        // History files are copied unchanged.
+        assert_eq!(read_rows(output.path(), "MRCUI.RRF").len(), 5);
+        assert_eq!(read_rows(output.path(), "CHANGE/MERGEDCUI.RRF").len(), 2);
+
+        let mrfiles = read_rows(output.path(), "MRFILES.RRF");
+        assert_eq!(mrfiles.len(), written.len());
+        for row in mrfiles {
+            let fields = row.split('|').collect::<Vec<_>>();
+            let contents =
+                std::fs::read_to_string(output.path().join("META").join(fields[0])).unwrap();
+            assert_eq!(fields[4], contents.lines().count().to_string(), "{row}");
+            assert_eq!(fields[5], contents.len().to_string(), "{row}");
+        }
+
+        assert!(output.path().join("NET").join("SRDEF").exists());
+    }
+
+    #[test]
+    fn semantic_type_subset() {
+        let data = TestData::new();
+        let files = Files::new(data.dir.path()).unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let options = SubsetOptions {
+            semantic_types: vec!["CHEM".into()],
+            ..Default::default()
+        };
+        files.write_subset(output.path(), &options).unwrap();
+
+        let mrconso = read_rows(output.path(), "MRCONSO.RRF");
+        assert_eq!(mrconso.len(), 1);
+        assert!(mrconso[0].starts_with("C0025598|"));
+        assert_eq!(read_rows(output.path(), "MRSTY.RRF").len(), 1);
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use eyre::{eyre, Result};
+use rayon::prelude::*;
+
+use super::{file_basename, file_iterator::create_raw_stream, Files};
+
+/// A problem with a release, found by [Files::verify].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum VerifyProblem {
+    #[error("{filename} is missing")]
+    MissingFile { filename: String },
+    #[error("{filename} could not be read: {message}")]
+    Unreadable { filename: String, message: String },
+    #[error("{filename} has {actual} rows, but MRFILES lists {expected}")]
+    RowCount {
+        filename: String,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("{filename} has {actual} bytes, but MRFILES lists {expected}")]
+    ByteCount {
+        filename: String,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("{} has MD5 checksum {actual}, but {} lists {expected}", path.display(), checksum_file.display())]
+    Checksum {
+        path: PathBuf,
+        checksum_file: PathBuf,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Files {
+    /// Check the release for truncated or missing files. Every file listed in MRFILES is read in
+    /// full, after decompression, and its row and byte counts are compared with MRFILES. Any
+    /// `.md5` checksum files, such as those for the `.nlm` archives, are checked against the
+    /// files next to them.
+    ///
+    /// Returns every problem found, or an empty list if the release is intact.
+    pub fn verify(&self) -> Result<Vec<VerifyProblem>> {
+        let mut mrfiles = self.get_file_stream("MRFILES")?;
+        let mut listed = Vec::new();
+        for line in mrfiles.records() {
+            let line = line?;
+            let filename = line.get(0).unwrap_or_default().to_string();
+            let count = |i: usize, name: &str| {
+                let value = line.get(i).unwrap_or_default();
+                value
+                    .parse::<u64>()
+                    .map_err(|_| eyre!("Invalid {name} {value} in MRFILES for {filename}"))
+            };
+
+            listed.push((filename.clone(), count(4, "RWS")?, count(5, "BTS")?));
+        }
+
+        let mut problems = listed
+            .par_iter()
+            .flat_map_iter(|(filename, rows, bytes)| self.verify_file(filename, *rows, *bytes))
+            .collect::<Vec<_>>();
+
+        let base = self.base_dir.display();
+        let mut checksum_files = Vec::new();
+        for pattern in [
+            format!("{base}/*.md5"),
+            format!("{base}/*/*.md5"),
+            format!("{base}/*/CHANGE/*.md5"),
+        ] {
+            for path in glob::glob(&pattern)? {
+                checksum_files.push(path?);
+            }
+        }
+
+        let checksum_problems = checksum_files
+            .par_iter()
+            .map(|path| verify_checksum(path))
+            .collect::<Result<Vec<_>>>()?;
+        problems.extend(checksum_problems.into_iter().flatten());
+
+        Ok(problems)
+    }
+
+    fn verify_file(
+        &self,
+        filename: &str,
+        expected_rows: u64,
+        expected_bytes: u64,
+    ) -> Vec<VerifyProblem> {
+        let Some(file) = self.files.get(file_basename(filename)) else {
+            return vec![VerifyProblem::MissingFile {
+                filename: filename.to_string(),
+            }];
+        };
+
+        let (rows, bytes) = match create_raw_stream(&file.locations).and_then(count_lines) {
+            Ok(counts) => counts,
+            Err(e) => {
+                return vec![VerifyProblem::Unreadable {
+                    filename: filename.to_string(),
+                    message: e.to_string(),
+                }]
+            }
+        };
+
+        let mut problems = Vec::new();
+        if rows != expected_rows {
+            problems.push(VerifyProblem::RowCount {
+                filename: filename.to_string(),
+                expected: expected_rows,
+                actual: rows,
+            });
+        }
+
+        if bytes != expected_bytes {
+            problems.push(VerifyProblem::ByteCount {
+                filename: filename.to_string(),
+                expected: expected_bytes,
+                actual: bytes,
+            });
+        }
+
+        problems
+    }
+}
+
+/// Count the lines and bytes in a stream.
+fn count_lines(mut reader: impl Read) -> Result<(u64, u64)> {
+    let mut buffer = vec![0; 1 << 16];
+    let mut rows = 0;
+    let mut bytes = 0;
+    loop {
+        let len = reader.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+
+        bytes += len as u64;
+        rows += buffer[..len].iter().filter(|&&b| b == b'\n').count() as u64;
+    }
+
+    Ok((rows, bytes))
+}
+
+/// Check a file against the checksum in `checksum_file`, which is the file's name with `.md5`
+/// added. Both the `md5sum` format and the BSD `MD5 (file) = checksum` format are accepted.
+fn verify_checksum(checksum_file: &Path) -> Result<Option<VerifyProblem>> {
+    let path = checksum_file.with_extension("");
+    if !path.is_file() {
+        return Ok(Some(VerifyProblem::MissingFile {
+            filename: path.display().to_string(),
+        }));
+    }
+
+    let contents = std::fs::read_to_string(checksum_file)?;
+    let expected = match contents.split_once(" = ") {
+        Some((_, checksum)) => checksum.trim(),
+        None => contents.split_whitespace().next().unwrap_or_default(),
+    }
+    .to_ascii_lowercase();
+
+    let mut context = md5::Context::new();
+    std::io::copy(&mut std::fs::File::open(&path)?, &mut context)?;
+    let actual = format!("{:x}", context.compute());
+
+    if actual == expected {
+        return Ok(None);
+    }
+
+    Ok(Some(VerifyProblem::Checksum {
+        path,
+        checksum_file: checksum_file.to_path_buf(),
+        expected,
+        actual,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    #[test]
+    fn verify() {
+        let data = TestData::new();
+        let files = Files::new(data.dir.path()).unwrap();
+        assert_eq!(files.verify().unwrap(), []);
+
+        // Truncate MRSTY, and add a checksum that doesn't match.
+        let meta = data.dir.path().join("META");
+        let mrsty = meta.join("MRSTY.RRF");
+        std::fs::write(
+            &mrsty,
+            "C0012634|T047|B2.2.1.2.1|Disease or Syndrome|AT01||\n",
+        )
+        .unwrap();
+        std::fs::remove_file(meta.join("MRSTY.RRF.gz")).unwrap();
+        std::fs::write(
+            meta.join("MRREL.RRF.gz.md5"),
+            "0123456789abcdef0123456789abcdef  MRREL.RRF.gz\n",
+        )
+        .unwrap();
+        std::fs::write(meta.join("MRAUI.RRF.gz.md5"), "0123456789abcdef").unwrap();
+
+        let files = Files::new(data.dir.path()).unwrap();
+        let problems = files.verify().unwrap();
+        assert_eq!(problems.len(), 4, "{problems:?}");
+        assert_eq!(
+            problems[0],
+            VerifyProblem::RowCount {
+                filename: "MRSTY.RRF".to_string(),
+                expected: 5,
+                actual: 1,
+            }
+        );
+        assert!(matches!(problems[1], VerifyProblem::ByteCount { .. }));
+        assert!(matches!(
+            &problems[3],
+            VerifyProblem::Checksum { expected, .. } if expected.starts_with("0123")
+        ));
+        assert!(matches!(
+            &problems[2],
+            VerifyProblem::MissingFile { filename } if filename.ends_with("MRAUI.RRF.gz")
+        ));
+    }
+}
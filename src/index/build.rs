@@ -1,20 +1,30 @@
 use std::path::Path;
-use std::{collections::BTreeMap, io::Write};
+use std::{
+    collections::{hash_map::Entry, BTreeMap},
+    io::Write,
+    sync::RwLock,
+};
 
-use ahash::{HashMap, HashMapExt};
+use ahash::{HashMap, HashMapExt, HashSet, HashSetExt};
 use eyre::Result;
-use flate2::write::GzEncoder;
 use fst::MapBuilder;
+use rayon::prelude::*;
 use smallvec::SmallVec;
 use smol_str::SmolStr;
 
-use crate::files::{create_csv_reader, Files};
+use crate::files::{create_csv_reader, Files, MrconsoRow, MrhierRow, MrrelRow, MrsatRow};
 
+use super::store::{self, ListBuilder, Section, StoreWriter, StringPool};
+use super::{
+    attribute_key, code_key, ATTRIBUTES_FST_NAME, CODES_FST_NAME, CONCEPTS_STORE_NAME,
+    CUI_HISTORY_FST_NAME, MAPPINGS_FST_NAME, MULTIPLE_CONCEPTS_FLAG, STRINGS_FST_NAME,
+};
 use super::{
-    parse_tui, Concept, ConceptCode, SearchIndexMeta, SemanticType, METADATA_NAME,
+    parse_tui, semantic_network::semantic_type_matches, Atom, Attribute, Concept, ConceptCode,
+    CuiChangeKind, Definition, RelationKinds, SearchIndexMeta, SemanticNetwork,
+    SemanticRelationType, SemanticType, Suppress, METADATA_NAME, SEMANTIC_NETWORK_NAME,
     SEMANTIC_TYPES_LST_NAME,
 };
-use super::{CONCEPTS_LST_NAME, STRINGS_FST_NAME};
 
 pub struct IndexBuilderOptions<'a> {
     pub output_dir: &'a Path,
@@ -26,8 +36,18 @@ pub struct IndexBuilderOptions<'a> {
     pub sources: Vec<SmolStr>,
     /// The semantic types to include in the index. If empty, all semantic types are included.
     /// This takes semantic tree numbers, and a number will be used as a prefix, applying to all
-    /// of its children as well.
+    /// of its children as well. Semantic group abbreviations such as `DISO` are also accepted.
     pub semantic_types: Vec<SmolStr>,
+    /// Precompute the ancestors of every concept over these kinds of relationship, so that
+    /// [super::Index::is_a] can answer without traversing the graph. This can make the index
+    /// much larger.
+    pub closure: Option<RelationKinds>,
+    /// Only keep definitions from these sources, ordered by their position in this list. If
+    /// empty, definitions from all included sources are kept.
+    pub definition_sources: Vec<SmolStr>,
+    /// The names (ATN values) of the MRSAT attributes to include, such as `NDC`. MRSAT is large,
+    /// so no attributes are included unless they are listed here.
+    pub attributes: Vec<SmolStr>,
 }
 
 pub fn build_index(options: IndexBuilderOptions) -> Result<()> {
@@ -38,6 +58,9 @@ pub fn build_index(options: IndexBuilderOptions) -> Result<()> {
         languages,
         sources,
         semantic_types,
+        closure,
+        definition_sources,
+        attributes,
     } = options;
 
     let ranks = read_ranks(files)?;
@@ -45,225 +68,1146 @@ pub fn build_index(options: IndexBuilderOptions) -> Result<()> {
     let concept_semantic_types =
         read_semantic_types_map(files, &semantic_type_defs, &semantic_types)?;
 
-    let mut mrconso = files.get_file_stream("MRCONSO")?;
+    // The history and mappings don't depend on the concepts, so read them while the concepts
+    // are built.
+    let (concept_data, history_and_mappings) = rayon::join(
+        || {
+            read_concepts(
+                files,
+                &ranks,
+                &concept_semantic_types,
+                &languages,
+                &sources,
+                case_insensitive,
+            )
+        },
+        || -> Result<_> { Ok((read_cui_history(files)?, read_mappings(files, &sources)?)) },
+    );
+    let ConceptData {
+        string_to_number,
+        concepts,
+        mut atoms,
+    } = concept_data?;
+    let (cui_history, map_rows) = history_and_mappings?;
+
+    // Strings that map to a single concept store the concept ID directly in the FST. Otherwise
+    // the FST value points to a list of concept IDs in the postings section.
+    let mut postings: Vec<u32> = Vec::new();
+    write_fst(
+        &output_dir.join(STRINGS_FST_NAME),
+        string_to_number,
+        &mut postings,
+    )?;
+
+    let output_types_path = output_dir.join(SEMANTIC_TYPES_LST_NAME);
+    let mut output_types_writer =
+        std::io::BufWriter::new(std::fs::File::create(&output_types_path)?);
+    for sem in semantic_type_defs.values() {
+        serde_json::to_writer(&mut output_types_writer, &sem)?;
+        writeln!(output_types_writer)?;
+    }
+
+    output_types_writer.flush()?;
+
+    let semantic_network = read_semantic_network(files, &semantic_type_defs)?;
+    let network_writer = std::io::BufWriter::new(std::fs::File::create(
+        output_dir.join(SEMANTIC_NETWORK_NAME),
+    )?);
+    serde_json::to_writer(network_writer, &semantic_network)?;
+
+    let mut sorted_names = concepts
+        .into_iter()
+        .map(|(_, (id, _, concept))| (id, concept))
+        .collect::<Vec<_>>();
+    sorted_names.sort_unstable_by_key(|(id, _)| *id);
+
+    let cui_ids = sorted_names
+        .iter()
+        .map(|(id, c)| (c.cui.clone(), *id))
+        .collect::<HashMap<_, _>>();
+
+    // MRCONSO is sorted by CUI so this is usually sorted already, but make sure.
+    atoms.sort_by_key(|a| a.concept);
+
+    // The relationships add edges to the concepts, while the other phases only need to look up
+    // concepts by CUI, so they can all run at once.
+    let mut strings = StringPool::new();
+    let (relations, (concept_attributes, (definitions, hierarchies))) = rayon::join(
+        || build_relationships(files, &cui_ids, &mut sorted_names, &mut strings),
+        || {
+            rayon::join(
+                || read_attributes(files, &cui_ids, &sources, &attributes),
+                || {
+                    rayon::join(
+                        || read_definitions(files, &cui_ids, &sources, &definition_sources),
+                        || build_hierarchies(files, &atoms),
+                    )
+                },
+            )
+        },
+    );
+    let relations = relations?;
+    let concept_attributes = concept_attributes?;
+    let definitions = definitions?;
+    let hierarchies = hierarchies?;
+
+    let mut code_to_number: BTreeMap<String, SmallVec<[u32; 2]>> = BTreeMap::new();
+    for (id, concept) in &sorted_names {
+        for code in &concept.codes {
+            code_to_number
+                .entry(code_key(&code.source, &code.code))
+                .or_default()
+                .push(*id);
+        }
+    }
+    let mappings = file_mappings(map_rows, |key| code_to_number.contains_key(key));
+    write_fst(
+        &output_dir.join(CODES_FST_NAME),
+        code_to_number,
+        &mut postings,
+    )?;
+
+    let mut attribute_to_number: BTreeMap<String, SmallVec<[u32; 2]>> = BTreeMap::new();
+    for (id, list) in concept_attributes.iter().enumerate() {
+        for attribute in list {
+            let ids = attribute_to_number
+                .entry(attribute_key(&attribute.name, &attribute.value))
+                .or_default();
+            if !ids.contains(&(id as u32)) {
+                ids.push(id as u32);
+            }
+        }
+    }
+    write_fst(
+        &output_dir.join(ATTRIBUTES_FST_NAME),
+        attribute_to_number,
+        &mut postings,
+    )?;
+
+    let ancestors = closure.map(|kinds| build_ancestor_closure(&sorted_names, kinds));
+
+    write_concept_store(
+        &output_dir.join(CONCEPTS_STORE_NAME),
+        sorted_names,
+        strings,
+        StoreData {
+            atoms: &atoms,
+            postings: &postings,
+            ancestors: ancestors.as_deref(),
+            hierarchies: &hierarchies,
+            relations: &relations,
+            definitions: &definitions,
+            attributes: &concept_attributes,
+            cui_history: &cui_history,
+            mappings: &mappings,
+        },
+    )?;
+
+    let meta = SearchIndexMeta {
+        case_insensitive,
+        languages,
+        sources,
+        semantic_types,
+        closure,
+        definition_sources,
+        attributes,
+    };
+
+    let mut meta_file = std::fs::File::create(output_dir.join(METADATA_NAME))?;
+    serde_json::to_writer(&meta_file, &meta)?;
+    meta_file.flush()?;
+
+    Ok(())
+}
+
+/// The concepts, atoms and strings read from MRCONSO.
+struct ConceptData {
+    string_to_number: BTreeMap<String, SmallVec<[u32; 2]>>,
+    concepts: HashMap<SmolStr, (u32, u32, Concept)>,
+    atoms: Vec<Atom>,
+}
+
+impl ConceptData {
+    /// Add the concepts from a part of MRCONSO that comes after the parts already added,
+    /// numbering the new concepts in the order that they appear in the part.
+    fn add_part(&mut self, part: ConceptPart) {
+        let ids = part
+            .concepts
+            .into_iter()
+            .map(|(priority, concept)| {
+                let next_id = self.concepts.len() as u32;
+                match self.concepts.entry(concept.cui.clone()) {
+                    Entry::Occupied(entry) => {
+                        let (id, existing_priority, existing) = entry.into_mut();
+                        for code in concept.codes {
+                            if !existing.codes.contains(&code) {
+                                existing.codes.push(code);
+                            }
+                        }
+
+                        if priority > *existing_priority {
+                            *existing_priority = priority;
+                            existing.preferred_name = concept.preferred_name;
+                        }
+                        *id
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert((next_id, priority, concept));
+                        next_id
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        self.atoms.extend(part.atoms.into_iter().map(|atom| Atom {
+            concept: ids[atom.concept as usize],
+            ..atom
+        }));
+
+        for (string, part_ids) in part.strings {
+            let string_concepts = self.string_to_number.entry(string).or_default();
+            for id in part_ids {
+                let id = ids[id as usize];
+                if !string_concepts.contains(&id) {
+                    string_concepts.push(id);
+                }
+            }
+        }
+    }
+}
+
+/// The concepts, atoms and strings read from one part of MRCONSO. The concepts are numbered in
+/// the order that they first appear in the part, and the atoms and strings refer to them by
+/// those numbers until the part is added to [ConceptData].
+#[derive(Default)]
+struct ConceptPart {
+    /// Each concept, with the priority of its preferred name
+    concepts: Vec<(u32, Concept)>,
+    ids: HashMap<SmolStr, u32>,
+    atoms: Vec<Atom>,
+    strings: HashMap<String, SmallVec<[u32; 2]>>,
+}
+
+impl ConceptPart {
+    fn add_atom(
+        &mut self,
+        line: MrconsoRow,
+        types: &SmallVec<[u16; 4]>,
+        priority: u32,
+        convert_for_search: fn(&str) -> String,
+    ) {
+        let id = match self.ids.get(&line.cui) {
+            Some(&id) => id,
+            None => {
+                let id = self.concepts.len() as u32;
+                self.ids.insert(line.cui.clone(), id);
+
+                // Add the CUI to the search index too.
+                self.strings
+                    .entry(convert_for_search(&line.cui))
+                    .or_default()
+                    .push(id);
+
+                let concept = Concept {
+                    cui: line.cui.clone(),
+                    preferred_name: SmolStr::from(&line.str),
+                    codes: SmallVec::new(),
+                    types: types.clone(),
+                    parents: SmallVec::new(),
+                    children: SmallVec::new(),
+                    parent_kinds: SmallVec::new(),
+                    child_kinds: SmallVec::new(),
+                    similar: SmallVec::new(),
+                    synonym: SmallVec::new(),
+                    other_relationship: SmallVec::new(),
+                    related_possibly_synonymous: SmallVec::new(),
+                    allowed_qualifier: SmallVec::new(),
+                    qualified_by: SmallVec::new(),
+                };
+                self.concepts.push((priority, concept));
+                id
+            }
+        };
+
+        let (existing_priority, concept) = &mut self.concepts[id as usize];
+        if !line.code.is_empty() {
+            let concept_code = ConceptCode {
+                source: line.sab.clone(),
+                code: line.code.clone(),
+            };
+
+            if !concept.codes.contains(&concept_code) {
+                concept.codes.push(concept_code);
+            }
+        }
 
-    let cui_idx = mrconso.columns.iter().position(|c| c == "CUI").unwrap();
-    let lang_idx = mrconso.columns.iter().position(|c| c == "LAT").unwrap();
-    let str_idx = mrconso.columns.iter().position(|c| c == "STR").unwrap();
-    let tty_idx = mrconso.columns.iter().position(|c| c == "TTY").unwrap();
-    let source_idx = mrconso.columns.iter().position(|c| c == "SAB").unwrap();
-    let code_idx = mrconso.columns.iter().position(|c| c == "CODE").unwrap();
+        if priority > *existing_priority {
+            *existing_priority = priority;
+            concept.preferred_name = SmolStr::from(&line.str);
+        }
 
+        let string_concepts = self
+            .strings
+            .entry(convert_for_search(&line.str))
+            .or_default();
+        if !string_concepts.contains(&id) {
+            string_concepts.push(id);
+        }
+
+        self.atoms.push(Atom {
+            concept: id,
+            aui: line.aui,
+            string: line.str.into(),
+            tty: line.tty,
+            source: line.sab,
+            code: line.code,
+            preferred: line.ispref == "Y",
+            suppress: Suppress::from_rrf(&line.suppress),
+        });
+    }
+}
+
+/// Read the atoms from MRCONSO, building the concepts from them. The parts of MRCONSO are read in
+/// parallel, and the concepts are numbered in the order that they first appear in the file.
+fn read_concepts(
+    files: &Files,
+    ranks: &HashMap<RankSource, u32>,
+    concept_semantic_types: &SemanticTypeMap,
+    languages: &[SmolStr],
+    sources: &[SmolStr],
+    case_insensitive: bool,
+) -> Result<ConceptData> {
     // First build the lookups. We just do this in memory since in there are expected to be a few
     // tens of millions of strings.
-    let mut string_to_number = BTreeMap::new();
-    let mut concepts: HashMap<SmolStr, (u32, u32, Concept)> = HashMap::new();
-
     let convert_for_search = if case_insensitive {
         |s: &str| s.to_lowercase()
     } else {
         |s: &str| s.to_string()
     };
 
-    for line in mrconso.records() {
+    let parts = files.par_fold_rows(ConceptPart::default, |part, line: MrconsoRow| {
+        let Some(types) = concept_semantic_types.get(&line.cui) else {
+            return;
+        };
+
+        if !languages.is_empty() && !languages.contains(&line.lat) {
+            return;
+        }
+
+        if !sources.is_empty() && !sources.contains(&line.sab) {
+            return;
+        }
+
+        let priority = *ranks
+            .get(&RankSource {
+                sab: line.sab.clone(),
+                tty: line.tty.clone(),
+            })
+            .unwrap_or(&0);
+        part.add_atom(line, types, priority, convert_for_search);
+    })?;
+
+    let mut data = ConceptData {
+        string_to_number: BTreeMap::new(),
+        concepts: HashMap::new(),
+        atoms: Vec::new(),
+    };
+    for part in parts {
+        data.add_part(part);
+    }
+
+    Ok(data)
+}
+
+/// Write an FST mapping each string to its concepts, adding lists of concepts to `postings` for
+/// strings that have more than one.
+fn write_fst(
+    path: &Path,
+    strings: BTreeMap<String, SmallVec<[u32; 2]>>,
+    postings: &mut Vec<u32>,
+) -> Result<()> {
+    let writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut fst_builder = MapBuilder::new(writer)?;
+
+    // Since the strings are in a BTreeMap, they're already sorted as the FST builder requires.
+    for (string, concept_numbers) in strings {
+        if let [concept_number] = concept_numbers.as_slice() {
+            fst_builder.insert(string, *concept_number as u64)?;
+            continue;
+        }
+
+        fst_builder.insert(string, MULTIPLE_CONCEPTS_FLAG | postings.len() as u64)?;
+
+        postings.push(concept_numbers.len() as u32);
+        postings.extend_from_slice(&concept_numbers);
+    }
+
+    fst_builder.finish()?;
+    Ok(())
+}
+
+/// Everything besides the concepts themselves that goes into the concept store. The lists of
+/// atoms, hierarchies and relations must be sorted by concept.
+struct StoreData<'a> {
+    atoms: &'a [Atom],
+    postings: &'a [u32],
+    ancestors: Option<&'a [Vec<u32>]>,
+    hierarchies: &'a [HierarchyContext],
+    relations: &'a [Relation],
+    definitions: &'a [Vec<Definition>],
+    attributes: &'a [Vec<Attribute>],
+    cui_history: &'a BTreeMap<SmolStr, Vec<CuiHistoryEntry>>,
+    /// Mappings keyed by the source and code they map from
+    mappings: &'a BTreeMap<SmolStr, Vec<MapEntry>>,
+}
+
+/// Write the concepts, along with their atoms and the search postings lists, to the binary
+/// concept store. `strings` holds the strings that the relations refer to.
+fn write_concept_store(
+    path: &Path,
+    concepts: Vec<(u32, Concept)>,
+    mut strings: StringPool,
+    data: StoreData,
+) -> Result<()> {
+    let StoreData {
+        atoms,
+        postings,
+        ancestors,
+        hierarchies,
+        relations,
+        definitions,
+        attributes,
+        cui_history,
+        mappings,
+    } = data;
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut writer = StoreWriter::new(file)?;
+
+    let mut records = Vec::with_capacity(concepts.len() * store::CONCEPT_RECORD_LEN);
+    let mut types = ListBuilder::new(store::TYPES);
+    let mut codes = ListBuilder::new(store::CODES);
+    let mut atom_list = ListBuilder::new(store::ATOMS);
+    let mut parent_kinds = ListBuilder::new(store::PARENT_KINDS);
+    let mut child_kinds = ListBuilder::new(store::CHILD_KINDS);
+    let mut hierarchy_list = ListBuilder::new(store::HIERARCHIES);
+    let mut hierarchy_paths: Vec<u32> = Vec::new();
+    let mut relation_list = ListBuilder::new(store::RELATIONS);
+    let mut definition_list = ListBuilder::new(store::DEFINITIONS);
+    let mut attribute_list = ListBuilder::new(store::ATTRIBUTES);
+    let mut relationships = [
+        store::PARENTS,
+        store::CHILDREN,
+        store::SIMILAR,
+        store::SYNONYM,
+        store::OTHER_RELATIONSHIP,
+        store::RELATED_POSSIBLY_SYNONYMOUS,
+        store::ALLOWED_QUALIFIER,
+        store::QUALIFIED_BY,
+    ]
+    .map(ListBuilder::new);
+
+    let mut atoms = atoms.iter().peekable();
+    let mut hierarchies = hierarchies.iter().peekable();
+    let mut relations = relations.iter().peekable();
+    let mut num_atoms = 0;
+    for (id, mut concept) in concepts {
+        records.push(strings.intern(&concept.cui));
+        records.push(strings.intern(&concept.preferred_name));
+
+        for t in &concept.types {
+            types.push(&[*t as u32]);
+        }
+        types.finish_concept();
+
+        concept.codes.sort_unstable();
+        for code in &concept.codes {
+            codes.push(&[strings.intern(&code.source), strings.intern(&code.code)]);
+        }
+        codes.finish_concept();
+
+        while let Some(atom) = atoms.next_if(|a| a.concept == id) {
+            let mut flags = atom.suppress.as_u32() << store::ATOM_SUPPRESS_SHIFT;
+            if atom.preferred {
+                flags |= store::ATOM_PREFERRED_FLAG;
+            }
+
+            atom_list.push(&[
+                strings.intern(&atom.aui),
+                strings.intern(&atom.string),
+                strings.intern(&atom.tty),
+                strings.intern(&atom.source),
+                strings.intern(&atom.code),
+                flags,
+            ]);
+            num_atoms += 1;
+        }
+        atom_list.finish_concept();
+
+        while let Some(context) = hierarchies.next_if(|h| h.atom < num_atoms) {
+            hierarchy_list.push(&[
+                context.atom,
+                strings.intern(&context.rela),
+                strings.intern(&context.hcd),
+                hierarchy_paths.len() as u32,
+                context.complete as u32,
+            ]);
+            hierarchy_paths.push(context.path.len() as u32);
+            hierarchy_paths.extend_from_slice(&context.path);
+        }
+        hierarchy_list.finish_concept();
+
+        while let Some(relation) = relations.next_if(|r| r.concept == id) {
+            relation_list.push(&[
+                relation.other,
+                relation.rel,
+                relation.rela,
+                relation.source,
+                relation.group,
+                relation.direction,
+            ]);
+        }
+        relation_list.finish_concept();
+
+        for definition in &definitions[id as usize] {
+            definition_list.push(&[
+                strings.intern(&definition.source),
+                strings.intern(&definition.text),
+            ]);
+        }
+        definition_list.finish_concept();
+
+        for attribute in &attributes[id as usize] {
+            attribute_list.push(&[
+                strings.intern(&attribute.name),
+                strings.intern(&attribute.value),
+                strings.intern(&attribute.source),
+                strings.intern(&attribute.code),
+            ]);
+        }
+        attribute_list.finish_concept();
+
+        let lists = [
+            &concept.parents,
+            &concept.children,
+            &concept.similar,
+            &concept.synonym,
+            &concept.other_relationship,
+            &concept.related_possibly_synonymous,
+            &concept.allowed_qualifier,
+            &concept.qualified_by,
+        ];
+        for (builder, list) in relationships.iter_mut().zip(lists) {
+            for id in list {
+                builder.push(&[*id]);
+            }
+            builder.finish_concept();
+        }
+
+        for (builder, kinds) in [
+            (&mut parent_kinds, &concept.parent_kinds),
+            (&mut child_kinds, &concept.child_kinds),
+        ] {
+            for kind in kinds {
+                builder.push(&[*kind]);
+            }
+            builder.finish_concept();
+        }
+    }
+
+    // These FSTs live next to the store, and point into its sections.
+    let cui_history = write_record_fst(
+        &path.with_file_name(CUI_HISTORY_FST_NAME),
+        cui_history,
+        &mut strings,
+        |entry, strings| {
+            [
+                strings.intern(&entry.to),
+                strings.intern(&entry.version),
+                entry.kind.as_u32(),
+            ]
+        },
+    )?;
+    let mappings = write_record_fst(
+        &path.with_file_name(MAPPINGS_FST_NAME),
+        mappings,
+        &mut strings,
+        |m: &MapEntry, strings| {
+            [
+                strings.intern(&m.target_code),
+                strings.intern(&m.target_source),
+                strings.intern(&m.map_set),
+                m.group,
+                m.priority,
+                strings.intern(&m.rule),
+                strings.intern(&m.advice),
+                strings.intern(&m.rel),
+                strings.intern(&m.rela),
+                strings.intern(&m.map_type),
+            ]
+        },
+    )?;
+
+    writer.write_section(Section::Concepts, bytemuck::cast_slice(&records))?;
+    writer.write_section(Section::CuiHistory, bytemuck::cast_slice(&cui_history))?;
+    writer.write_section(Section::Mappings, bytemuck::cast_slice(&mappings))?;
+    writer.write_section(Section::Postings, bytemuck::cast_slice(postings))?;
+    strings.write(&mut writer)?;
+    types.write(&mut writer)?;
+    codes.write(&mut writer)?;
+    atom_list.write(&mut writer)?;
+    for builder in relationships {
+        builder.write(&mut writer)?;
+    }
+    parent_kinds.write(&mut writer)?;
+    child_kinds.write(&mut writer)?;
+    hierarchy_list.write(&mut writer)?;
+    writer.write_section(
+        Section::HierarchyPaths,
+        bytemuck::cast_slice(&hierarchy_paths),
+    )?;
+    relation_list.write(&mut writer)?;
+    definition_list.write(&mut writer)?;
+    attribute_list.write(&mut writer)?;
+
+    if let Some(ancestors) = ancestors {
+        let mut builder = ListBuilder::new(store::ANCESTORS);
+        for list in ancestors {
+            for id in list {
+                builder.push(&[*id]);
+            }
+            builder.finish_concept();
+        }
+        builder.write(&mut writer)?;
+    }
+
+    writer.finish()?.into_inner()?.flush()?;
+
+    Ok(())
+}
+
+/// Read the definitions of each concept from MRDEF, if the release has it. Suppressed definitions
+/// are skipped.
+fn read_definitions(
+    files: &Files,
+    cui_ids: &HashMap<SmolStr, u32>,
+    sources: &[SmolStr],
+    definition_sources: &[SmolStr],
+) -> Result<Vec<Vec<Definition>>> {
+    let mut definitions = vec![Vec::new(); cui_ids.len()];
+    if !files.has_file("MRDEF") {
+        return Ok(definitions);
+    }
+
+    let mut mrdef = files.mrdef()?;
+    for line in mrdef.rows() {
         let line = line?;
-        let cui = line.get(cui_idx).unwrap();
-        let code = line.get(code_idx).unwrap();
-        let source = line.get(source_idx).unwrap();
-        let orig_string = line.get(str_idx).unwrap();
+        let Some(&id) = cui_ids.get(&line.cui) else {
+            continue;
+        };
 
-        let Some(sty) = concept_semantic_types.get(cui) else {
+        if Suppress::from_rrf(&line.suppress) != Suppress::No {
             continue;
+        }
+
+        let source = line.sab;
+        if !sources.is_empty() && !sources.contains(&source) {
+            continue;
+        }
+        if !definition_sources.is_empty() && !definition_sources.contains(&source) {
+            continue;
+        }
+
+        let definition = Definition {
+            source,
+            text: line.def.into(),
         };
+        let list = &mut definitions[id as usize];
+        if !list.contains(&definition) {
+            list.push(definition);
+        }
+    }
+
+    if !definition_sources.is_empty() {
+        let rank = |d: &Definition| definition_sources.iter().position(|s| *s == d.source);
+        for list in &mut definitions {
+            // A stable sort keeps the definitions from each source in file order.
+            list.sort_by_key(rank);
+        }
+    }
+
+    Ok(definitions)
+}
+
+/// A change to a retired CUI.
+#[derive(PartialEq, Eq)]
+struct CuiHistoryEntry {
+    /// The new CUI, or empty for a deletion.
+    to: SmolStr,
+    version: SmolStr,
+    kind: CuiChangeKind,
+}
+
+/// Read the history of retired CUIs from MRCUI, along with DELETEDCUI and MERGEDCUI from the
+/// CHANGE directory when the release has them.
+fn read_cui_history(files: &Files) -> Result<BTreeMap<SmolStr, Vec<CuiHistoryEntry>>> {
+    let mut history: BTreeMap<SmolStr, Vec<CuiHistoryEntry>> = BTreeMap::new();
+    let mut add = |cui: &str, entry: CuiHistoryEntry| {
+        let entries = history.entry(cui.into()).or_default();
+        // DELETEDCUI and MERGEDCUI mostly repeat what MRCUI says, without the version.
+        if !entries
+            .iter()
+            .any(|e| e.to == entry.to && e.kind == entry.kind)
+        {
+            entries.push(entry);
+        }
+    };
+
+    if files.has_file("MRCUI") {
+        for line in files.mrcui()?.rows() {
+            let line = line?;
+            let Some(kind) = CuiChangeKind::from_rel(&line.rel) else {
+                continue;
+            };
+
+            add(
+                &line.cui1,
+                CuiHistoryEntry {
+                    to: line.cui2,
+                    version: line.ver,
+                    kind,
+                },
+            );
+        }
+    }
+
+    if files.has_file("MERGEDCUI") {
+        for line in files.mergedcui()?.rows() {
+            let line = line?;
+            add(
+                &line.pcui,
+                CuiHistoryEntry {
+                    to: line.cui,
+                    version: SmolStr::default(),
+                    kind: CuiChangeKind::Merged,
+                },
+            );
+        }
+    }
+
+    if files.has_file("DELETEDCUI") {
+        for line in files.deletedcui()?.rows() {
+            let line = line?;
+            add(
+                &line.pcui,
+                CuiHistoryEntry {
+                    to: SmolStr::default(),
+                    version: SmolStr::default(),
+                    kind: CuiChangeKind::Deleted,
+                },
+            );
+        }
+    }
+
+    Ok(history)
+}
+
+/// A row of MRMAP or MRSMAP.
+#[derive(PartialEq, Eq)]
+struct MapEntry {
+    target_code: SmolStr,
+    target_source: SmolStr,
+    map_set: SmolStr,
+    group: u32,
+    priority: u32,
+    rule: SmolStr,
+    advice: SmolStr,
+    rel: SmolStr,
+    rela: SmolStr,
+    map_type: SmolStr,
+}
+
+/// A mapping read from MRMAP or MRSMAP, before it is filed under the code it maps from.
+struct MapRow {
+    /// The source and ID that the mapping starts from, as made by [code_key]
+    key: SmolStr,
+    /// True if the ID is the source's concept or descriptor ID (SCUI or SDUI) rather than a code
+    from_source_id: bool,
+    entry: MapEntry,
+}
 
-        let string = convert_for_search(orig_string);
-        if !languages.is_empty() {
-            let lang = line.get(lang_idx).unwrap();
-            if !languages.iter().any(|l| l == lang) {
+/// Read the source-asserted mappings from MRMAP, and from MRSMAP for map sets that are not in
+/// MRMAP. Mappings from anything other than a code or a source concept or descriptor ID, such as
+/// an expression, are left out. See [file_mappings] for the next step.
+fn read_mappings(files: &Files, sources: &[SmolStr]) -> Result<Vec<MapRow>> {
+    // (map set CUI, map set source, from ID, from ID type, entry)
+    let mut rows: Vec<(SmolStr, SmolStr, SmolStr, SmolStr, MapEntry)> = Vec::new();
+
+    if files.has_file("MRMAP") {
+        for line in files.mrmap()?.rows() {
+            let line = line?;
+            rows.push((
+                line.mapsetcui.clone(),
+                line.mapsetsab,
+                line.fromexpr,
+                line.fromtype,
+                MapEntry {
+                    target_code: line.toexpr,
+                    target_source: SmolStr::default(),
+                    map_set: line.mapsetcui,
+                    group: line.mapsubsetid.unwrap_or(0),
+                    priority: line.maprank.unwrap_or(0),
+                    rule: line.maprule.into(),
+                    advice: line.mapres.into(),
+                    rel: line.rel,
+                    rela: line.rela,
+                    map_type: line.maptype,
+                },
+            ));
+        }
+    }
+
+    // MRSMAP repeats the simpler map sets from MRMAP, without the rules.
+    let full_map_sets = rows
+        .iter()
+        .map(|(set, ..)| set.clone())
+        .collect::<HashSet<_>>();
+    if files.has_file("MRSMAP") {
+        for line in files.mrsmap()?.rows() {
+            let line = line?;
+            if full_map_sets.contains(&line.mapsetcui) {
                 continue;
             }
+
+            rows.push((
+                line.mapsetcui.clone(),
+                line.mapsetsab,
+                line.fromexpr,
+                line.fromtype,
+                MapEntry {
+                    target_code: line.toexpr,
+                    target_source: SmolStr::default(),
+                    map_set: line.mapsetcui,
+                    group: 0,
+                    priority: 0,
+                    rule: SmolStr::default(),
+                    advice: SmolStr::default(),
+                    rel: line.rel,
+                    rela: line.rela,
+                    map_type: SmolStr::default(),
+                },
+            ));
         }
+    }
+
+    let map_set_sources =
+        read_map_set_sources(files, &rows.iter().map(|(set, ..)| set.clone()).collect())?;
+
+    let mut mappings = Vec::new();
+    for (map_set, map_set_source, from_id, from_type, mut entry) in rows {
+        let from_source_id = match from_type.as_str() {
+            "CODE" => false,
+            "SCUI" | "SDUI" => true,
+            _ => continue,
+        };
 
-        if !sources.is_empty() && !sources.iter().any(|s| s == source) {
+        let (from_source, target_source) = match map_set_sources.get(&map_set) {
+            Some((from, to)) => (from.clone(), to.clone()),
+            None => (map_set_source, SmolStr::default()),
+        };
+
+        // The target source is unknown for map sets without MRSAT attributes, so they are kept
+        // whatever sources were chosen.
+        let included = |source: &SmolStr| sources.is_empty() || sources.contains(source);
+        if !included(&from_source) || !(target_source.is_empty() || included(&target_source)) {
             continue;
         }
 
-        let next_id = (concepts.len()) as u32;
-        let (concept_number, _, _) = *concepts
-            .entry(cui.into())
-            .and_modify(|(_, existing_priority, concept)| {
-                let new_priority = *ranks
-                    .get(&RankSource {
-                        sab: source.into(),
-                        tty: line.get(tty_idx).unwrap().into(),
-                    })
-                    .unwrap_or(&0);
-
-                if !code.is_empty() {
-                    let concept_code = ConceptCode {
-                        source: source.into(),
-                        code: code.into(),
-                    };
-
-                    if !concept.codes.contains(&concept_code) {
-                        concept.codes.push(concept_code);
-                    }
-                }
+        entry.target_source = target_source;
+        mappings.push(MapRow {
+            key: code_key(&from_source, &from_id).into(),
+            from_source_id,
+            entry,
+        });
+    }
 
-                if new_priority > *existing_priority {
-                    *existing_priority = new_priority;
-                    concept.preferred_name = SmolStr::from(orig_string);
-                }
-            })
-            .or_insert_with(|| {
-                let source = SmolStr::from(source);
-                let rank_source_arg = RankSource {
-                    sab: source,
-                    tty: line.get(tty_idx).unwrap().into(),
-                };
-                let string_priority = *ranks.get(&rank_source_arg).unwrap_or(&0);
-
-                let mut codes = SmallVec::new();
-                if !code.is_empty() {
-                    codes.push(ConceptCode {
-                        source: rank_source_arg.sab,
-                        code: code.into(),
-                    });
-                }
+    Ok(mappings)
+}
 
-                // Add the CUI to the search index too.
-                string_to_number.insert(convert_for_search(cui), next_id);
-
-                (
-                    next_id,
-                    string_priority,
-                    Concept {
-                        cui: cui.into(),
-                        preferred_name: SmolStr::from(orig_string),
-                        codes,
-                        types: sty.clone(),
-                        parents: SmallVec::new(),
-                        children: SmallVec::new(),
-                        similar: SmallVec::new(),
-                        synonym: SmallVec::new(),
-                        other_relationship: SmallVec::new(),
-                        related_possibly_synonymous: SmallVec::new(),
-                        allowed_qualifier: SmallVec::new(),
-                        qualified_by: SmallVec::new(),
-                    },
-                )
-            });
+/// File the mappings under the source and code that they map from. Some map sets, such as
+/// SNOMED CT's, map from the source's concept IDs, so those mappings are kept when `is_code`
+/// says the ID is also one of the source's codes, and left out otherwise. Each list is sorted by
+/// map group and priority.
+fn file_mappings(
+    rows: Vec<MapRow>,
+    is_code: impl Fn(&str) -> bool,
+) -> BTreeMap<SmolStr, Vec<MapEntry>> {
+    let mut mappings: BTreeMap<SmolStr, Vec<MapEntry>> = BTreeMap::new();
+    for row in rows {
+        if row.from_source_id && !is_code(&row.key) {
+            continue;
+        }
+
+        let entries = mappings.entry(row.key).or_default();
+        if !entries.contains(&row.entry) {
+            entries.push(row.entry);
+        }
+    }
 
-        string_to_number.entry(string).or_insert(concept_number);
+    for entries in mappings.values_mut() {
+        entries.sort_by_key(|e| (e.group, e.priority));
     }
 
-    // Now that we have the strings sorted (since we're using a BTree) we can build the FST.
-    let output_fst_path = output_dir.join(STRINGS_FST_NAME);
-    let output_fst_writer = std::io::BufWriter::new(std::fs::File::create(&output_fst_path)?);
-    let mut fst_builder = MapBuilder::new(output_fst_writer)?;
+    mappings
+}
 
-    for (string, concept_number) in string_to_number {
-        fst_builder.insert(string, concept_number as u64)?;
+/// Find the sources that each map set maps from and to, using the FROMRSAB and TORSAB attributes
+/// of the map set concepts in MRSAT. Map sets without these attributes are left out.
+fn read_map_set_sources(
+    files: &Files,
+    map_sets: &HashSet<SmolStr>,
+) -> Result<HashMap<SmolStr, (SmolStr, SmolStr)>> {
+    let mut result: HashMap<SmolStr, (SmolStr, SmolStr)> = HashMap::new();
+    if map_sets.is_empty() || !files.has_file("MRSAT") {
+        return Ok(result);
     }
 
-    fst_builder.finish()?;
+    let mut from = HashMap::new();
+    let mut to = HashMap::new();
+    let mut mrsat = files.mrsat()?;
+    for line in mrsat.rows() {
+        let line = line?;
+        let target = match line.atn.as_str() {
+            "FROMRSAB" => &mut from,
+            "TORSAB" => &mut to,
+            _ => continue,
+        };
 
-    let output_types_path = output_dir.join(SEMANTIC_TYPES_LST_NAME);
-    let mut output_types_writer =
-        std::io::BufWriter::new(std::fs::File::create(&output_types_path)?);
-    for (_, sem) in semantic_type_defs {
-        serde_json::to_writer(&mut output_types_writer, &sem)?;
-        writeln!(output_types_writer)?;
+        if map_sets.contains(&line.cui) {
+            target.insert(line.cui, SmolStr::from(line.atv));
+        }
     }
 
-    output_types_writer.flush()?;
+    for (cui, from_source) in from {
+        if let Some(to_source) = to.remove(&cui) {
+            result.insert(cui, (from_source, to_source));
+        }
+    }
 
-    let output_names_path = output_dir.join(CONCEPTS_LST_NAME);
-    let mut output_names_writer = GzEncoder::new(
-        std::io::BufWriter::new(std::fs::File::create(&output_names_path)?),
-        flate2::Compression::default(),
-    );
-    let mut sorted_names = concepts
-        .into_iter()
-        .map(|(_, (id, _, concept))| (id, concept))
-        .collect::<Vec<_>>();
-    sorted_names.sort_unstable_by_key(|(id, _)| *id);
+    Ok(result)
+}
 
-    build_relationships(files, sorted_names.as_mut())?;
+/// Write an FST mapping each key to a group of records, returning the section that the FST points
+/// into. Each group in the section is the number of records followed by the records.
+fn write_record_fst<T, const N: usize>(
+    path: &Path,
+    groups: &BTreeMap<SmolStr, Vec<T>>,
+    strings: &mut StringPool,
+    mut encode: impl FnMut(&T, &mut StringPool) -> [u32; N],
+) -> Result<Vec<u32>> {
+    let writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut fst_builder = MapBuilder::new(writer)?;
+
+    let mut section = Vec::new();
+    for (key, records) in groups {
+        fst_builder.insert(key.as_bytes(), section.len() as u64)?;
+        section.push(records.len() as u32);
+        for record in records {
+            section.extend_from_slice(&encode(record, strings));
+        }
+    }
 
-    for (_, mut concept) in sorted_names {
-        concept.codes.sort_unstable();
-        serde_json::to_writer(&mut output_names_writer, &concept)?;
-        writeln!(output_names_writer)?;
+    fst_builder.finish()?;
+    Ok(section)
+}
+
+/// Read the attributes named in `names` from MRSAT, if the release has it. Suppressed attributes
+/// are skipped.
+fn read_attributes(
+    files: &Files,
+    cui_ids: &HashMap<SmolStr, u32>,
+    sources: &[SmolStr],
+    names: &[SmolStr],
+) -> Result<Vec<Vec<Attribute>>> {
+    let mut attributes = vec![Vec::new(); cui_ids.len()];
+    if names.is_empty() || !files.has_file("MRSAT") {
+        return Ok(attributes);
     }
 
-    let buf_writer = output_names_writer.finish()?;
-    buf_writer.into_inner()?.flush()?;
+    fn add(list: &mut Vec<Attribute>, attribute: Attribute) {
+        if !list.contains(&attribute) {
+            list.push(attribute);
+        }
+    }
 
-    let meta = SearchIndexMeta {
-        case_insensitive,
-        languages,
-        sources,
-        semantic_types,
-    };
+    // MRSAT is the largest file, so read its parts in parallel. The same attribute is often
+    // repeated for several atoms of a concept, so remove duplicates within each part too.
+    let parts = files.par_fold_rows(
+        HashMap::<u32, Vec<Attribute>>::new,
+        |part, line: MrsatRow| {
+            if !names.contains(&line.atn) {
+                return;
+            }
 
-    let mut meta_file = std::fs::File::create(output_dir.join(METADATA_NAME))?;
-    serde_json::to_writer(&meta_file, &meta)?;
-    meta_file.flush()?;
+            let Some(&id) = cui_ids.get(&line.cui) else {
+                return;
+            };
 
-    Ok(())
+            if Suppress::from_rrf(&line.suppress) != Suppress::No {
+                return;
+            }
+
+            if !sources.is_empty() && !sources.contains(&line.sab) {
+                return;
+            }
+
+            let attribute = Attribute {
+                name: line.atn,
+                value: line.atv.into(),
+                source: line.sab,
+                code: line.code,
+            };
+            add(part.entry(id).or_default(), attribute);
+        },
+    )?;
+
+    for part in parts {
+        for (id, list) in part {
+            for attribute in list {
+                add(&mut attributes[id as usize], attribute);
+            }
+        }
+    }
+
+    Ok(attributes)
+}
+
+/// A row of MRREL, from the point of view of CUI1. The REL, RELA, SAB and RG values are string IDs,
+/// so that the tens of millions of rows in a full release stay small.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Relation {
+    concept: u32,
+    other: u32,
+    rel: u32,
+    rela: u32,
+    source: u32,
+    group: u32,
+    direction: u32,
 }
 
 /// Take the sorted list of concepts and add relationship data to it.
-/// This modifies `concepts` in place.
-fn build_relationships(files: &Files, concepts: &mut [(u32, Concept)]) -> Result<()> {
-    let by_cui = concepts
-        .iter()
-        .enumerate()
-        .map(|(i, c)| (c.1.cui.clone(), i))
-        .collect::<HashMap<_, _>>();
+/// This modifies `concepts` in place, and returns every relationship with its attributes, sorted
+/// by concept. The strings of the relationships are added to `strings`.
+fn build_relationships(
+    files: &Files,
+    cui_ids: &HashMap<SmolStr, u32>,
+    concepts: &mut [(u32, Concept)],
+    strings: &mut StringPool,
+) -> Result<Vec<Relation>> {
+    // The rows are read on many threads, so they get temporary IDs for their strings, which are
+    // replaced with IDs from the string pool once they have all been read. These columns only
+    // take a few thousand values, so nearly every lookup only needs the read lock.
+    let labels = RwLock::new(HashMap::<SmolStr, u32>::new());
+    let intern = |values: [SmolStr; 4]| {
+        let found = {
+            let labels = labels.read().unwrap();
+            values.each_ref().map(|v| labels.get(v).copied())
+        };
+        if let [Some(rel), Some(rela), Some(source), Some(group)] = found {
+            return [rel, rela, source, group];
+        }
 
-    let mut mrrel = files.get_file_stream("MRREL")?;
-    let cui_idx = mrrel.columns.iter().position(|c| c == "CUI1").unwrap();
-    let rel_idx = mrrel.columns.iter().position(|c| c == "REL").unwrap();
-    let cui2_idx = mrrel.columns.iter().position(|c| c == "CUI2").unwrap();
+        let mut labels = labels.write().unwrap();
+        values.map(|v| {
+            let next = labels.len() as u32;
+            *labels.entry(v).or_insert(next)
+        })
+    };
 
-    for line in mrrel.records() {
-        let line = line?;
-        let cui1 = line.get(cui_idx).unwrap();
-        let rel = line.get(rel_idx).unwrap();
-        let cui2 = line.get(cui2_idx).unwrap();
+    // Atom-level relationships are often repeated between the same concepts, so each part only
+    // keeps one copy of each.
+    let parts = files.par_fold_rows(HashSet::<Relation>::new, |part, line: MrrelRow| {
+        if line.cui1 == line.cui2 {
+            return;
+        }
+
+        let Some((&i1, &i2)) = cui_ids.get(&line.cui1).zip(cui_ids.get(&line.cui2)) else {
+            return;
+        };
+        let [rel, rela, source, group] = intern([line.rel, line.rela, line.sab, line.rg]);
+        part.insert(Relation {
+            concept: i1,
+            other: i2,
+            rel,
+            rela,
+            source,
+            group,
+            direction: match line.dir.as_str() {
+                "Y" => store::RELATION_DIRECTION_ASSERTED,
+                "N" => store::RELATION_DIRECTION_INVERSE,
+                _ => 0,
+            },
+        });
+    })?;
 
-        let is_parent = rel == "PAR" || rel == "RB";
-        let is_child = rel == "CHD" || rel == "RN";
+    let mut names = vec![SmolStr::default(); labels.read().unwrap().len()];
+    for (name, id) in labels.into_inner().unwrap() {
+        names[id as usize] = name;
+    }
 
-        if cui1 == cui2 {
-            continue;
-        }
+    // Add the strings to the pool in sorted order, so that the string IDs, and so the order of
+    // each concept's relations, don't depend on which thread saw a string first.
+    let mut order = (0..names.len()).collect::<Vec<_>>();
+    order.sort_unstable_by_key(|&i| &names[i]);
+    let mut ids = vec![0; names.len()];
+    let mut rel_names = HashMap::with_capacity(names.len());
+    for i in order {
+        ids[i] = strings.intern(&names[i]);
+        rel_names.insert(ids[i], names[i].as_str());
+    }
 
-        let (i1, i2) = match by_cui.get(cui1).zip(by_cui.get(cui2)) {
-            Some((i1, i2)) => (*i1 as u32, *i2 as u32),
-            None => continue,
+    let mut relations = parts
+        .into_iter()
+        .flatten()
+        .map(|relation| Relation {
+            rel: ids[relation.rel as usize],
+            rela: ids[relation.rela as usize],
+            source: ids[relation.source as usize],
+            group: ids[relation.group as usize],
+            ..relation
+        })
+        .collect::<Vec<_>>();
+    relations.sort_unstable();
+    relations.dedup();
+
+    for relation in &relations {
+        let (i1, i2) = (relation.concept, relation.other);
+        let rel = rel_names[&relation.rel];
+        let (is_parent, is_child, kind) = match rel {
+            "PAR" => (true, false, RelationKinds::PARENT),
+            "CHD" => (false, true, RelationKinds::PARENT),
+            "RB" => (true, false, RelationKinds::BROADER),
+            "RN" => (false, true, RelationKinds::BROADER),
+            _ => (false, false, RelationKinds::NONE),
         };
 
         if is_parent || is_child {
-            {
-                let concept1 = &mut concepts[i1 as usize].1;
-                if is_parent && !concept1.parents.contains(&i2) {
-                    concept1.parents.push(i2);
-                } else if is_child && !concept1.children.contains(&i2) {
-                    concept1.children.push(i2);
-                }
-            }
-
-            {
-                let concept2 = &mut concepts[i2 as usize].1;
-                if is_parent && !concept2.children.contains(&i1) {
-                    concept2.children.push(i1);
-                } else if is_child && !concept2.parents.contains(&i1) {
-                    concept2.parents.push(i1);
-                }
-            }
+            let (child, parent) = if is_parent { (i1, i2) } else { (i2, i1) };
+
+            let child_concept = &mut concepts[child as usize].1;
+            add_edge(
+                &mut child_concept.parents,
+                &mut child_concept.parent_kinds,
+                parent,
+                kind,
+            );
+
+            let parent_concept = &mut concepts[parent as usize].1;
+            add_edge(
+                &mut parent_concept.children,
+                &mut parent_concept.child_kinds,
+                child,
+                kind,
+            );
         } else {
             let concept1 = &mut concepts[i1 as usize].1;
             let add_array = match rel {
@@ -282,7 +1226,120 @@ fn build_relationships(files: &Files, concepts: &mut [(u32, Concept)]) -> Result
         }
     }
 
-    Ok(())
+    Ok(relations)
+}
+
+/// A row of MRHIER, placing an atom in its source's hierarchy.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct HierarchyContext {
+    /// The index of the atom, in the order that the atoms are written to the store.
+    atom: u32,
+    rela: SmolStr,
+    hcd: SmolStr,
+    /// The atoms from the root of the hierarchy down to the parent of this atom.
+    path: Vec<u32>,
+    /// False if any atom in the path, other than the root, was left out because it isn't in the
+    /// index.
+    complete: bool,
+}
+
+/// Read the source hierarchies from MRHIER, if the release has it. The returned contexts are
+/// sorted by atom. Atoms in the path that are not in the index, such as the SRC atoms at the root
+/// of each hierarchy, are left out, and the context is marked incomplete if any of them were
+/// below the root.
+fn build_hierarchies(files: &Files, atoms: &[Atom]) -> Result<Vec<HierarchyContext>> {
+    if !files.has_file("MRHIER") {
+        return Ok(Vec::new());
+    }
+
+    let atom_ids = atoms
+        .iter()
+        .enumerate()
+        .map(|(i, atom)| (atom.aui.as_str(), i as u32))
+        .collect::<HashMap<_, _>>();
+
+    let parts =
+        files.par_fold_rows(HashSet::<HierarchyContext>::new, |part, line: MrhierRow| {
+            let Some(&atom) = atom_ids.get(line.aui.as_str()) else {
+                return;
+            };
+
+            let mut complete = true;
+            let path = line
+                .ptr
+                .split('.')
+                .filter(|aui| !aui.is_empty())
+                .enumerate()
+                .filter_map(|(i, aui)| {
+                    let atom = atom_ids.get(aui).copied();
+                    // The root is the SRC atom for the source, which is often not in the index.
+                    complete &= atom.is_some() || i == 0;
+                    atom
+                })
+                .collect();
+
+            part.insert(HierarchyContext {
+                atom,
+                rela: line.rela,
+                hcd: line.hcd,
+                path,
+                complete,
+            });
+        })?;
+
+    let mut contexts = parts.into_iter().flatten().collect::<Vec<_>>();
+    contexts.sort_unstable();
+    contexts.dedup();
+    Ok(contexts)
+}
+
+/// Find the sorted list of ancestors of each concept, following only parents of the given kinds.
+fn build_ancestor_closure(concepts: &[(u32, Concept)], kinds: RelationKinds) -> Vec<Vec<u32>> {
+    let parents = |id: u32| {
+        let concept = &concepts[id as usize].1;
+        concept
+            .parents
+            .iter()
+            .zip(&concept.parent_kinds)
+            .filter(move |(_, &k)| RelationKinds::from_bits(k).intersects(kinds))
+            .map(|(&p, _)| p)
+    };
+
+    concepts
+        .par_iter()
+        .map(|(id, _)| {
+            let mut seen = HashSet::new();
+            let mut queue = parents(*id).collect::<Vec<_>>();
+            while let Some(ancestor) = queue.pop() {
+                if seen.insert(ancestor) {
+                    queue.extend(parents(ancestor));
+                }
+            }
+
+            // The graph can contain cycles, but a concept isn't its own ancestor.
+            seen.remove(id);
+            let mut ancestors = seen.into_iter().collect::<Vec<_>>();
+            ancestors.sort_unstable();
+            ancestors
+        })
+        .collect()
+}
+
+/// Add an edge to a list of hierarchical relationships, or add `kind` to the edge if it is
+/// already there.
+fn add_edge(
+    ids: &mut SmallVec<[u32; 4]>,
+    kinds: &mut SmallVec<[u32; 4]>,
+    id: u32,
+    kind: RelationKinds,
+) {
+    match ids.iter().position(|&existing| existing == id) {
+        Some(i) => kinds[i] |= kind.bits(),
+        None => {
+            ids.push(id);
+            kinds.push(kind.bits());
+        }
+    }
 }
 
 #[derive(Hash, PartialEq, Eq)]
@@ -293,26 +1350,18 @@ struct RankSource {
 
 /// Read the ranks files and return the list of sources sorted by priority.
 fn read_ranks(files: &Files) -> Result<HashMap<RankSource, u32>> {
-    let mut mrrank = files.get_file_stream("MRRANK").unwrap();
-
-    let rank_idx = mrrank.columns.iter().position(|c| c == "RANK").unwrap();
-    let sab_idx = mrrank.columns.iter().position(|c| c == "SAB").unwrap();
-    let tty_idx = mrrank.columns.iter().position(|c| c == "TTY").unwrap();
+    let mut mrrank = files.mrrank()?;
 
     let ranks = mrrank
-        .records()
+        .rows()
         .map(|line| {
             let line = line?;
-            let rank = line.get(rank_idx).unwrap().parse::<u32>()?;
-            let sab = line.get(sab_idx).unwrap();
-            let tty = line.get(tty_idx).unwrap();
-
             Ok((
                 RankSource {
-                    sab: sab.into(),
-                    tty: tty.into(),
+                    sab: line.sab,
+                    tty: line.tty,
                 },
-                rank,
+                line.rank,
             ))
         })
         .collect::<Result<HashMap<_, _>>>()?;
@@ -351,6 +1400,76 @@ pub fn read_semantic_types(files: &Files) -> Result<HashMap<u16, SemanticType>>
     Ok(output)
 }
 
+/// Read the relationship types from SRDEF, and the relationships between semantic types. This
+/// uses the fully inherited relationships in SRSTRE1 when it is present, and otherwise falls back
+/// to the relationships that SRSTR defines directly, without inheritance.
+pub fn read_semantic_network(
+    files: &Files,
+    type_defs: &HashMap<u16, SemanticType>,
+) -> Result<SemanticNetwork> {
+    let net_dir = files.base_dir.join("NET");
+    let srdef = std::fs::File::open(net_dir.join("SRDEF"))?;
+    let mut reader = create_csv_reader(srdef);
+
+    let mut relation_types = Vec::new();
+    for line in reader.records() {
+        let line = line?;
+        if line.get(0) != Some("RL") {
+            continue;
+        }
+
+        relation_types.push(SemanticRelationType {
+            rui: line.get(1).unwrap_or_default().into(),
+            name: line.get(2).unwrap_or_default().into(),
+            tree_number: line.get(3).unwrap_or_default().into(),
+            description: line.get(4).unwrap_or_default().into(),
+            inverse: line.get(9).unwrap_or_default().into(),
+        });
+    }
+
+    let mut relations = Vec::new();
+    let srstre1 = net_dir.join("SRSTRE1");
+    let srstr = net_dir.join("SRSTR");
+    if srstre1.exists() {
+        let mut reader = create_csv_reader(std::fs::File::open(srstre1)?);
+        for line in reader.records() {
+            let line = line?;
+            relations.push((
+                parse_tui(line.get(0).unwrap_or_default())?,
+                parse_tui(line.get(1).unwrap_or_default())?,
+                parse_tui(line.get(2).unwrap_or_default())?,
+            ));
+        }
+    } else if srstr.exists() {
+        let type_ids = type_defs
+            .iter()
+            .map(|(id, t)| (t.name.as_str(), *id))
+            .collect::<HashMap<_, _>>();
+        let relation_ids = relation_types
+            .iter()
+            .filter_map(|r| Some((r.name.as_str(), parse_tui(&r.rui).ok()?)))
+            .collect::<HashMap<_, _>>();
+
+        let mut reader = create_csv_reader(std::fs::File::open(srstr)?);
+        for line in reader.records() {
+            let line = line?;
+            // Only the defined relationships, and not the blocked ones
+            if line.get(3) != Some("D") {
+                continue;
+            }
+
+            let from = type_ids.get(line.get(0).unwrap_or_default());
+            let rel = relation_ids.get(line.get(1).unwrap_or_default());
+            let to = type_ids.get(line.get(2).unwrap_or_default());
+            if let (Some(from), Some(rel), Some(to)) = (from, rel, to) {
+                relations.push((*from, *rel, *to));
+            }
+        }
+    }
+
+    Ok(SemanticNetwork::new(relation_types, relations))
+}
+
 type SemanticTypeMap = HashMap<SmolStr, SmallVec<[u16; 4]>>;
 
 fn read_semantic_types_map(
@@ -358,15 +1477,13 @@ fn read_semantic_types_map(
     type_defs: &HashMap<u16, SemanticType>,
     include: &[SmolStr],
 ) -> Result<SemanticTypeMap> {
-    let mut mrsty = files.get_file_stream("MRSTY")?;
+    let mut mrsty = files.mrsty()?;
 
     let mut output: SemanticTypeMap = HashMap::new();
 
-    for record in mrsty.records() {
+    for record in mrsty.rows() {
         let record = record?;
-
-        let cui = record.get(0).unwrap_or_default();
-        let tui = parse_tui(record.get(1).unwrap_or_default())?;
+        let tui = parse_tui(&record.tui)?;
 
         if !include.is_empty() {
             let Some(semantic_type) = type_defs.get(&tui) else {
@@ -375,15 +1492,83 @@ fn read_semantic_types_map(
 
             let should_include = include
                 .iter()
-                .any(|i| semantic_type.tree_number.starts_with(i.as_str()));
+                .any(|i| semantic_type_matches(semantic_type, i));
 
             if !should_include {
                 continue;
             }
         }
 
-        output.entry(cui.into()).or_default().push(tui);
+        output.entry(record.cui).or_default().push(tui);
     }
 
     Ok(output)
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::*;
+    use crate::test_data::TestData;
+
+    /// Build an index from the release in `dir`, and return the contents of each output file.
+    fn build(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+        let output = tempfile::tempdir().unwrap();
+        let files = Files::new(dir).unwrap();
+        build_index(IndexBuilderOptions {
+            output_dir: output.path(),
+            files: &files,
+            case_insensitive: true,
+            languages: Vec::new(),
+            sources: Vec::new(),
+            semantic_types: Vec::new(),
+            closure: Some(RelationKinds::ALL),
+            definition_sources: Vec::new(),
+            attributes: vec!["NDC".into()],
+        })
+        .unwrap();
+
+        std::fs::read_dir(output.path())
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                let mut contents = std::fs::read(&path).unwrap();
+                // The semantic types are written in hash map order.
+                if name == SEMANTIC_TYPES_LST_NAME {
+                    let mut lines = contents.split(|&b| b == b'\n').collect::<Vec<_>>();
+                    lines.sort_unstable();
+                    contents = lines.join(&b'\n');
+                }
+                (name, contents)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_files() {
+        let data = TestData::new();
+        let whole = build(data.dir.path());
+
+        // Split the files that are read in parallel into small parts, so that rows and runs of
+        // carried over values break at many different points.
+        let meta = data.dir.path().join("META");
+        for name in ["MRCONSO", "MRREL", "MRSAT", "MRHIER"] {
+            let path = meta.join(format!("{name}.RRF.gz"));
+            let mut contents = Vec::new();
+            flate2::read::GzDecoder::new(std::fs::File::open(&path).unwrap())
+                .read_to_end(&mut contents)
+                .unwrap();
+            std::fs::remove_file(path).unwrap();
+
+            for (i, part) in contents.chunks(37).enumerate() {
+                let suffix = [b'a' + (i / 26) as u8, b'a' + (i % 26) as u8];
+                let suffix = std::str::from_utf8(&suffix).unwrap();
+                std::fs::write(meta.join(format!("{name}.RRF.{suffix}")), part).unwrap();
+            }
+        }
+
+        assert_eq!(build(data.dir.path()), whole);
+    }
+}
@@ -0,0 +1,127 @@
+use ahash::{HashSet, HashSetExt};
+use serde::Serialize;
+use smol_str::SmolStr;
+
+use super::Index;
+
+#[derive(Debug, Clone, Default)]
+pub struct CrosswalkOptions {
+    /// The sources to map codes into. If empty, codes from every source other than the source
+    /// of the input code are returned.
+    pub target_sources: Vec<SmolStr>,
+    /// Also return the codes of the descendants of the matching concepts, for when the target
+    /// sources have no code that is as general as the input code.
+    pub include_descendants: bool,
+}
+
+/// A code that a code was mapped to by [Index::crosswalk].
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrosswalkMatch {
+    /// The CUI of the concept that the input code belongs to.
+    pub cui: SmolStr,
+    pub target_source: SmolStr,
+    pub target_code: SmolStr,
+    /// The CUI of the concept that the target code belongs to. This is the same as `cui` unless
+    /// the target code came from a descendant concept.
+    pub target_cui: SmolStr,
+    /// If the target code came from a descendant of the concept rather than the concept itself.
+    pub descendant: bool,
+}
+
+impl Index {
+    /// Map a code from one source to the codes in other sources that share a concept with it.
+    /// Codes from the concept itself are returned before codes from its descendants, and each
+    /// target code is returned at most once.
+    pub fn crosswalk(
+        &self,
+        source: &str,
+        code: &str,
+        options: &CrosswalkOptions,
+    ) -> Vec<CrosswalkMatch> {
+        let wanted = |target: &str| {
+            if options.target_sources.is_empty() {
+                target != source
+            } else {
+                options.target_sources.iter().any(|s| s == target)
+            }
+        };
+
+        let mut seen = HashSet::new();
+        let mut output = Vec::new();
+        for concept_id in self.find_by_code(source, code) {
+            let concept = self.concept(concept_id);
+            let codes: Box<dyn Iterator<Item = (u32, _)>> = if options.include_descendants {
+                Box::new(
+                    self.downstream_codes(concept_id, &options.target_sources)
+                        .map(|(id, code)| (id as u32, code)),
+                )
+            } else {
+                Box::new(concept.codes().map(|code| (concept_id, code)))
+            };
+
+            for (target_id, target) in codes {
+                if !wanted(&target.source) || (target.source == source && target.code == code) {
+                    continue;
+                }
+
+                if !seen.insert((target.source.clone(), target.code.clone())) {
+                    continue;
+                }
+
+                output.push(CrosswalkMatch {
+                    cui: concept.cui().into(),
+                    target_source: target.source,
+                    target_code: target.code,
+                    target_cui: self.concept(target_id).cui().into(),
+                    descendant: target_id != concept_id,
+                });
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    fn codes(matches: &[CrosswalkMatch]) -> Vec<(&str, bool)> {
+        matches
+            .iter()
+            .map(|m| (m.target_code.as_str(), m.descendant))
+            .collect()
+    }
+
+    #[test]
+    fn crosswalk() {
+        let data = TestData::new();
+        let mut options = CrosswalkOptions {
+            target_sources: vec!["ICD10CM".into()],
+            include_descendants: false,
+        };
+
+        let matches = data.index.crosswalk("SNOMEDCT_US", "73211009", &options);
+        assert_eq!(codes(&matches), vec![("E08-E13", false)]);
+        assert_eq!(matches[0].cui, "C0011849");
+
+        options.include_descendants = true;
+        let matches = data.index.crosswalk("SNOMEDCT_US", "73211009", &options);
+        assert_eq!(codes(&matches), vec![("E08-E13", false), ("E11", true)]);
+        assert_eq!(matches[1].target_cui, "C0011860");
+
+        let matches = data
+            .index
+            .crosswalk("ICD10CM", "E11", &CrosswalkOptions::default());
+        assert_eq!(
+            codes(&matches),
+            vec![("D003920", false), ("44054006", false)]
+        );
+
+        assert!(data
+            .index
+            .crosswalk("SNOMEDCT_US", "1", &CrosswalkOptions::default())
+            .is_empty());
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::{hash_map::Entry, VecDeque},
+    str::FromStr,
+};
+
+use ahash::{HashMap, HashMapExt, HashSet, HashSetExt};
+use serde::{Deserialize, Serialize};
+
+use super::{store, Index};
+
+/// A set of kinds of hierarchical relationship between concepts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct RelationKinds(u32);
+
+impl RelationKinds {
+    pub const NONE: RelationKinds = RelationKinds(0);
+    /// PAR and CHD relationships, where a source places one concept directly under another in its
+    /// hierarchy.
+    pub const PARENT: RelationKinds = RelationKinds(1);
+    /// RB and RN relationships, where one concept is broader or narrower than another without
+    /// being its parent.
+    pub const BROADER: RelationKinds = RelationKinds(2);
+    pub const ALL: RelationKinds = RelationKinds(3);
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u32) -> RelationKinds {
+        RelationKinds(bits & Self::ALL.0)
+    }
+
+    /// Check if the two sets have any kinds in common.
+    pub fn intersects(self, other: RelationKinds) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl std::ops::BitOr for RelationKinds {
+    type Output = RelationKinds;
+
+    fn bitor(self, rhs: RelationKinds) -> RelationKinds {
+        RelationKinds(self.0 | rhs.0)
+    }
+}
+
+impl FromStr for RelationKinds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parent" => Ok(RelationKinds::PARENT),
+            "broader" => Ok(RelationKinds::BROADER),
+            "all" => Ok(RelationKinds::ALL),
+            _ => Err(format!(
+                "Unknown relationship kind {s}. Expected parent, broader, or all"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TraversalOptions {
+    /// Stop after this many steps from the starting concept.
+    pub max_depth: Option<u32>,
+    /// The kinds of relationship to follow.
+    pub kinds: RelationKinds,
+    /// Stop once this many concepts have been found. Only used by [Index::ancestors] and
+    /// [Index::descendants].
+    pub max_results: Option<usize>,
+}
+
+impl Default for TraversalOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            kinds: RelationKinds::ALL,
+            max_results: None,
+        }
+    }
+}
+
+/// A concept found by a graph traversal.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphNode {
+    pub concept_id: u32,
+    /// The number of steps from the starting concept, along the shortest path.
+    pub depth: u32,
+}
+
+/// Maps each concept found while walking up from a concept to its depth and the concept that
+/// it was reached from.
+type AncestorTree = HashMap<u32, (u32, Option<u32>)>;
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Up,
+    Down,
+}
+
+impl Index {
+    /// The parents of a concept, over the given kinds of relationship.
+    pub fn parents_of(&self, id: u32, kinds: RelationKinds) -> impl Iterator<Item = u32> + '_ {
+        self.edges(id, Direction::Up, kinds)
+    }
+
+    /// The children of a concept, over the given kinds of relationship.
+    pub fn children_of(&self, id: u32, kinds: RelationKinds) -> impl Iterator<Item = u32> + '_ {
+        self.edges(id, Direction::Down, kinds)
+    }
+
+    fn edges(
+        &self,
+        id: u32,
+        direction: Direction,
+        kinds: RelationKinds,
+    ) -> impl Iterator<Item = u32> + '_ {
+        let (ids, kind_table) = match direction {
+            Direction::Up => (self.concept(id).parents(), store::PARENT_KINDS),
+            Direction::Down => (self.concept(id).children(), store::CHILD_KINDS),
+        };
+
+        ids.iter()
+            .zip(self.store.list(kind_table, id))
+            .filter(move |(_, &k)| RelationKinds::from_bits(k).intersects(kinds))
+            .map(|(&id, _)| id)
+    }
+
+    /// Find all the ancestors of a concept, in order of increasing depth.
+    pub fn ancestors(&self, id: u32, options: &TraversalOptions) -> Vec<GraphNode> {
+        self.traverse(id, Direction::Up, options)
+    }
+
+    /// Find all the descendants of a concept, in order of increasing depth.
+    pub fn descendants(&self, id: u32, options: &TraversalOptions) -> Vec<GraphNode> {
+        self.traverse(id, Direction::Down, options)
+    }
+
+    /// A breadth-first traversal from a concept, not including the concept itself.
+    fn traverse(
+        &self,
+        id: u32,
+        direction: Direction,
+        options: &TraversalOptions,
+    ) -> Vec<GraphNode> {
+        let mut seen = HashSet::new();
+        seen.insert(id);
+        let mut queue = VecDeque::from([GraphNode {
+            concept_id: id,
+            depth: 0,
+        }]);
+
+        let mut output = Vec::new();
+        while let Some(node) = queue.pop_front() {
+            if options.max_depth.is_some_and(|max| node.depth >= max) {
+                continue;
+            }
+
+            for next in self.edges(node.concept_id, direction, options.kinds) {
+                if seen.insert(next) {
+                    let next = GraphNode {
+                        concept_id: next,
+                        depth: node.depth + 1,
+                    };
+                    output.push(next);
+                    if options.max_results.is_some_and(|max| output.len() >= max) {
+                        return output;
+                    }
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Check if concept `a` is `b` or one of its descendants.
+    ///
+    /// When the index was built with an ancestor closure over the same relationship kinds and
+    /// there is no depth limit, this is a binary search of `a`'s ancestors instead of a graph
+    /// traversal.
+    pub fn is_a(&self, a: u32, b: u32, options: &TraversalOptions) -> bool {
+        if a == b {
+            return true;
+        }
+
+        if options.max_depth.is_none() && self.meta.closure == Some(options.kinds) {
+            return self
+                .store
+                .list(store::ANCESTORS, a)
+                .binary_search(&b)
+                .is_ok();
+        }
+
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([(a, 0)]);
+        while let Some((id, depth)) = queue.pop_front() {
+            if options.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+
+            for parent in self.parents_of(id, options.kinds) {
+                if parent == b {
+                    return true;
+                }
+
+                if seen.insert(parent) {
+                    queue.push_back((parent, depth + 1));
+                }
+            }
+        }
+
+        false
+    }
+
+    /// The number of steps from a concept to the nearest root concept, which is one with no
+    /// parents. Returns `None` if no root is found within `max_depth` steps, which can also
+    /// happen if every path upward leads to a cycle.
+    pub fn depth(&self, id: u32, options: &TraversalOptions) -> Option<u32> {
+        if self.parents_of(id, options.kinds).next().is_none() {
+            return Some(0);
+        }
+
+        self.ancestors(id, options)
+            .into_iter()
+            .find(|node| {
+                self.parents_of(node.concept_id, options.kinds)
+                    .next()
+                    .is_none()
+            })
+            .map(|node| node.depth)
+    }
+
+    /// Walk up from a concept, returning each ancestor, and the concept itself, along with its
+    /// depth and the concept it was reached from.
+    fn ancestor_tree(&self, id: u32, options: &TraversalOptions) -> AncestorTree {
+        let mut tree = HashMap::new();
+        tree.insert(id, (0, None));
+        let mut queue = VecDeque::from([(id, 0)]);
+        while let Some((id, depth)) = queue.pop_front() {
+            if options.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+
+            for parent in self.parents_of(id, options.kinds) {
+                if let Entry::Vacant(entry) = tree.entry(parent) {
+                    entry.insert((depth + 1, Some(id)));
+                    queue.push_back((parent, depth + 1));
+                }
+            }
+        }
+
+        tree
+    }
+
+    /// The concepts in both trees, ordered by their total distance from the two starting
+    /// concepts.
+    fn common_ancestors(a: &AncestorTree, b: &AncestorTree) -> Vec<u32> {
+        let mut common = a
+            .iter()
+            .filter_map(|(id, (depth_a, _))| b.get(id).map(|(depth_b, _)| (depth_a + depth_b, *id)))
+            .collect::<Vec<_>>();
+        common.sort_unstable();
+        common.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Find the lowest common ancestors of two concepts: the ancestors shared by both concepts
+    /// that have no descendants which are also shared. Since the hierarchy is not a tree, there
+    /// may be more than one. If one concept is an ancestor of the other, it is the only result.
+    ///
+    /// The results are ordered by their total distance from `a` and `b`.
+    pub fn lowest_common_ancestors(&self, a: u32, b: u32, options: &TraversalOptions) -> Vec<u32> {
+        let tree_a = self.ancestor_tree(a, options);
+        let tree_b = self.ancestor_tree(b, options);
+        let shared = |id: u32| tree_a.contains_key(&id) && tree_b.contains_key(&id);
+
+        // Every ancestor of a shared ancestor is shared too, so it's enough to check the children.
+        Self::common_ancestors(&tree_a, &tree_b)
+            .into_iter()
+            .filter(|&id| !self.children_of(id, options.kinds).any(shared))
+            .collect()
+    }
+
+    /// Find the shortest path between two concepts that goes up from `a` to a common ancestor,
+    /// and then down to `b`. The returned path starts with `a` and ends with `b`. Returns `None`
+    /// if the concepts have no common ancestor within `max_depth` steps of each.
+    pub fn path_between(&self, a: u32, b: u32, options: &TraversalOptions) -> Option<Vec<u32>> {
+        let tree_a = self.ancestor_tree(a, options);
+        let tree_b = self.ancestor_tree(b, options);
+        let top = *Self::common_ancestors(&tree_a, &tree_b).first()?;
+
+        let chain = |tree: &AncestorTree| {
+            let mut chain = vec![top];
+            while let Some((_, Some(next))) = tree.get(chain.last().unwrap()) {
+                chain.push(*next);
+            }
+            chain
+        };
+
+        // Both chains start at the common ancestor, so reverse the first one to start from `a`.
+        let mut path = chain(&tree_a);
+        path.reverse();
+        path.extend(chain(&tree_b).into_iter().skip(1));
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    fn cuis(index: &Index, nodes: &[GraphNode]) -> Vec<(String, u32)> {
+        nodes
+            .iter()
+            .map(|n| (index.concept(n.concept_id).cui().to_string(), n.depth))
+            .collect()
+    }
+
+    #[test]
+    fn traversal() {
+        let data = TestData::new();
+        let index = &data.index;
+        let disease = index.find_cui("C0012634").unwrap();
+        let dm = index.find_cui("C0011849").unwrap();
+        let t2dm = index.find_cui("C0011860").unwrap();
+        let htn = index.find_cui("C0020538").unwrap();
+
+        let options = TraversalOptions::default();
+        assert_eq!(
+            cuis(index, &index.ancestors(t2dm, &options)),
+            vec![("C0011849".to_string(), 1), ("C0012634".to_string(), 2)]
+        );
+
+        let limited = TraversalOptions {
+            max_depth: Some(1),
+            ..options
+        };
+        assert_eq!(index.ancestors(t2dm, &limited).len(), 1);
+        assert_eq!(index.descendants(disease, &options).len(), 3);
+        assert_eq!(index.descendants(disease, &limited).len(), 2);
+        let first = TraversalOptions {
+            max_results: Some(1),
+            ..options
+        };
+        assert_eq!(
+            cuis(index, &index.ancestors(t2dm, &first)),
+            vec![("C0011849".to_string(), 1)]
+        );
+
+        assert!(index.is_a(t2dm, disease, &options));
+        assert!(index.is_a(t2dm, t2dm, &options));
+        assert!(!index.is_a(t2dm, disease, &limited));
+        assert!(!index.is_a(disease, t2dm, &options));
+        assert!(!index.is_a(htn, dm, &options));
+
+        let broader_only = TraversalOptions {
+            kinds: RelationKinds::BROADER,
+            ..options
+        };
+        assert!(!index.is_a(t2dm, disease, &broader_only));
+
+        assert_eq!(index.depth(disease, &options), Some(0));
+        assert_eq!(index.depth(t2dm, &options), Some(2));
+        assert_eq!(index.depth(t2dm, &limited), None);
+    }
+
+    #[test]
+    fn paths() {
+        let data = TestData::new();
+        let index = &data.index;
+        let id = |cui| index.find_cui(cui).unwrap();
+        let (disease, dm, t2dm, htn) = (
+            id("C0012634"),
+            id("C0011849"),
+            id("C0011860"),
+            id("C0020538"),
+        );
+        let metformin = id("C0025598");
+        let options = TraversalOptions::default();
+
+        assert_eq!(
+            index.path_between(t2dm, htn, &options),
+            Some(vec![t2dm, dm, disease, htn])
+        );
+        assert_eq!(
+            index.path_between(disease, t2dm, &options),
+            Some(vec![disease, dm, t2dm])
+        );
+        assert_eq!(index.path_between(t2dm, t2dm, &options), Some(vec![t2dm]));
+        assert_eq!(index.path_between(t2dm, metformin, &options), None);
+
+        assert_eq!(
+            index.lowest_common_ancestors(t2dm, htn, &options),
+            vec![disease]
+        );
+        assert_eq!(index.lowest_common_ancestors(t2dm, dm, &options), vec![dm]);
+        assert!(index
+            .lowest_common_ancestors(t2dm, metformin, &options)
+            .is_empty());
+    }
+
+    #[test]
+    fn closure() {
+        let data = TestData::with_closure(RelationKinds::ALL);
+        let index = &data.index;
+        assert_eq!(index.meta.closure, Some(RelationKinds::ALL));
+
+        let disease = index.find_cui("C0012634").unwrap();
+        let t2dm = index.find_cui("C0011860").unwrap();
+        let htn = index.find_cui("C0020538").unwrap();
+        let options = TraversalOptions::default();
+        assert!(index.is_a(t2dm, disease, &options));
+        assert!(!index.is_a(disease, t2dm, &options));
+        assert!(!index.is_a(t2dm, htn, &options));
+    }
+}
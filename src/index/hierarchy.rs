@@ -0,0 +1,133 @@
+use serde::Serialize;
+use smol_str::SmolStr;
+
+use super::{store, Index};
+
+/// An atom on a path through a source hierarchy.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HierarchyNode {
+    pub concept_id: u32,
+    pub aui: SmolStr,
+    pub code: SmolStr,
+    /// The atom's string, as the source names it.
+    pub name: SmolStr,
+}
+
+/// One position of a code in its source's hierarchy, from MRHIER. A code can appear in several
+/// places in a hierarchy, and each one is a separate path.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HierarchyPath {
+    pub source: SmolStr,
+    /// The relationship of the code to its parent, such as `isa`. This is often empty.
+    pub rela: SmolStr,
+    /// The source's own hierarchical code for this position, such as a MeSH tree number. This is
+    /// often empty.
+    pub hcd: SmolStr,
+    /// The atoms from the root of the hierarchy down to and including the code itself.
+    pub nodes: Vec<HierarchyNode>,
+    /// False if some of the atoms on the path are not in the index, such as when the index was
+    /// built from a subset of the release, and so are missing from `nodes`. The SRC atom at the
+    /// root of each source's hierarchy is often left out, and doesn't count.
+    pub complete: bool,
+}
+
+impl Index {
+    /// Get the paths from the root of a source's hierarchy to a code. This only uses the
+    /// hierarchy that the source itself defines, unlike the parents and children of a concept,
+    /// which combine the relationships from every source.
+    ///
+    /// Returns an empty list if the code is not in the index, or if the release has no MRHIER
+    /// data for it.
+    pub fn hierarchy(&self, source: &str, code: &str) -> Vec<HierarchyPath> {
+        let paths = self.store.u32s(store::Section::HierarchyPaths);
+
+        let mut result = Vec::new();
+        for concept_id in self.find_by_code(source, code) {
+            let contexts = self.store.list(store::HIERARCHIES, concept_id);
+            for context in contexts.chunks_exact(store::HIERARCHIES.stride) {
+                let atom = self.hierarchy_node(context[0]);
+                let atom_source = self
+                    .store
+                    .string(self.store.record(store::ATOMS, context[0])[3]);
+                if atom_source != source || atom.code != code {
+                    continue;
+                }
+
+                let offset = context[3] as usize;
+                let len = paths[offset] as usize;
+                let mut nodes = paths[offset + 1..offset + 1 + len]
+                    .iter()
+                    .map(|&a| self.hierarchy_node(a))
+                    .collect::<Vec<_>>();
+                nodes.push(atom);
+
+                result.push(HierarchyPath {
+                    source: source.into(),
+                    rela: self.store.string(context[1]).into(),
+                    hcd: self.store.string(context[2]).into(),
+                    nodes,
+                    complete: context[4] != 0,
+                });
+            }
+        }
+
+        result
+    }
+
+    fn hierarchy_node(&self, atom: u32) -> HierarchyNode {
+        let record = self.store.record(store::ATOMS, atom);
+        HierarchyNode {
+            concept_id: self.store.record_concept(store::ATOMS, atom),
+            aui: self.store.string(record[0]).into(),
+            code: self.store.string(record[4]).into(),
+            name: self.store.string(record[1]).into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    fn codes(path: &HierarchyPath) -> Vec<&str> {
+        path.nodes.iter().map(|n| n.code.as_str()).collect()
+    }
+
+    #[test]
+    fn hierarchy() {
+        let data = TestData::new();
+        let index = &data.index;
+
+        let paths = index.hierarchy("SNOMEDCT_US", "44054006");
+        // The other contexts use the carried-over PTR prefix from the row before them. The
+        // paths are sorted, so the one with the short PTR comes first.
+        assert_eq!(paths.len(), 3);
+        assert_eq!(codes(&paths[0]), ["64572001", "44054006"]);
+        assert_eq!(codes(&paths[1]), ["64572001", "73211009", "44054006"]);
+        assert_eq!(codes(&paths[2]), ["64572001", "38341003", "44054006"]);
+        assert_eq!(paths[1].rela, "isa");
+        assert_eq!(paths[1].nodes[2].name, "Diabetes mellitus type 2");
+        assert_eq!(
+            paths[1].nodes[0].concept_id,
+            index.find_cui("C0012634").unwrap()
+        );
+
+        // Only the SNOMED atoms, and not the ICD-10-CM ones, for the same concept.
+        assert!(index.hierarchy("ICD10CM", "E11").is_empty());
+
+        let mesh = index.hierarchy("MSH", "D004194");
+        assert_eq!(mesh.len(), 1);
+        assert_eq!(codes(&mesh[0]), ["D004194"]);
+        assert_eq!(mesh[0].hcd, "C");
+        assert!(mesh[0].complete);
+        assert!(paths.iter().all(|p| p.complete));
+
+        // A7777 is missing, so the path skips from Diseases to Diabetes mellitus.
+        let mesh = index.hierarchy("MSH", "D003920");
+        assert_eq!(codes(&mesh[0]), ["D004194", "D003920"]);
+        assert!(!mesh[0].complete);
+
+        assert!(index.hierarchy("SNOMEDCT_US", "000000").is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use ahash::{HashSet, HashSetExt};
+use serde::Serialize;
+use smol_str::SmolStr;
+
+use super::{store, Index};
+
+/// How a retired CUI changed, from the REL column of MRCUI.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CuiChangeKind {
+    /// The CUI was removed with no replacement (DEL)
+    Deleted,
+    /// The CUI was merged into another CUI (SY)
+    Merged,
+    /// The CUI was split, and the new CUI is broader than it (RB)
+    Broader,
+    /// The CUI was split, and the new CUI is narrower than it (RN)
+    Narrower,
+    /// The CUI was split, and the new CUI is related to it in some other way (RO)
+    Related,
+}
+
+impl CuiChangeKind {
+    pub(crate) fn from_rel(rel: &str) -> Option<CuiChangeKind> {
+        match rel {
+            "DEL" => Some(CuiChangeKind::Deleted),
+            "SY" => Some(CuiChangeKind::Merged),
+            "RB" => Some(CuiChangeKind::Broader),
+            "RN" => Some(CuiChangeKind::Narrower),
+            "RO" => Some(CuiChangeKind::Related),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u32(self) -> u32 {
+        match self {
+            CuiChangeKind::Deleted => 0,
+            CuiChangeKind::Merged => 1,
+            CuiChangeKind::Broader => 2,
+            CuiChangeKind::Narrower => 3,
+            CuiChangeKind::Related => 4,
+        }
+    }
+
+    fn from_u32(value: u32) -> CuiChangeKind {
+        match value {
+            1 => CuiChangeKind::Merged,
+            2 => CuiChangeKind::Broader,
+            3 => CuiChangeKind::Narrower,
+            4 => CuiChangeKind::Related,
+            _ => CuiChangeKind::Deleted,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CuiChangeKind::Deleted => "deleted",
+            CuiChangeKind::Merged => "merged",
+            CuiChangeKind::Broader => "broader",
+            CuiChangeKind::Narrower => "narrower",
+            CuiChangeKind::Related => "related",
+        }
+    }
+}
+
+/// A single step in the history of a retired CUI.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CuiChange {
+    pub from: SmolStr,
+    /// The CUI that replaced `from`, or `None` if it was deleted.
+    pub to: Option<SmolStr>,
+    /// The release where the change happened. This is empty for changes that only appear in
+    /// DELETEDCUI or MERGEDCUI.
+    pub version: SmolStr,
+    pub kind: CuiChangeKind,
+}
+
+/// Where a CUI ended up in the current release.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CuiResolution {
+    /// The CUI in this index, or `None` if the history ends with a deletion or with a CUI that
+    /// is not in the index.
+    pub cui: Option<SmolStr>,
+    /// The changes that lead from the requested CUI to `cui`. This is empty when the requested
+    /// CUI is still current.
+    pub changes: Vec<CuiChange>,
+}
+
+impl Index {
+    /// Find the current CUIs for a CUI from an older release, following the merges, splits and
+    /// deletions in its history. A split returns one resolution for each of the new CUIs.
+    ///
+    /// Returns an empty list if the CUI is neither in the index nor in the history.
+    pub fn resolve_cui(&self, cui: &str) -> Vec<CuiResolution> {
+        let cui = cui.trim().to_ascii_uppercase();
+        let mut seen = HashSet::new();
+        let mut results = Vec::new();
+        self.resolve_cui_from(&cui, Vec::new(), &mut seen, &mut results);
+        results
+    }
+
+    fn resolve_cui_from(
+        &self,
+        cui: &str,
+        changes: Vec<CuiChange>,
+        seen: &mut HashSet<SmolStr>,
+        results: &mut Vec<CuiResolution>,
+    ) {
+        // Splits and merges can lead to the same CUI more than once, and the history can
+        // contain cycles.
+        if !seen.insert(SmolStr::from(cui)) {
+            return;
+        }
+
+        if self.find_cui(cui).is_some() {
+            results.push(CuiResolution {
+                cui: Some(cui.into()),
+                changes,
+            });
+            return;
+        }
+
+        let entries = self.cui_history(cui);
+        if entries.is_empty() {
+            if !changes.is_empty() {
+                results.push(CuiResolution { cui: None, changes });
+            }
+            return;
+        }
+
+        for change in entries {
+            let to = change.to.clone();
+            let mut changes = changes.clone();
+            changes.push(change);
+            match to {
+                Some(to) => self.resolve_cui_from(&to, changes, seen, results),
+                None => results.push(CuiResolution { cui: None, changes }),
+            }
+        }
+    }
+
+    /// The recorded changes to a retired CUI.
+    fn cui_history(&self, cui: &str) -> Vec<CuiChange> {
+        let Some(offset) = self.cui_history.get(cui) else {
+            return Vec::new();
+        };
+
+        self.store
+            .counted_records(
+                store::Section::CuiHistory,
+                offset,
+                store::CUI_HISTORY_RECORD_LEN,
+            )
+            .chunks_exact(store::CUI_HISTORY_RECORD_LEN)
+            .map(|r| {
+                let to = self.store.string(r[0]);
+                CuiChange {
+                    from: cui.into(),
+                    to: (!to.is_empty()).then(|| to.into()),
+                    version: self.store.string(r[1]).into(),
+                    kind: CuiChangeKind::from_u32(r[2]),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    fn resolved(resolutions: &[CuiResolution]) -> Vec<Option<&str>> {
+        resolutions.iter().map(|r| r.cui.as_deref()).collect()
+    }
+
+    #[test]
+    fn resolve_cui() {
+        let data = TestData::new();
+        let index = &data.index;
+
+        let current = index.resolve_cui("C0011860");
+        assert_eq!(resolved(&current), [Some("C0011860")]);
+        assert!(current[0].changes.is_empty());
+
+        let merged = index.resolve_cui("c0000002");
+        assert_eq!(resolved(&merged), [Some("C0011860")]);
+        assert_eq!(merged[0].changes[0].kind, CuiChangeKind::Merged);
+        assert_eq!(merged[0].changes[0].version, "2019AB");
+
+        // Merged into a CUI that was merged again later.
+        let chain = index.resolve_cui("C0000004");
+        assert_eq!(resolved(&chain), [Some("C0011860")]);
+        assert_eq!(chain[0].changes.len(), 2);
+
+        let split = index.resolve_cui("C0000003");
+        assert_eq!(resolved(&split), [Some("C0011849"), Some("C0020538")]);
+        assert_eq!(split[1].changes[0].kind, CuiChangeKind::Narrower);
+
+        let deleted = index.resolve_cui("C0000001");
+        assert_eq!(resolved(&deleted), [None]);
+        assert_eq!(deleted[0].changes[0].kind, CuiChangeKind::Deleted);
+
+        // From the CHANGE directory, and not in MRCUI
+        assert_eq!(resolved(&index.resolve_cui("C0000005")), [Some("C0012634")]);
+        assert_eq!(resolved(&index.resolve_cui("C0000006")), [None]);
+
+        assert!(index.resolve_cui("C9999999").is_empty());
+    }
+}
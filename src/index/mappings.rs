@@ -0,0 +1,125 @@
+use serde::Serialize;
+use smol_str::SmolStr;
+
+use super::{code_key, store, Index};
+
+/// A mapping from a code to a code in another source, as asserted by a map set in MRMAP or
+/// MRSMAP.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub source: SmolStr,
+    pub code: SmolStr,
+    /// The source that the map set maps into. This is empty if MRSAT doesn't say.
+    pub target_source: SmolStr,
+    /// The code that this maps to. Some map sets include entries with no target code, where the
+    /// advice explains why the code can't be mapped.
+    pub target_code: SmolStr,
+    /// The CUI of the map set
+    pub map_set: SmolStr,
+    /// The map group. For maps that use several groups, a complete mapping uses one target
+    /// from each group. This is 0 when the map set doesn't use groups.
+    pub group: u32,
+    /// The order in which to try the rules within a group, starting from 1. This is 0 when the
+    /// map set doesn't give one.
+    pub priority: u32,
+    /// The rule for choosing this target, such as `TRUE` or a condition on the patient's age.
+    pub rule: SmolStr,
+    /// Human-readable advice about the mapping
+    pub advice: SmolStr,
+    pub rel: SmolStr,
+    pub rela: SmolStr,
+    pub map_type: SmolStr,
+}
+
+impl Index {
+    /// Get the source-asserted mappings from a code, sorted by group and priority. If
+    /// `target_source` is given, only mappings into that source are returned.
+    pub fn mappings(&self, source: &str, code: &str, target_source: Option<&str>) -> Vec<Mapping> {
+        let Some(offset) = self.mappings.get(code_key(source, code)) else {
+            return Vec::new();
+        };
+
+        let store = &self.store;
+        store
+            .counted_records(store::Section::Mappings, offset, store::MAPPING_RECORD_LEN)
+            .chunks_exact(store::MAPPING_RECORD_LEN)
+            .filter(|r| target_source.is_none_or(|target| store.string(r[1]) == target))
+            .map(|r| Mapping {
+                source: source.into(),
+                code: code.into(),
+                target_code: store.string(r[0]).into(),
+                target_source: store.string(r[1]).into(),
+                map_set: store.string(r[2]).into(),
+                group: r[3],
+                priority: r[4],
+                rule: store.string(r[5]).into(),
+                advice: store.string(r[6]).into(),
+                rel: store.string(r[7]).into(),
+                rela: store.string(r[8]).into(),
+                map_type: store.string(r[9]).into(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_data::TestData;
+
+    #[test]
+    fn mappings() {
+        let data = TestData::new();
+        let index = &data.index;
+
+        let htn = index.mappings("SNOMEDCT_US", "38341003", Some("ICD10CM"));
+        let targets = htn
+            .iter()
+            .map(|m| (m.group, m.priority, m.target_code.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(targets, [(1, 1, "I15.9"), (1, 2, "I10")]);
+        assert_eq!(htn[1].rule, "OTHERWISE TRUE");
+        assert_eq!(htn[1].advice, "ALWAYS I10");
+        assert_eq!(htn[0].map_set, "C2919943");
+
+        assert!(index
+            .mappings("SNOMEDCT_US", "38341003", Some("ICD9CM"))
+            .is_empty());
+        assert_eq!(index.mappings("SNOMEDCT_US", "38341003", None).len(), 2);
+
+        // This map set is only in MRSMAP, and has no MRSAT attributes, so its target source is
+        // unknown.
+        let simple = index.mappings("MSH", "D003924", None);
+        assert_eq!(simple.len(), 1);
+        assert_eq!(simple[0].target_code, "E11");
+        assert_eq!(simple[0].target_source, "");
+        assert_eq!(simple[0].group, 0);
+
+        // Only source concept IDs that are also codes are kept.
+        assert!(index.mappings("MSH", "M0006138", None).is_empty());
+    }
+
+    #[test]
+    fn mappings_with_sources() {
+        let data = TestData::build(|options| {
+            options.sources = vec!["SNOMEDCT_US".into(), "MSH".into()];
+        });
+        // ICD-10-CM isn't included, so neither are the SNOMED CT mappings to it.
+        assert!(data
+            .index
+            .mappings("SNOMEDCT_US", "38341003", None)
+            .is_empty());
+        // The target source of this map set is unknown, so it is kept.
+        let simple = data.index.mappings("MSH", "D003924", None);
+        assert_eq!(simple.len(), 1);
+        assert_eq!(simple[0].target_code, "E11");
+
+        let data = TestData::build(|options| {
+            options.sources = vec!["SNOMEDCT_US".into(), "ICD10CM".into()];
+        });
+        assert_eq!(
+            data.index.mappings("SNOMEDCT_US", "38341003", None).len(),
+            2
+        );
+        assert!(data.index.mappings("MSH", "D003924", None).is_empty());
+    }
+}
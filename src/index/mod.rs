@@ -1,19 +1,43 @@
-use ahash::{HashMap, HashMapExt};
+use ahash::{HashMap, HashMapExt, HashSet};
 use eyre::{eyre, Result};
-use flate2::read::GzDecoder;
 use fst::{IntoStreamer, Streamer};
+use itertools::Either;
+use memmap2::Mmap;
 use regex_automata::dense;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use smol_str::SmolStr;
-use std::{
-    borrow::Cow,
-    io::{BufRead, Read},
-    path::Path,
-};
+use std::{borrow::Cow, io::BufRead, path::Path};
+
+use self::store::Store;
 
 pub mod build;
+mod crosswalk;
+mod graph;
+mod hierarchy;
+mod history;
+mod mappings;
+mod relations;
 pub mod score;
+mod search;
+mod semantic_network;
+mod similarity;
+mod store;
+
+pub use crosswalk::{CrosswalkMatch, CrosswalkOptions};
+pub use graph::{GraphNode, RelationKinds, TraversalOptions};
+pub use hierarchy::{HierarchyNode, HierarchyPath};
+pub use history::{CuiChange, CuiChangeKind, CuiResolution};
+pub use mappings::Mapping;
+pub use relations::{Relation, RelationFilter};
+pub use search::{MatchKind, RegexLimits, RegexSearchError, SearchHit, SearchOptions};
+pub use semantic_network::{
+    find_semantic_group, semantic_group, SemanticGroup, SemanticNetwork, SemanticRelationType,
+    SEMANTIC_GROUPS,
+};
+pub use similarity::{Similarity, SimilarityMeasure};
+
+pub(crate) use semantic_network::semantic_type_matches;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SearchIndexMeta {
@@ -21,6 +45,16 @@ pub struct SearchIndexMeta {
     pub languages: Vec<SmolStr>,
     pub sources: Vec<SmolStr>,
     pub semantic_types: Vec<SmolStr>,
+    /// The relationship kinds that the ancestor closure was built over, if it was built.
+    #[serde(default)]
+    pub closure: Option<RelationKinds>,
+    /// The sources that definitions were kept from, in order of preference. If empty,
+    /// definitions were kept from every included source.
+    #[serde(default)]
+    pub definition_sources: Vec<SmolStr>,
+    /// The names (ATN values) of the MRSAT attributes in the index.
+    #[serde(default)]
+    pub attributes: Vec<SmolStr>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -29,6 +63,25 @@ pub struct ConceptCode {
     pub code: SmolStr,
 }
 
+/// A definition of a concept, from MRDEF.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub source: SmolStr,
+    pub text: SmolStr,
+}
+
+/// An attribute of a concept, or of one of its atoms, from MRSAT.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Attribute {
+    /// The attribute name (ATN), such as `NDC`
+    pub name: SmolStr,
+    /// The attribute value (ATV)
+    pub value: SmolStr,
+    pub source: SmolStr,
+    /// The source code that the attribute belongs to, or an empty string if it has none.
+    pub code: SmolStr,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Concept {
     pub cui: SmolStr,
@@ -37,6 +90,12 @@ pub struct Concept {
     pub codes: SmallVec<[ConceptCode; 4]>,
     pub parents: SmallVec<[u32; 4]>,
     pub children: SmallVec<[u32; 4]>,
+    /// The [RelationKinds] bits for each entry in `parents`.
+    #[serde(default, skip_serializing_if = "SmallVec::is_empty")]
+    pub parent_kinds: SmallVec<[u32; 4]>,
+    /// The [RelationKinds] bits for each entry in `children`.
+    #[serde(default, skip_serializing_if = "SmallVec::is_empty")]
+    pub child_kinds: SmallVec<[u32; 4]>,
     #[serde(rename = "rl", default, skip_serializing_if = "SmallVec::is_empty")]
     pub similar: SmallVec<[u32; 4]>,
     #[serde(rename = "sy", default, skip_serializing_if = "SmallVec::is_empty")]
@@ -51,6 +110,68 @@ pub struct Concept {
     pub qualified_by: SmallVec<[u32; 4]>,
 }
 
+/// The suppression status of an atom, from the SUPPRESS column of MRCONSO.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Suppress {
+    /// Not suppressible
+    #[serde(rename = "N")]
+    No,
+    /// Obsolete content, suppressed by the source
+    #[serde(rename = "O")]
+    Obsolete,
+    /// Suppressed by the UMLS editors
+    #[serde(rename = "E")]
+    Editor,
+    /// Suppressed by the source, without being obsolete
+    #[serde(rename = "Y")]
+    Source,
+}
+
+impl Suppress {
+    fn from_rrf(value: &str) -> Suppress {
+        match value {
+            "O" => Suppress::Obsolete,
+            "E" => Suppress::Editor,
+            "Y" => Suppress::Source,
+            _ => Suppress::No,
+        }
+    }
+
+    fn from_u32(value: u32) -> Suppress {
+        match value {
+            1 => Suppress::Obsolete,
+            2 => Suppress::Editor,
+            3 => Suppress::Source,
+            _ => Suppress::No,
+        }
+    }
+
+    fn as_u32(self) -> u32 {
+        match self {
+            Suppress::No => 0,
+            Suppress::Obsolete => 1,
+            Suppress::Editor => 2,
+            Suppress::Source => 3,
+        }
+    }
+}
+
+/// A single name for a concept from one source, from a row of MRCONSO.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Atom {
+    /// The ID of the concept that this atom belongs to.
+    pub concept: u32,
+    pub aui: SmolStr,
+    pub string: SmolStr,
+    pub tty: SmolStr,
+    pub source: SmolStr,
+    /// The source's code for the atom, or an empty string if it has none.
+    pub code: SmolStr,
+    /// If this is the preferred atom for its string within the concept (the ISPREF column)
+    pub preferred: bool,
+    pub suppress: Suppress,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SemanticType {
     pub tui: SmolStr,
@@ -59,17 +180,220 @@ pub struct SemanticType {
     pub description: String,
 }
 
+/// A view of a concept stored in the index. The data is read directly from the index file as it
+/// is accessed.
+#[derive(Clone, Copy)]
+pub struct ConceptRef<'a> {
+    store: &'a Store,
+    id: u32,
+}
+
+impl<'a> ConceptRef<'a> {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn cui(&self) -> &'a str {
+        self.store.string(self.store.concept_record(self.id)[0])
+    }
+
+    pub fn preferred_name(&self) -> &'a str {
+        self.store.string(self.store.concept_record(self.id)[1])
+    }
+
+    pub fn types(&self) -> impl Iterator<Item = u16> + 'a {
+        self.store
+            .list(store::TYPES, self.id)
+            .iter()
+            .map(|&t| t as u16)
+    }
+
+    pub fn codes(&self) -> impl Iterator<Item = ConceptCode> + 'a {
+        let store = self.store;
+        store
+            .list(store::CODES, self.id)
+            .chunks_exact(store::CODES.stride)
+            .map(|c| ConceptCode {
+                source: store.string(c[0]).into(),
+                code: store.string(c[1]).into(),
+            })
+    }
+
+    pub fn has_codes(&self) -> bool {
+        !self.store.list(store::CODES, self.id).is_empty()
+    }
+
+    /// All the atoms (names from each source) for the concept.
+    pub fn atoms(&self) -> impl Iterator<Item = Atom> + 'a {
+        let store = self.store;
+        let concept = self.id;
+        store
+            .list(store::ATOMS, self.id)
+            .chunks_exact(store::ATOMS.stride)
+            .map(move |a| Atom {
+                concept,
+                aui: store.string(a[0]).into(),
+                string: store.string(a[1]).into(),
+                tty: store.string(a[2]).into(),
+                source: store.string(a[3]).into(),
+                code: store.string(a[4]).into(),
+                preferred: a[5] & store::ATOM_PREFERRED_FLAG != 0,
+                suppress: Suppress::from_u32(a[5] >> store::ATOM_SUPPRESS_SHIFT),
+            })
+    }
+
+    /// The sources of the concept's atoms. A source will appear once for each of its atoms.
+    pub fn sources(&self) -> impl Iterator<Item = &'a str> + 'a {
+        let store = self.store;
+        store
+            .list(store::ATOMS, self.id)
+            .chunks_exact(store::ATOMS.stride)
+            .map(|a| store.string(a[3]))
+    }
+
+    pub fn parents(&self) -> &'a [u32] {
+        self.store.list(store::PARENTS, self.id)
+    }
+
+    pub fn children(&self) -> &'a [u32] {
+        self.store.list(store::CHILDREN, self.id)
+    }
+
+    pub fn similar(&self) -> &'a [u32] {
+        self.store.list(store::SIMILAR, self.id)
+    }
+
+    pub fn synonym(&self) -> &'a [u32] {
+        self.store.list(store::SYNONYM, self.id)
+    }
+
+    pub fn other_relationship(&self) -> &'a [u32] {
+        self.store.list(store::OTHER_RELATIONSHIP, self.id)
+    }
+
+    pub fn related_possibly_synonymous(&self) -> &'a [u32] {
+        self.store.list(store::RELATED_POSSIBLY_SYNONYMOUS, self.id)
+    }
+
+    pub fn allowed_qualifier(&self) -> &'a [u32] {
+        self.store.list(store::ALLOWED_QUALIFIER, self.id)
+    }
+
+    pub fn qualified_by(&self) -> &'a [u32] {
+        self.store.list(store::QUALIFIED_BY, self.id)
+    }
+
+    /// The definitions of the concept. When the index was built with preferred definition
+    /// sources, these are in order of preference.
+    pub fn definitions(&self) -> impl Iterator<Item = Definition> + 'a {
+        let store = self.store;
+        store
+            .list(store::DEFINITIONS, self.id)
+            .chunks_exact(store::DEFINITIONS.stride)
+            .map(|d| Definition {
+                source: store.string(d[0]).into(),
+                text: store.string(d[1]).into(),
+            })
+    }
+
+    /// The attributes of the concept that were included in the index.
+    pub fn attributes(&self) -> impl Iterator<Item = Attribute> + 'a {
+        let store = self.store;
+        store
+            .list(store::ATTRIBUTES, self.id)
+            .chunks_exact(store::ATTRIBUTES.stride)
+            .map(|a| Attribute {
+                name: store.string(a[0]).into(),
+                value: store.string(a[1]).into(),
+                source: store.string(a[2]).into(),
+                code: store.string(a[3]).into(),
+            })
+    }
+
+    /// Copy the concept out of the index.
+    pub fn to_concept(&self) -> Concept {
+        Concept {
+            cui: self.cui().into(),
+            preferred_name: self.preferred_name().into(),
+            types: self.types().collect(),
+            codes: self.codes().collect(),
+            parents: self.parents().into(),
+            children: self.children().into(),
+            parent_kinds: self.store.list(store::PARENT_KINDS, self.id).into(),
+            child_kinds: self.store.list(store::CHILD_KINDS, self.id).into(),
+            similar: self.similar().into(),
+            synonym: self.synonym().into(),
+            other_relationship: self.other_relationship().into(),
+            related_possibly_synonymous: self.related_possibly_synonymous().into(),
+            allowed_qualifier: self.allowed_qualifier().into(),
+            qualified_by: self.qualified_by().into(),
+        }
+    }
+}
+
+impl std::fmt::Debug for ConceptRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConceptRef")
+            .field("id", &self.id)
+            .field("cui", &self.cui())
+            .field("preferred_name", &self.preferred_name())
+            .finish()
+    }
+}
+
 pub struct Index {
     pub meta: SearchIndexMeta,
-    pub concepts: Vec<Concept>,
     pub semantic_types: HashMap<u16, SemanticType>,
-    index: fst::Map<Vec<u8>>,
+    pub semantic_network: SemanticNetwork,
+    index: fst::Map<Mmap>,
+    /// Maps `SAB|CODE` keys to the concepts with that code.
+    codes: fst::Map<Mmap>,
+    /// Maps `ATN|ATV` keys to the concepts with that attribute value.
+    attributes: fst::Map<Mmap>,
+    /// Maps retired CUIs to their history in the concept store.
+    cui_history: fst::Map<Mmap>,
+    /// Maps `SAB|CODE` keys to the source-asserted mappings from that code.
+    mappings: fst::Map<Mmap>,
+    store: Store,
 }
 
 const METADATA_NAME: &str = "umls_search.metadata.json";
 const STRINGS_FST_NAME: &str = "umls_search.strings.fst";
-const CONCEPTS_LST_NAME: &str = "umls_search.concepts.ndjson.gz";
+const CODES_FST_NAME: &str = "umls_search.codes.fst";
+const ATTRIBUTES_FST_NAME: &str = "umls_search.attributes.fst";
+const CUI_HISTORY_FST_NAME: &str = "umls_search.cui_history.fst";
+const MAPPINGS_FST_NAME: &str = "umls_search.mappings.fst";
+const CONCEPTS_STORE_NAME: &str = "umls_search.concepts.bin";
 const SEMANTIC_TYPES_LST_NAME: &str = "umls_search.semantic_types.ndjson";
+const SEMANTIC_NETWORK_NAME: &str = "umls_search.semantic_network.json";
+
+/// The key for a code in the codes FST.
+fn code_key(source: &str, code: &str) -> String {
+    format!("{source}|{code}")
+}
+
+/// The key for an attribute value in the attributes FST.
+fn attribute_key(name: &str, value: &str) -> String {
+    format!("{name}|{value}")
+}
+
+/// When this bit is set on a value in the strings FST, the rest of the value is an offset into the
+/// postings section of the concept store instead of a concept ID. The postings list at that
+/// offset starts with the number of concepts, followed by the concept IDs.
+const MULTIPLE_CONCEPTS_FLAG: u64 = 1 << 63;
+
+fn open_fst(path: &Path) -> Result<fst::Map<Mmap>> {
+    let file = std::fs::File::open(path).map_err(|e| {
+        eyre!(
+            "Failed to open {}: {e}. Please rebuild the index.",
+            path.display()
+        )
+    })?;
+    // SAFETY: The index files are written once by the index builder and are not expected to
+    // be modified while they are in use.
+    let contents = unsafe { Mmap::map(&file)? };
+    Ok(fst::Map::new(contents)?)
+}
 
 impl Index {
     pub fn new(base_dir: &Path) -> Result<Index> {
@@ -77,18 +401,16 @@ impl Index {
         let meta_file = std::fs::File::open(meta_path)?;
         let meta = serde_json::from_reader(meta_file)?;
 
-        let strings_fst_path = base_dir.join(STRINGS_FST_NAME);
-        let mut strings = std::fs::File::open(strings_fst_path)?;
-        let mut fst_contents = Vec::new();
-        strings.read_to_end(&mut fst_contents)?;
-
-        let index = fst::Map::new(fst_contents)?;
-
         Ok(Self {
             meta,
-            index,
-            concepts: Self::load_concepts(base_dir)?,
+            index: open_fst(&base_dir.join(STRINGS_FST_NAME))?,
+            codes: open_fst(&base_dir.join(CODES_FST_NAME))?,
+            attributes: open_fst(&base_dir.join(ATTRIBUTES_FST_NAME))?,
+            cui_history: open_fst(&base_dir.join(CUI_HISTORY_FST_NAME))?,
+            mappings: open_fst(&base_dir.join(MAPPINGS_FST_NAME))?,
+            store: Store::open(&base_dir.join(CONCEPTS_STORE_NAME))?,
             semantic_types: Self::load_semantic_types(base_dir)?,
+            semantic_network: Self::load_semantic_network(base_dir)?,
         })
     }
 
@@ -110,47 +432,138 @@ impl Index {
         Ok(output)
     }
 
-    /// Read the concepts list from disk.
-    pub fn load_concepts(base_dir: &Path) -> Result<Vec<Concept>> {
-        let concepts_lst_path = base_dir.join(CONCEPTS_LST_NAME);
+    /// Read the semantic network relationships from disk.
+    pub fn load_semantic_network(base_dir: &Path) -> Result<SemanticNetwork> {
+        let network_file = std::fs::File::open(base_dir.join(SEMANTIC_NETWORK_NAME))?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(
+            network_file,
+        ))?)
+    }
+
+    /// Get the concept IDs for a value from the strings FST, such as those returned by
+    /// [Index::fuzzy_search].
+    pub fn concept_ids(&self, value: u64) -> impl Iterator<Item = u64> + '_ {
+        if value & MULTIPLE_CONCEPTS_FLAG == 0 {
+            return Either::Left(std::iter::once(value));
+        }
 
-        let concepts_file = std::fs::File::open(concepts_lst_path)?;
-        let concepts_reader = std::io::BufReader::new(GzDecoder::new(concepts_file));
-        let concepts = concepts_reader
-            .lines()
-            .map(|line| Ok::<Concept, eyre::Report>(serde_json::from_str(&line?)?))
-            .collect::<Result<Vec<_>, _>>()?;
+        let postings = self.store.u32s(store::Section::Postings);
+        let offset = (value & !MULTIPLE_CONCEPTS_FLAG) as usize;
+        let len = postings[offset] as usize;
+        Either::Right(
+            postings[offset + 1..offset + 1 + len]
+                .iter()
+                .map(|&id| id as u64),
+        )
+    }
 
-        Ok(concepts)
+    /// The raw FST mapping strings to concepts, for callers that need to walk it directly.
+    pub(crate) fn strings_fst(&self) -> &fst::raw::Fst<Mmap> {
+        self.index.as_fst()
     }
 
-    /// Get the string associated with an ID returned from the search function.
-    pub fn concept_id(&self, id: u64) -> &Concept {
-        &self.concepts[id as usize]
+    /// The number of concepts in the index.
+    pub fn num_concepts(&self) -> usize {
+        self.store.num_concepts()
+    }
+
+    /// Get a concept by its ID.
+    pub fn concept(&self, id: u32) -> ConceptRef<'_> {
+        assert!(
+            (id as usize) < self.num_concepts(),
+            "Invalid concept ID {id}"
+        );
+        ConceptRef {
+            store: &self.store,
+            id,
+        }
+    }
+
+    /// Iterate over all the concepts in the index.
+    pub fn concepts(&self) -> impl Iterator<Item = ConceptRef<'_>> {
+        (0..self.num_concepts() as u32).map(|id| self.concept(id))
+    }
+
+    /// Get the concept associated with an ID returned from the search function.
+    pub fn concept_id(&self, id: u64) -> ConceptRef<'_> {
+        self.concept(id as u32)
     }
 
     /// Find a word in a case-insensitive fashion. For indexes built in case-insensitive mode,
     /// this does a simple get. Otherwise it builds an automata that searches the index in a
     /// case-insensitive fashion.
-    pub fn search(&self, word: &str) -> Result<Option<u64>> {
+    pub fn search(&self, word: &str) -> Result<Vec<u64>> {
         if self.meta.case_insensitive {
             let word = word.to_lowercase();
             Ok(self.search_exact(&word))
         } else {
-            let pattern = format!("(?i){}", word);
+            let pattern = format!("(?i){}", search::escape_regex(word));
             self.search_regex(&pattern)
         }
     }
 
-    /// Find an exact match for the given word.
-    pub fn search_exact(&self, word: &str) -> Option<u64> {
-        self.index.get(word.as_bytes())
+    /// Find an exact match for the given word, returning every concept that it maps to.
+    pub fn search_exact(&self, word: &str) -> Vec<u64> {
+        self.index
+            .get(word.as_bytes())
+            .map(|value| self.concept_ids(value).collect())
+            .unwrap_or_default()
+    }
+
+    /// Find the concepts that have the given code from a source.
+    pub fn find_by_code(&self, source: &str, code: &str) -> Vec<u32> {
+        self.codes
+            .get(code_key(source, code))
+            .map(|value| self.concept_ids(value).map(|id| id as u32).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the values of an attribute for a concept, such as the `NDC` codes of an RxNorm drug.
+    /// Only the attributes chosen when building the index are available.
+    pub fn attributes(&self, concept: u32, name: &str) -> Vec<Attribute> {
+        self.concept(concept)
+            .attributes()
+            .filter(|a| a.name == name)
+            .collect()
+    }
+
+    /// Find the concepts that have an attribute value, such as the concept for an NDC.
+    pub fn find_by_attribute(&self, name: &str, value: &str) -> Vec<u32> {
+        self.attributes
+            .get(attribute_key(name, value))
+            .map(|value| self.concept_ids(value).map(|id| id as u32).collect())
+            .unwrap_or_default()
+    }
+
+    /// Find a concept by its CUI.
+    pub fn find_cui(&self, cui: &str) -> Option<u32> {
+        let key = if self.meta.case_insensitive {
+            Cow::Owned(cui.to_lowercase())
+        } else {
+            Cow::Borrowed(cui)
+        };
+
+        self.search_exact(&key)
+            .into_iter()
+            .map(|id| id as u32)
+            .find(|&id| self.concept(id).cui().eq_ignore_ascii_case(cui))
     }
 
-    /// Search for a word using a regex pattern.
-    pub fn search_regex(&self, word: &str) -> Result<Option<u64>> {
+    /// Search for a word using a regex pattern, returning the concepts for all matching strings.
+    pub fn search_regex(&self, word: &str) -> Result<Vec<u64>> {
         let dfa = dense::Builder::new().anchored(true).build(word)?;
-        let result = self.index.search(&dfa).into_stream().next().map(|i| i.1);
+        let mut stream = self.index.search(&dfa).into_stream();
+
+        let mut seen = HashSet::default();
+        let mut result = Vec::new();
+        while let Some((_, value)) = stream.next() {
+            for id in self.concept_ids(value) {
+                if seen.insert(id) {
+                    result.push(id);
+                }
+            }
+        }
+
         Ok(result)
     }
 
@@ -173,41 +586,41 @@ impl Index {
         &'a self,
         start_concept_id: u32,
         code_types: &'a [impl AsRef<str> + PartialEq],
-    ) -> impl Iterator<Item = (usize, &'a ConceptCode)> {
-        ConceptCodeIterator::new(&self.concepts, code_types, start_concept_id)
+    ) -> impl Iterator<Item = (usize, ConceptCode)> + 'a {
+        ConceptCodeIterator::new(self, code_types, start_concept_id)
     }
 }
 
 pub struct ConceptCodeIterator<'a, CODETYPE: AsRef<str>> {
-    all_concepts: &'a [Concept],
+    index: &'a Index,
     code_sources: &'a [CODETYPE],
     concept_queue: Vec<u32>,
-    seen_concepts: Vec<u32>,
+    seen_concepts: HashSet<u32>,
     current_concept: usize,
     current_concept_code: usize,
 }
 
 impl<'a, CODETYPE: AsRef<str>> ConceptCodeIterator<'a, CODETYPE> {
     fn new(
-        concepts: &'a [Concept],
+        index: &'a Index,
         code_sources: &'a [CODETYPE],
         start: u32,
     ) -> ConceptCodeIterator<'a, CODETYPE> {
         ConceptCodeIterator {
-            all_concepts: concepts,
+            index,
             concept_queue: Vec::new(),
             code_sources,
             current_concept: start as usize,
             current_concept_code: 0,
-            seen_concepts: vec![start],
+            seen_concepts: HashSet::from_iter([start]),
         }
     }
 
-    fn find_next_code(&mut self) -> Option<&'a ConceptCode> {
-        let current_concept = &self.all_concepts[self.current_concept];
+    fn find_next_code(&mut self) -> Option<ConceptCode> {
+        let current_concept = self.index.concept(self.current_concept as u32);
+        let mut codes = current_concept.codes().skip(self.current_concept_code);
 
-        while self.current_concept_code < current_concept.codes.len() {
-            let code = &current_concept.codes[self.current_concept_code];
+        for code in codes.by_ref() {
             self.current_concept_code += 1;
 
             if self.code_sources.is_empty()
@@ -222,7 +635,7 @@ impl<'a, CODETYPE: AsRef<str>> ConceptCodeIterator<'a, CODETYPE> {
 }
 
 impl<'a, CODETYPE: AsRef<str>> Iterator for ConceptCodeIterator<'a, CODETYPE> {
-    type Item = (usize, &'a ConceptCode);
+    type Item = (usize, ConceptCode);
 
     fn next(&mut self) -> Option<Self::Item> {
         // First see if we have any codes left in the current concept
@@ -234,11 +647,10 @@ impl<'a, CODETYPE: AsRef<str>> Iterator for ConceptCodeIterator<'a, CODETYPE> {
         self.current_concept_code = 0;
 
         // Queue up all the children
-        let current_concept = &self.all_concepts[self.current_concept];
-        for child in &current_concept.children {
-            if !self.seen_concepts.contains(child) {
+        let current_concept = self.index.concept(self.current_concept as u32);
+        for child in current_concept.children() {
+            if self.seen_concepts.insert(*child) {
                 self.concept_queue.push(*child);
-                self.seen_concepts.push(*child);
             }
         }
 
@@ -253,10 +665,132 @@ impl<'a, CODETYPE: AsRef<str>> Iterator for ConceptCodeIterator<'a, CODETYPE> {
 }
 
 /// Parse a TUI into just the number part.
-fn parse_tui(tui: &str) -> Result<u16> {
+pub(crate) fn parse_tui(tui: &str) -> Result<u16> {
     if tui.chars().next().unwrap_or_default() != 'T' {
         return Err(eyre!("Invalid TUI: {}", tui));
     }
 
     tui[1..].parse().map_err(eyre::Report::from)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    #[test]
+    fn shared_strings() {
+        let data = TestData::new();
+        let index = &data.index;
+        let dm = index.find_cui("C0011849").unwrap() as u64;
+        let t2dm = index.find_cui("C0011860").unwrap() as u64;
+
+        // "DM" is a synonym of both concepts, so it uses a postings list.
+        let value = index.index.get("dm").unwrap();
+        assert_ne!(value & MULTIPLE_CONCEPTS_FLAG, 0);
+        assert_eq!(index.concept_ids(value).collect::<Vec<_>>(), [dm, t2dm]);
+        assert_eq!(index.search_exact("dm"), [dm, t2dm]);
+        assert_eq!(index.search("DM").unwrap(), [dm, t2dm]);
+
+        // A string with one concept stores the concept ID directly.
+        let value = index.index.get("type 2 diabetes").unwrap();
+        assert_eq!(value & MULTIPLE_CONCEPTS_FLAG, 0);
+        assert_eq!(index.concept_ids(value).collect::<Vec<_>>(), [t2dm]);
+
+        // Each concept is only returned once, even when several strings match.
+        let mut matches = index.search_regex("d.*").unwrap();
+        let len = matches.len();
+        matches.sort_unstable();
+        matches.dedup();
+        assert_eq!(matches.len(), len);
+        assert!(matches.contains(&dm) && matches.contains(&t2dm));
+    }
+
+    #[test]
+    fn find_by_code() {
+        let data = TestData::new();
+        let index = &data.index;
+        let dm = index.find_cui("C0011849").unwrap();
+        let t2dm = index.find_cui("C0011860").unwrap();
+
+        assert_eq!(index.find_by_code("SNOMEDCT_US", "44054006"), [t2dm]);
+        assert_eq!(index.find_by_code("ICD10CM", "E08-E13"), [dm]);
+        // The code must be from the given source.
+        assert!(index.find_by_code("ICD10CM", "44054006").is_empty());
+        assert!(index.find_by_code("SNOMEDCT_US", "1").is_empty());
+        assert!(index.find_by_code("NOSUCHSOURCE", "E11").is_empty());
+
+        // The MeSH heading for diabetes has atoms in both concepts.
+        assert_eq!(index.find_by_code("MSH", "D003920"), [dm, t2dm]);
+
+        // The codes shown for a concept found by code can be limited to some sources.
+        let codes = |types: &[&str]| {
+            index
+                .downstream_codes(dm, types)
+                .map(|(id, code)| (index.concept(id as u32).cui().to_string(), code.code))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            codes(&["ICD10CM"]),
+            [
+                ("C0011849".to_string(), "E08-E13".into()),
+                ("C0011860".to_string(), "E11".into())
+            ]
+        );
+        assert_eq!(codes(&["MSH"]).len(), 2);
+        assert!(codes(&["RXNORM"]).is_empty());
+        // With no sources given, all the codes are shown: SNOMED CT, ICD-10-CM and MeSH codes
+        // for each of the two concepts.
+        assert_eq!(codes(&[]).len(), 6);
+    }
+
+    #[test]
+    fn definitions() {
+        let data = TestData::new();
+        let dm = data.index.find_cui("C0011849").unwrap();
+        let definitions = data.index.concept(dm).definitions().collect::<Vec<_>>();
+        // The obsolete definition is left out.
+        assert_eq!(definitions.len(), 2);
+        assert_eq!(definitions[0].source, "NCI");
+        assert_eq!(
+            definitions[1].text,
+            "A heterogeneous group of disorders characterized by hyperglycemia."
+        );
+
+        let data = TestData::build(|options| {
+            options.definition_sources = vec!["MSH".into(), "NCI".into()];
+        });
+        let sources = data
+            .index
+            .concept(dm)
+            .definitions()
+            .map(|d| d.source)
+            .collect::<Vec<_>>();
+        assert_eq!(sources, ["MSH", "NCI"]);
+
+        let data = TestData::build(|options| options.definition_sources = vec!["MSH".into()]);
+        assert_eq!(data.index.concept(dm).definitions().count(), 1);
+    }
+
+    #[test]
+    fn attributes() {
+        let data = TestData::new();
+        let index = &data.index;
+        let metformin = index.find_cui("C0025598").unwrap();
+
+        let ndcs = index.attributes(metformin, "NDC");
+        assert_eq!(ndcs.len(), 2);
+        assert_eq!(ndcs[0].value, "00093-1048-01");
+        // Decoded from the carried-over row.
+        assert_eq!(ndcs[1].value, "00093-1049-01");
+        assert_eq!(ndcs[1].source, "RXNORM");
+        assert_eq!(ndcs[1].code, "6809");
+
+        assert_eq!(index.find_by_attribute("NDC", "00093-1049-01"), [metformin]);
+        assert!(index.find_by_attribute("NDC", "00000-0000-00").is_empty());
+
+        // CTV3ID was not in the list of attributes to include.
+        let disease = index.find_cui("C0012634").unwrap();
+        assert!(index.attributes(disease, "CTV3ID").is_empty());
+    }
+}
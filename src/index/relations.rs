@@ -0,0 +1,107 @@
+use serde::Serialize;
+use smol_str::SmolStr;
+
+use super::{store, Index};
+
+/// A relationship between two concepts from MRREL, with its attributes.
+///
+/// Following the UMLS convention, a relation fetched for a concept reads as "`concept_id` is
+/// `rela` of the concept". For example, the relations of a disease include metformin with a RELA
+/// of `may_treat`, and the relations of a disorder include its finding sites with a RELA of
+/// `finding_site_of`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Relation {
+    /// The other concept in the relationship
+    pub concept_id: u32,
+    /// The broad relationship, such as `PAR` or `RO`
+    pub rel: SmolStr,
+    /// The specific relationship, such as `may_treat` or `isa`. This is empty for many sources.
+    pub rela: SmolStr,
+    /// The source that asserts the relationship
+    pub source: SmolStr,
+    /// The relationship group, which ties together relationships that apply together. This is
+    /// empty when the relationship is not part of a group.
+    pub group: SmolStr,
+    /// `Some(true)` if the source asserted the relationship in this direction, `Some(false)` if
+    /// it is the inverse of what the source asserted, and `None` if the source doesn't say.
+    pub asserted: Option<bool>,
+}
+
+/// Filters for [Index::relations]. Empty lists match everything.
+#[derive(Debug, Clone, Default)]
+pub struct RelationFilter {
+    pub rels: Vec<SmolStr>,
+    pub relas: Vec<SmolStr>,
+    pub sources: Vec<SmolStr>,
+}
+
+impl RelationFilter {
+    fn matches(&self, relation: &Relation) -> bool {
+        (self.rels.is_empty() || self.rels.contains(&relation.rel))
+            && (self.relas.is_empty() || self.relas.contains(&relation.rela))
+            && (self.sources.is_empty() || self.sources.contains(&relation.source))
+    }
+}
+
+impl Index {
+    /// Get the relationships of a concept to other concepts, filtered by their attributes.
+    pub fn relations(&self, id: u32, filter: &RelationFilter) -> Vec<Relation> {
+        let store = &self.store;
+        store
+            .list(store::RELATIONS, id)
+            .chunks_exact(store::RELATIONS.stride)
+            .map(|r| Relation {
+                concept_id: r[0],
+                rel: store.string(r[1]).into(),
+                rela: store.string(r[2]).into(),
+                source: store.string(r[3]).into(),
+                group: store.string(r[4]).into(),
+                asserted: match r[5] {
+                    store::RELATION_DIRECTION_ASSERTED => Some(true),
+                    store::RELATION_DIRECTION_INVERSE => Some(false),
+                    _ => None,
+                },
+            })
+            .filter(|r| filter.matches(r))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    #[test]
+    fn relations() {
+        let data = TestData::new();
+        let index = &data.index;
+        let t2dm = index.find_cui("C0011860").unwrap();
+        let metformin = index.find_cui("C0025598").unwrap();
+
+        let filter = RelationFilter {
+            relas: vec!["may_treat".into()],
+            sources: vec!["MED-RT".into()],
+            ..Default::default()
+        };
+        let treatments = index.relations(t2dm, &filter);
+        assert_eq!(treatments.len(), 1);
+        assert_eq!(treatments[0].concept_id, metformin);
+        assert_eq!(treatments[0].rel, "RO");
+        assert_eq!(treatments[0].asserted, Some(true));
+
+        // The parent relationship is kept alongside the one with metformin.
+        let all = index.relations(t2dm, &RelationFilter::default());
+        assert_eq!(all.len(), 2);
+        let parent = all.iter().find(|r| r.rel == "PAR").unwrap();
+        assert_eq!(parent.rela, "inverse_isa");
+        assert_eq!(parent.source, "SNOMEDCT_US");
+        assert_eq!(parent.asserted, None);
+
+        let filter = RelationFilter {
+            sources: vec!["MSH".into()],
+            ..Default::default()
+        };
+        assert!(index.relations(t2dm, &filter).is_empty());
+    }
+}
@@ -13,59 +13,84 @@ enum TrigramIteratorState {
     Done,
 }
 
+/// Iterates over the one, two and three character grams of a word, including the partial grams
+/// at its start and end.
 pub struct TrigramIterator<'a> {
     word: &'a str,
+    /// The byte offset of each character in `word`, followed by the length of `word`
+    bounds: Vec<usize>,
     state: TrigramIteratorState,
 }
 
 impl<'a> TrigramIterator<'a> {
-    pub fn new(word: &str) -> TrigramIterator {
+    pub fn new(word: &str) -> TrigramIterator<'_> {
+        let bounds = word
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([word.len()])
+            .collect::<Vec<_>>();
+        let state = if word.is_empty() {
+            TrigramIteratorState::Done
+        } else {
+            TrigramIteratorState::FirstOneGram
+        };
+
         TrigramIterator {
             word,
-            state: TrigramIteratorState::FirstOneGram,
+            bounds,
+            state,
         }
     }
+
+    /// The number of characters in the word
+    fn chars(&self) -> usize {
+        self.bounds.len() - 1
+    }
+
+    /// The characters from `start` up to `end`
+    fn slice(&self, start: usize, end: usize) -> &'a str {
+        &self.word[self.bounds[start]..self.bounds[end]]
+    }
 }
 
 impl<'a> Iterator for TrigramIterator<'a> {
     type Item = &'a str;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let len = self.chars();
         let (next_state, result) = match self.state {
             TrigramIteratorState::FirstOneGram => {
-                let next_state = if self.word.len() > 1 {
+                let next_state = if len > 1 {
                     TrigramIteratorState::FirstTwoGram
                 } else {
                     TrigramIteratorState::Done
                 };
-                (next_state, &self.word[0..1])
+                (next_state, self.slice(0, 1))
             }
             TrigramIteratorState::FirstTwoGram => {
-                let next_state = if self.word.len() > 2 {
+                let next_state = if len > 2 {
                     TrigramIteratorState::Trigram(0)
                 } else {
                     TrigramIteratorState::LastOneGram
                 };
-                (next_state, &self.word[0..2])
+                (next_state, self.slice(0, 2))
             }
             TrigramIteratorState::Trigram(index) => {
-                let next_state = if index + 3 >= self.word.len() {
+                let next_state = if index + 3 >= len {
                     TrigramIteratorState::LastTwoGram
                 } else {
                     TrigramIteratorState::Trigram(index + 1)
                 };
 
-                (next_state, &self.word[index..index + 3])
+                (next_state, self.slice(index, index + 3))
             }
 
-            TrigramIteratorState::LastTwoGram => (
-                TrigramIteratorState::LastOneGram,
-                &self.word[self.word.len() - 2..],
-            ),
-            TrigramIteratorState::LastOneGram => (
-                TrigramIteratorState::Done,
-                &self.word[self.word.len() - 1..],
-            ),
+            TrigramIteratorState::LastTwoGram => {
+                (TrigramIteratorState::LastOneGram, self.slice(len - 2, len))
+            }
+            TrigramIteratorState::LastOneGram => {
+                (TrigramIteratorState::Done, self.slice(len - 1, len))
+            }
             TrigramIteratorState::Done => return None,
         };
 
@@ -89,8 +114,16 @@ mod test {
 
     #[test]
     fn empty() {
-        let result = TrigramIterator::new("a").collect::<Vec<_>>();
-        assert_eq!(result, vec!["a"]);
+        assert_eq!(TrigramIterator::new("").count(), 0);
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        let result = TrigramIterator::new("gré").collect::<Vec<_>>();
+        assert_eq!(result, vec!["g", "gr", "gré", "ré", "é"]);
+
+        let result = TrigramIterator::new("ö").collect::<Vec<_>>();
+        assert_eq!(result, vec!["ö"]);
     }
 
     #[test]
@@ -0,0 +1,429 @@
+use std::borrow::Cow;
+
+use ahash::{HashMap, HashMapExt, HashSet};
+use eyre::Result;
+use fst::{IntoStreamer, Streamer};
+use regex_automata::dense;
+use serde::Serialize;
+use smol_str::SmolStr;
+
+use super::{score::jaccard_trigram_distance, semantic_network::semantic_type_matches, Index};
+
+/// How a search result matched the query.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    /// The string matched the query exactly.
+    Exact,
+    /// The string matched the query when ignoring case.
+    CaseInsensitive,
+    /// The string was within the allowed edit distance of the query.
+    Fuzzy,
+}
+
+/// A single result from [Index::search_ranked].
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchHit {
+    /// The string in the index that matched the query.
+    pub string: String,
+    pub concept_id: u32,
+    pub cui: SmolStr,
+    /// The Jaccard trigram similarity between the query and the matched string, from 0 to 1.
+    pub score: f32,
+    pub kind: MatchKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// The maximum Levenshtein distance for fuzzy matches. When this is 0, only exact matches
+    /// (ignoring case) are returned.
+    pub max_edits: u32,
+    /// The minimum score for a fuzzy match to be returned.
+    pub score_threshold: f32,
+    /// The maximum number of results to return.
+    pub limit: Option<usize>,
+    /// Only return concepts that have an atom from one of these sources. If empty, concepts
+    /// from all sources are returned.
+    pub sources: Vec<SmolStr>,
+    /// Only return concepts with one of these semantic types. This takes semantic tree numbers,
+    /// and a number also matches all of its children, or semantic group abbreviations such as
+    /// `DISO`. If empty, all semantic types are returned.
+    pub semantic_types: Vec<SmolStr>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            max_edits: 0,
+            score_threshold: 0.7,
+            limit: None,
+            sources: Vec::new(),
+            semantic_types: Vec::new(),
+        }
+    }
+}
+
+/// Limits for [Index::search_regex_limited], which bound the memory and time used by a search
+/// for an untrusted pattern.
+#[derive(Debug, Clone, Copy)]
+pub struct RegexLimits {
+    /// The maximum length of the pattern, in bytes.
+    pub max_pattern_len: usize,
+    /// The maximum number of concepts to return.
+    pub max_results: usize,
+}
+
+impl Default for RegexLimits {
+    fn default() -> Self {
+        Self {
+            max_pattern_len: 256,
+            max_results: 1000,
+        }
+    }
+}
+
+/// Why [Index::search_regex_limited] failed.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RegexSearchError {
+    #[error("The pattern is longer than {0} bytes")]
+    PatternTooLong(usize),
+    #[error("The pattern is too complex")]
+    TooComplex,
+    #[error("The pattern matches more than {0} concepts")]
+    TooManyResults(usize),
+    #[error("Invalid pattern: {0}")]
+    Invalid(String),
+}
+
+/// The deepest nesting of groups and repetitions allowed in a limited regex search.
+const REGEX_NEST_LIMIT: u32 = 50;
+
+impl Index {
+    /// Search for a regex pattern like [Index::search_regex], but fail instead of using too much
+    /// memory or returning too many results. This is meant for patterns from untrusted sources.
+    pub fn search_regex_limited(
+        &self,
+        pattern: &str,
+        limits: &RegexLimits,
+    ) -> Result<Vec<u64>, RegexSearchError> {
+        if pattern.len() > limits.max_pattern_len {
+            return Err(RegexSearchError::PatternTooLong(limits.max_pattern_len));
+        }
+
+        // With 16-bit state IDs the DFA can have at most 65,536 states, so a pattern that needs
+        // a larger DFA fails to build instead of using unbounded memory.
+        let dfa = dense::Builder::new()
+            .anchored(true)
+            .nest_limit(REGEX_NEST_LIMIT)
+            .build_with_size::<u16>(pattern)
+            .map_err(|e| match e.kind() {
+                regex_automata::ErrorKind::StateIDOverflow { .. }
+                | regex_automata::ErrorKind::PremultiplyOverflow { .. } => {
+                    RegexSearchError::TooComplex
+                }
+                _ => RegexSearchError::Invalid(e.to_string()),
+            })?;
+
+        let mut stream = self.index.search(&dfa).into_stream();
+        let mut seen = HashSet::default();
+        let mut result = Vec::new();
+        while let Some((_, value)) = stream.next() {
+            for id in self.concept_ids(value) {
+                if !seen.insert(id) {
+                    continue;
+                }
+
+                if result.len() == limits.max_results {
+                    return Err(RegexSearchError::TooManyResults(limits.max_results));
+                }
+                result.push(id);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Search for a string, returning the matching concepts sorted from best to worst match.
+    /// Each concept appears at most once, with the best-scoring string that matched it.
+    pub fn search_ranked(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchHit>> {
+        let query = if self.meta.case_insensitive {
+            Cow::Owned(query.to_lowercase())
+        } else {
+            Cow::Borrowed(query)
+        };
+
+        let mut hits: HashMap<u32, SearchHit> = HashMap::new();
+        let mut add_hit = |hit: SearchHit| {
+            if !self.concept_matches_filters(
+                hit.concept_id,
+                &options.sources,
+                &options.semantic_types,
+            ) {
+                return;
+            }
+
+            let better = hits
+                .get(&hit.concept_id)
+                .map(|existing| compare_hits(&hit, existing).is_lt())
+                .unwrap_or(true);
+            if better {
+                hits.insert(hit.concept_id, hit);
+            }
+        };
+
+        // Find the exact matches. A case-insensitive index only holds lowercase strings, so the
+        // lowercased query can be looked up directly. Otherwise, search for the query in any case.
+        let mut exact_matches = Vec::new();
+        if self.meta.case_insensitive {
+            if let Some(value) = self.index.get(query.as_bytes()) {
+                exact_matches.push((query.to_string(), value));
+            }
+        } else {
+            let pattern = format!("(?i){}", escape_regex(&query));
+            let dfa = dense::Builder::new().anchored(true).build(&pattern)?;
+            let mut stream = self.index.search(&dfa).into_stream();
+            while let Some((s, value)) = stream.next() {
+                exact_matches.push((String::from_utf8_lossy(s).into_owned(), value));
+            }
+        }
+
+        for (string, value) in exact_matches {
+            let kind = if string == query {
+                MatchKind::Exact
+            } else {
+                MatchKind::CaseInsensitive
+            };
+
+            for id in self.concept_ids(value) {
+                add_hit(SearchHit {
+                    string: string.clone(),
+                    concept_id: id as u32,
+                    cui: self.concept_id(id).cui().into(),
+                    score: 1.0,
+                    kind,
+                });
+            }
+        }
+
+        if options.max_edits > 0 {
+            let lower_query = query.to_lowercase();
+            let mut stream = self.fuzzy_search(&query, options.max_edits)?;
+            while let Some((s, value, _)) = stream.next() {
+                let string = String::from_utf8_lossy(s);
+                let score = jaccard_trigram_distance(&lower_query, &string.to_lowercase());
+                if score < options.score_threshold {
+                    continue;
+                }
+
+                for id in self.concept_ids(value) {
+                    add_hit(SearchHit {
+                        string: string.to_string(),
+                        concept_id: id as u32,
+                        cui: self.concept_id(id).cui().into(),
+                        score,
+                        kind: MatchKind::Fuzzy,
+                    });
+                }
+            }
+        }
+
+        let mut hits = hits.into_values().collect::<Vec<_>>();
+        hits.sort_by(compare_hits);
+        if let Some(limit) = options.limit {
+            hits.truncate(limit);
+        }
+
+        Ok(hits)
+    }
+
+    /// Check if a concept has an atom from one of `sources` and a semantic type matching one of
+    /// the tree number prefixes or semantic groups in `semantic_types`. Empty lists match every
+    /// concept.
+    pub(crate) fn concept_matches_filters(
+        &self,
+        id: u32,
+        sources: &[SmolStr],
+        semantic_types: &[SmolStr],
+    ) -> bool {
+        let concept = self.concept(id);
+        if !sources.is_empty()
+            && !concept
+                .sources()
+                .any(|source| sources.iter().any(|s| s == source))
+        {
+            return false;
+        }
+
+        if !semantic_types.is_empty() {
+            return concept.types().any(|t| {
+                self.semantic_types
+                    .get(&t)
+                    .map(|sty| {
+                        semantic_types
+                            .iter()
+                            .any(|filter| semantic_type_matches(sty, filter))
+                    })
+                    .unwrap_or(false)
+            });
+        }
+
+        true
+    }
+}
+
+/// Order hits from best to worst.
+fn compare_hits(a: &SearchHit, b: &SearchHit) -> std::cmp::Ordering {
+    b.score
+        .total_cmp(&a.score)
+        .then(a.kind.cmp(&b.kind))
+        .then_with(|| a.string.cmp(&b.string))
+        .then(a.concept_id.cmp(&b.concept_id))
+}
+
+/// Escape a string so that it matches literally in a regex.
+pub(super) fn escape_regex(s: &str) -> String {
+    let mut output = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    fn cuis(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|h| h.cui.as_str()).collect()
+    }
+
+    #[test]
+    fn match_kinds() {
+        let data = TestData::new();
+        let hits = data
+            .index
+            .search_ranked("Diabetes Mellitus", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(cuis(&hits), ["C0011849"]);
+        assert_eq!(hits[0].kind, MatchKind::Exact);
+        assert_eq!(hits[0].string, "diabetes mellitus");
+
+        let data = TestData::build(|options| options.case_insensitive = false);
+        let index = &data.index;
+        let hits = index
+            .search_ranked("Diabetes mellitus", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(cuis(&hits), ["C0011849"]);
+        assert_eq!(hits[0].kind, MatchKind::Exact);
+
+        let hits = index
+            .search_ranked("DIABETES MELLITUS", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(cuis(&hits), ["C0011849"]);
+        assert_eq!(hits[0].kind, MatchKind::CaseInsensitive);
+        assert_eq!(hits[0].string, "Diabetes mellitus");
+        // Fuzzy matches are scored on characters, so non-ASCII and empty queries work too.
+        let options = SearchOptions {
+            max_edits: 1,
+            ..Default::default()
+        };
+        let hits = data
+            .index
+            .search_ranked("Diabétes mellitus", &options)
+            .unwrap();
+        assert_eq!(cuis(&hits), ["C0011849"]);
+        assert_eq!(hits[0].kind, MatchKind::Fuzzy);
+        assert!(index.search_ranked("", &options).is_ok());
+    }
+
+    #[test]
+    fn ordering_and_limit() {
+        let data = TestData::new();
+        let index = &data.index;
+
+        // Ties are broken by the concept ID.
+        let hits = index
+            .search_ranked("dm", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(cuis(&hits), ["C0011849", "C0011860"]);
+
+        let options = SearchOptions {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let hits = index.search_ranked("dm", &options).unwrap();
+        assert_eq!(cuis(&hits), ["C0011849"]);
+
+        // Fuzzy matches score lower than exact ones.
+        let options = SearchOptions {
+            max_edits: 1,
+            ..Default::default()
+        };
+        let hits = index.search_ranked("diabetes melitus", &options).unwrap();
+        assert_eq!(cuis(&hits), ["C0011849"]);
+        assert_eq!(hits[0].kind, MatchKind::Fuzzy);
+        assert!(hits[0].score < 1.0);
+
+        let hits = index.search_ranked("diseases", &options).unwrap();
+        assert_eq!(cuis(&hits), ["C0012634"]);
+        assert_eq!(hits[0].kind, MatchKind::Exact);
+        assert!(hits.windows(2).all(|w| compare_hits(&w[0], &w[1]).is_le()));
+    }
+
+    #[test]
+    fn filters() {
+        let data = TestData::new();
+        let index = &data.index;
+        let search = |sources: &[&str], semantic_types: &[&str]| {
+            let options = SearchOptions {
+                sources: sources.iter().map(|&s| s.into()).collect(),
+                semantic_types: semantic_types.iter().map(|&s| s.into()).collect(),
+                ..Default::default()
+            };
+            let hits = index.search_ranked("metformin", &options).unwrap();
+            cuis(&hits)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(search(&[], &[]), ["C0025598"]);
+        assert_eq!(search(&["RXNORM"], &[]), ["C0025598"]);
+        assert!(search(&["SNOMEDCT_US"], &[]).is_empty());
+        assert_eq!(search(&[], &["CHEM"]), ["C0025598"]);
+        assert_eq!(search(&[], &["A1.4"]), ["C0025598"]);
+        assert!(search(&[], &["DISO"]).is_empty());
+    }
+
+    #[test]
+    fn regex_limits() {
+        let data = TestData::new();
+        let index = &data.index;
+        let limits = RegexLimits::default();
+
+        let found = index.search_regex_limited("diabetes.*", &limits).unwrap();
+        assert_eq!(found.len(), 2);
+
+        let err = index.search_regex_limited(&"a".repeat(300), &limits);
+        assert_eq!(err, Err(RegexSearchError::PatternTooLong(256)));
+
+        let err = index.search_regex_limited("(diabetes", &limits);
+        assert!(matches!(err, Err(RegexSearchError::Invalid(_))));
+
+        // The DFA for this needs a state for each combination of the last 17 characters.
+        let err = index.search_regex_limited("[ab]*a[ab]{16}", &limits);
+        assert_eq!(err, Err(RegexSearchError::TooComplex));
+
+        let limits = RegexLimits {
+            max_results: 1,
+            ..limits
+        };
+        let err = index.search_regex_limited(".*", &limits);
+        assert_eq!(err, Err(RegexSearchError::TooManyResults(1)));
+    }
+}
@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+
+use super::{parse_tui, Index, SemanticType};
+
+/// A relationship type from the semantic network, such as `treats` or `isa`, from the RL rows of
+/// SRDEF.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SemanticRelationType {
+    pub rui: SmolStr,
+    pub name: SmolStr,
+    /// The name of the relationship in the other direction, such as `treated_by`
+    pub inverse: SmolStr,
+    pub tree_number: SmolStr,
+    pub description: String,
+}
+
+/// The relationships that the semantic network allows between semantic types.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SemanticNetwork {
+    pub relation_types: Vec<SemanticRelationType>,
+    /// `(type, relation, type)` triples of TUI and RUI numbers, sorted so that they can be
+    /// searched.
+    relations: Vec<(u16, u16, u16)>,
+}
+
+impl SemanticNetwork {
+    pub(crate) fn new(
+        relation_types: Vec<SemanticRelationType>,
+        mut relations: Vec<(u16, u16, u16)>,
+    ) -> SemanticNetwork {
+        relations.sort_unstable();
+        relations.dedup();
+        SemanticNetwork {
+            relation_types,
+            relations,
+        }
+    }
+
+    /// Look up a relationship type by its RUI or name, which can be the inverse name.
+    pub fn relation_type(&self, relation: &str) -> Option<&SemanticRelationType> {
+        self.relation_types.iter().find(|r| {
+            r.rui == relation
+                || r.name.eq_ignore_ascii_case(relation)
+                || r.inverse.eq_ignore_ascii_case(relation)
+        })
+    }
+
+    /// Check if the semantic network allows `relation` from the type `from` to the type `to`,
+    /// such as "T121 (Pharmacologic Substance) treats T047 (Disease or Syndrome)". The relation
+    /// can be given by name, by inverse name, or by RUI, and the types by TUI.
+    pub fn allows(&self, from: &str, relation: &str, to: &str) -> bool {
+        let (Ok(from), Ok(to)) = (parse_tui(from), parse_tui(to)) else {
+            return false;
+        };
+        let Some(rel) = self.relation_type(relation) else {
+            return false;
+        };
+        let Ok(rui) = parse_tui(&rel.rui) else {
+            return false;
+        };
+
+        let (from, to) = if rel.name.eq_ignore_ascii_case(relation) || rel.rui == relation {
+            (from, to)
+        } else {
+            (to, from)
+        };
+
+        self.relations.binary_search(&(from, rui, to)).is_ok()
+    }
+
+    /// The relationship types allowed from the type `from` to the type `to`.
+    pub fn relations_between(&self, from: &str, to: &str) -> Vec<&SemanticRelationType> {
+        let (Ok(from), Ok(to)) = (parse_tui(from), parse_tui(to)) else {
+            return Vec::new();
+        };
+
+        let start = self.relations.partition_point(|r| r.0 < from);
+        self.relations[start..]
+            .iter()
+            .take_while(|r| r.0 == from)
+            .filter(|r| r.2 == to)
+            .filter_map(|r| {
+                self.relation_types
+                    .iter()
+                    .find(|t| parse_tui(&t.rui).is_ok_and(|rui| rui == r.1))
+            })
+            .collect()
+    }
+
+    /// The TUIs of the types that `tui` is a kind of, through `isa` relationships.
+    pub fn parents(&self, tui: &str) -> Vec<SmolStr> {
+        let Ok(tui) = parse_tui(tui) else {
+            return Vec::new();
+        };
+        let Some(isa) = self
+            .relation_type("isa")
+            .and_then(|r| parse_tui(&r.rui).ok())
+        else {
+            return Vec::new();
+        };
+
+        let start = self.relations.partition_point(|r| r.0 < tui);
+        self.relations[start..]
+            .iter()
+            .take_while(|r| r.0 == tui)
+            .filter(|r| r.1 == isa && r.2 != tui)
+            .map(|r| format!("T{:03}", r.2).into())
+            .collect()
+    }
+}
+
+/// One of the semantic groups from McCray et al., which partition the semantic types into broad
+/// categories such as Disorders or Chemicals & Drugs.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SemanticGroup {
+    pub abbreviation: &'static str,
+    pub name: &'static str,
+    pub types: &'static [&'static str],
+}
+
+pub const SEMANTIC_GROUPS: &[SemanticGroup] = &[
+    SemanticGroup {
+        abbreviation: "ACTI",
+        name: "Activities & Behaviors",
+        types: &[
+            "T051", "T052", "T053", "T054", "T055", "T056", "T057", "T064", "T066",
+        ],
+    },
+    SemanticGroup {
+        abbreviation: "ANAT",
+        name: "Anatomy",
+        types: &[
+            "T017", "T018", "T021", "T022", "T023", "T024", "T025", "T026", "T029", "T030", "T031",
+        ],
+    },
+    SemanticGroup {
+        abbreviation: "CHEM",
+        name: "Chemicals & Drugs",
+        types: &[
+            "T103", "T104", "T109", "T114", "T116", "T120", "T121", "T122", "T123", "T125", "T126",
+            "T127", "T129", "T130", "T131", "T192", "T195", "T196", "T197", "T200",
+        ],
+    },
+    SemanticGroup {
+        abbreviation: "CONC",
+        name: "Concepts & Ideas",
+        types: &[
+            "T077", "T078", "T079", "T080", "T081", "T082", "T089", "T102", "T169", "T170", "T171",
+            "T185",
+        ],
+    },
+    SemanticGroup {
+        abbreviation: "DEVI",
+        name: "Devices",
+        types: &["T074", "T075", "T203"],
+    },
+    SemanticGroup {
+        abbreviation: "DISO",
+        name: "Disorders",
+        types: &[
+            "T019", "T020", "T033", "T037", "T046", "T047", "T048", "T049", "T050", "T184", "T190",
+            "T191",
+        ],
+    },
+    SemanticGroup {
+        abbreviation: "GENE",
+        name: "Genes & Molecular Sequences",
+        types: &["T028", "T085", "T086", "T087", "T088"],
+    },
+    SemanticGroup {
+        abbreviation: "GEOG",
+        name: "Geographic Areas",
+        types: &["T083"],
+    },
+    SemanticGroup {
+        abbreviation: "LIVB",
+        name: "Living Beings",
+        types: &[
+            "T001", "T002", "T004", "T005", "T007", "T008", "T010", "T011", "T012", "T013", "T014",
+            "T015", "T016", "T096", "T097", "T098", "T099", "T100", "T101", "T194", "T204",
+        ],
+    },
+    SemanticGroup {
+        abbreviation: "OBJC",
+        name: "Objects",
+        types: &["T071", "T072", "T073", "T167", "T168"],
+    },
+    SemanticGroup {
+        abbreviation: "OCCU",
+        name: "Occupations",
+        types: &["T090", "T091"],
+    },
+    SemanticGroup {
+        abbreviation: "ORGA",
+        name: "Organizations",
+        types: &["T092", "T093", "T094", "T095"],
+    },
+    SemanticGroup {
+        abbreviation: "PHEN",
+        name: "Phenomena",
+        types: &["T034", "T038", "T067", "T068", "T069", "T070"],
+    },
+    SemanticGroup {
+        abbreviation: "PHYS",
+        name: "Physiology",
+        types: &[
+            "T032", "T039", "T040", "T041", "T042", "T043", "T044", "T045", "T201",
+        ],
+    },
+    SemanticGroup {
+        abbreviation: "PROC",
+        name: "Procedures",
+        types: &["T058", "T059", "T060", "T061", "T062", "T063", "T065"],
+    },
+];
+
+/// Find a semantic group by its abbreviation, such as `DISO`, or its name, ignoring case.
+pub fn find_semantic_group(name: &str) -> Option<&'static SemanticGroup> {
+    SEMANTIC_GROUPS
+        .iter()
+        .find(|g| g.abbreviation.eq_ignore_ascii_case(name) || g.name.eq_ignore_ascii_case(name))
+}
+
+/// Get the semantic group that a semantic type belongs to.
+pub fn semantic_group(tui: &str) -> Option<&'static SemanticGroup> {
+    SEMANTIC_GROUPS.iter().find(|g| g.types.contains(&tui))
+}
+
+/// Check if a semantic type matches a filter, which is either a semantic group or a tree number
+/// prefix.
+pub(crate) fn semantic_type_matches(semantic_type: &SemanticType, filter: &str) -> bool {
+    match find_semantic_group(filter) {
+        Some(group) => group.types.contains(&semantic_type.tui.as_str()),
+        None => semantic_type.tree_number.starts_with(filter),
+    }
+}
+
+impl Index {
+    /// Check if the semantic network allows `relation` between two semantic types. See
+    /// [SemanticNetwork::allows].
+    pub fn relation_allowed(&self, from: &str, relation: &str, to: &str) -> bool {
+        self.semantic_network.allows(from, relation, to)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    #[test]
+    fn semantic_network() {
+        let data = TestData::new();
+        let network = &data.index.semantic_network;
+
+        assert!(data.index.relation_allowed("T121", "treats", "T047"));
+        assert!(network.allows("T047", "treated_by", "T121"));
+        assert!(network.allows("T121", "T154", "T047"));
+        assert!(!network.allows("T047", "treats", "T121"));
+        assert!(!network.allows("T121", "unknown", "T047"));
+
+        let between = network.relations_between("T121", "T047");
+        assert_eq!(between.len(), 1);
+        assert_eq!(between[0].name, "treats");
+
+        assert_eq!(network.parents("T047"), ["T046"]);
+    }
+
+    #[test]
+    fn semantic_groups() {
+        assert_eq!(find_semantic_group("diso").unwrap().name, "Disorders");
+        assert_eq!(
+            find_semantic_group("Chemicals & Drugs")
+                .unwrap()
+                .abbreviation,
+            "CHEM"
+        );
+        assert_eq!(semantic_group("T121").unwrap().abbreviation, "CHEM");
+
+        let total = SEMANTIC_GROUPS.iter().map(|g| g.types.len()).sum::<usize>();
+        assert_eq!(total, 127);
+
+        let data = TestData::new();
+        let index = &data.index;
+        let metformin = index.find_cui("C0025598").unwrap();
+        assert!(index.concept_matches_filters(metformin, &[], &["CHEM".into()]));
+        assert!(!index.concept_matches_filters(metformin, &[], &["DISO".into()]));
+        assert!(index.concept_matches_filters(metformin, &[], &["A1.4".into()]));
+    }
+}
@@ -0,0 +1,284 @@
+//! Similarity measures between concepts, based on their positions in the concept hierarchy.
+//!
+//! Information content is intrinsic, computed from the number of descendants that a concept has
+//! as described by Seco et al. (2004): `IC(c) = 1 - ln(descendants(c) + 1) / ln(N)`, where `N` is
+//! the number of concepts in the index. Leaf concepts have an IC of 1.
+
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::VecDeque,
+    str::FromStr,
+};
+
+use ahash::{HashMap, HashMapExt};
+use serde::Serialize;
+
+use super::{Index, TraversalOptions};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SimilarityMeasure {
+    /// `1 / (1 + edges)`, using the shortest path through a common ancestor.
+    Path,
+    /// Wu & Palmer: `2 * depth(lcs) / (depth(a) + depth(b))`, counting depth along the paths
+    /// through the deepest common ancestor.
+    WuPalmer,
+    /// Leacock & Chodorow: `-ln(nodes / (2 * D))`, where `nodes` is the number of concepts on the
+    /// shortest path and `D` is the depth of the deepest concept in the hierarchy.
+    LeacockChodorow,
+    /// The information content of the most informative common ancestor.
+    Resnik,
+    /// `2 * IC(lcs) / (IC(a) + IC(b))`
+    Lin,
+    /// `1 / (1 + IC(a) + IC(b) - 2 * IC(lcs))`
+    JiangConrath,
+}
+
+impl SimilarityMeasure {
+    pub const ALL: [SimilarityMeasure; 6] = [
+        SimilarityMeasure::Path,
+        SimilarityMeasure::WuPalmer,
+        SimilarityMeasure::LeacockChodorow,
+        SimilarityMeasure::Resnik,
+        SimilarityMeasure::Lin,
+        SimilarityMeasure::JiangConrath,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SimilarityMeasure::Path => "path",
+            SimilarityMeasure::WuPalmer => "wup",
+            SimilarityMeasure::LeacockChodorow => "lch",
+            SimilarityMeasure::Resnik => "resnik",
+            SimilarityMeasure::Lin => "lin",
+            SimilarityMeasure::JiangConrath => "jcn",
+        }
+    }
+}
+
+impl FromStr for SimilarityMeasure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SimilarityMeasure::ALL
+            .into_iter()
+            .find(|m| m.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown similarity measure {s}. Expected path, wup, lch, resnik, lin, or jcn"
+                )
+            })
+    }
+}
+
+/// Calculates similarity between concepts. The descendant counts and hierarchy depth that some
+/// of the measures need are expensive to calculate for large hierarchies, so they are cached for
+/// the lifetime of this object.
+pub struct Similarity<'a> {
+    index: &'a Index,
+    options: TraversalOptions,
+    descendant_counts: RefCell<HashMap<u32, usize>>,
+    hierarchy_depth: OnceCell<u32>,
+}
+
+impl<'a> Similarity<'a> {
+    pub fn new(index: &'a Index, options: TraversalOptions) -> Similarity<'a> {
+        Similarity {
+            index,
+            options,
+            descendant_counts: RefCell::new(HashMap::new()),
+            hierarchy_depth: OnceCell::new(),
+        }
+    }
+
+    /// Calculate the similarity between two concepts. Returns `None` if the concepts have no
+    /// common ancestor.
+    pub fn similarity(&self, a: u32, b: u32, measure: SimilarityMeasure) -> Option<f64> {
+        let index = self.index;
+        let options = &self.options;
+
+        let value = match measure {
+            SimilarityMeasure::Path => {
+                let edges = index.path_between(a, b, options)?.len() - 1;
+                1.0 / (1.0 + edges as f64)
+            }
+            SimilarityMeasure::WuPalmer => {
+                let (lcs, lcs_depth) = index
+                    .lowest_common_ancestors(a, b, options)
+                    .into_iter()
+                    .map(|id| (id, self.depth(id)))
+                    .max_by_key(|(_, depth)| *depth)?;
+                let to_a = self.edges_between(a, lcs)? as f64;
+                let to_b = self.edges_between(b, lcs)? as f64;
+                let lcs_depth = lcs_depth as f64;
+                2.0 * lcs_depth / (to_a + to_b + 2.0 * lcs_depth)
+            }
+            SimilarityMeasure::LeacockChodorow => {
+                let nodes = index.path_between(a, b, options)?.len() as f64;
+                let depth = self.hierarchy_depth() as f64;
+                -(nodes / (2.0 * depth)).ln()
+            }
+            SimilarityMeasure::Resnik => self.mica(a, b)?,
+            SimilarityMeasure::Lin => {
+                let lcs = self.mica(a, b)?;
+                let total = self.information_content(a) + self.information_content(b);
+                if total == 0.0 {
+                    if a == b {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                } else {
+                    2.0 * lcs / total
+                }
+            }
+            SimilarityMeasure::JiangConrath => {
+                let lcs = self.mica(a, b)?;
+                let distance =
+                    self.information_content(a) + self.information_content(b) - 2.0 * lcs;
+                1.0 / (1.0 + distance)
+            }
+        };
+
+        Some(value)
+    }
+
+    /// The intrinsic information content of a concept, from 0 for a concept that is an ancestor
+    /// of every other concept, to 1 for a leaf concept.
+    pub fn information_content(&self, id: u32) -> f64 {
+        let total = self.index.num_concepts() as f64;
+        if total <= 1.0 {
+            return 1.0;
+        }
+
+        let descendants = self.descendant_count(id) as f64;
+        1.0 - (descendants + 1.0).ln() / total.ln()
+    }
+
+    /// The information content of the most informative common ancestor.
+    fn mica(&self, a: u32, b: u32) -> Option<f64> {
+        self.index
+            .lowest_common_ancestors(a, b, &self.options)
+            .into_iter()
+            .map(|id| self.information_content(id))
+            .max_by(|x, y| x.total_cmp(y))
+    }
+
+    fn descendant_count(&self, id: u32) -> usize {
+        if let Some(count) = self.descendant_counts.borrow().get(&id) {
+            return *count;
+        }
+
+        let count = self.index.descendants(id, &self.options).len();
+        self.descendant_counts.borrow_mut().insert(id, count);
+        count
+    }
+
+    /// The depth of a concept counted in concepts, so a root concept has a depth of 1.
+    fn depth(&self, id: u32) -> u32 {
+        self.index.depth(id, &self.options).unwrap_or(0) + 1
+    }
+
+    /// The number of edges up from `descendant` to `ancestor`.
+    fn edges_between(&self, descendant: u32, ancestor: u32) -> Option<u32> {
+        if descendant == ancestor {
+            return Some(0);
+        }
+
+        self.index
+            .ancestors(descendant, &self.options)
+            .into_iter()
+            .find(|node| node.concept_id == ancestor)
+            .map(|node| node.depth)
+    }
+
+    /// The depth of the deepest concept in the hierarchy, counted in concepts.
+    fn hierarchy_depth(&self) -> u32 {
+        *self.hierarchy_depth.get_or_init(|| {
+            // Walk down from every root at once, so each concept gets its shortest depth.
+            let index = self.index;
+            let kinds = self.options.kinds;
+            let mut depths = vec![None; index.num_concepts()];
+            let mut queue = VecDeque::new();
+            for id in 0..index.num_concepts() as u32 {
+                if index.parents_of(id, kinds).next().is_none() {
+                    depths[id as usize] = Some(1);
+                    queue.push_back(id);
+                }
+            }
+
+            let mut max_depth = 1;
+            while let Some(id) = queue.pop_front() {
+                let depth = depths[id as usize].unwrap_or(1);
+                max_depth = max_depth.max(depth);
+                for child in index.children_of(id, kinds) {
+                    if depths[child as usize].is_none() {
+                        depths[child as usize] = Some(depth + 1);
+                        queue.push_back(child);
+                    }
+                }
+            }
+
+            max_depth
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::TestData;
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        let value = value.expect("a similarity value");
+        assert!(
+            (value - expected).abs() < 1e-9,
+            "expected {expected}, got {value}"
+        );
+    }
+
+    #[test]
+    fn measures() {
+        let data = TestData::new();
+        let index = &data.index;
+        let id = |cui| index.find_cui(cui).unwrap();
+        let (disease, t2dm, htn, metformin) = (
+            id("C0012634"),
+            id("C0011860"),
+            id("C0020538"),
+            id("C0025598"),
+        );
+        let sim = Similarity::new(index, TraversalOptions::default());
+
+        // T2DM -> DM -> Disease <- HTN
+        assert_close(sim.similarity(t2dm, htn, SimilarityMeasure::Path), 0.25);
+        assert_close(sim.similarity(t2dm, htn, SimilarityMeasure::WuPalmer), 0.4);
+        assert_close(
+            sim.similarity(t2dm, htn, SimilarityMeasure::LeacockChodorow),
+            -(4.0f64 / 6.0).ln(),
+        );
+
+        // Disease has 3 descendants out of 5 concepts.
+        let disease_ic = 1.0 - 4.0f64.ln() / 5.0f64.ln();
+        assert_close(Some(sim.information_content(disease)), disease_ic);
+        assert_close(Some(sim.information_content(t2dm)), 1.0);
+        assert_close(
+            sim.similarity(t2dm, htn, SimilarityMeasure::Resnik),
+            disease_ic,
+        );
+        assert_close(
+            sim.similarity(t2dm, htn, SimilarityMeasure::Lin),
+            disease_ic,
+        );
+        assert_close(
+            sim.similarity(t2dm, htn, SimilarityMeasure::JiangConrath),
+            1.0 / (1.0 + 2.0 - 2.0 * disease_ic),
+        );
+
+        assert_close(sim.similarity(t2dm, t2dm, SimilarityMeasure::Lin), 1.0);
+        assert_eq!(
+            sim.similarity(t2dm, metformin, SimilarityMeasure::Path),
+            None
+        );
+    }
+}
@@ -0,0 +1,645 @@
+//! A binary file format for the concept data, designed to be memory-mapped and read in place
+//! without any parsing at startup.
+//!
+//! The layout is, with all integers in little-endian byte order:
+//!
+//! - A header containing an 8-byte magic value, a u32 format version, a u32 section count, and a
+//!   u64 offset to the section table.
+//! - The sections, each starting on an 8-byte boundary so that they can be read as arrays of
+//!   integers directly from the mapped memory.
+//! - The section table, with a u32 section ID, 4 bytes of padding, a u64 offset and a u64 length
+//!   for each section.
+//!
+//! Strings are interned into a single string pool and referenced by their u32 ID. Variable-length
+//! per-concept data is stored in list tables, which consist of an offsets section with one more
+//! entry than there are concepts, and a data section containing fixed-size records. The records
+//! for concept `i` are at `offsets[i]..offsets[i + 1]`.
+
+use std::{
+    io::{Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+};
+
+use ahash::{HashMap, HashMapExt};
+use eyre::{eyre, Result};
+use memmap2::Mmap;
+use smol_str::SmolStr;
+
+#[cfg(not(target_endian = "little"))]
+compile_error!("The binary index format is only supported on little-endian targets");
+
+const MAGIC: &[u8; 8] = b"UMLSIDX\0";
+pub(crate) const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: u64 = 24;
+const SECTION_TABLE_ENTRY_LEN: usize = 24;
+
+/// The IDs of the sections in the file. These values are part of the file format and must not
+/// change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub(crate) enum Section {
+    StringOffsets = 1,
+    StringData = 2,
+    Postings = 3,
+    /// Fixed-size concept records. See [CONCEPT_RECORD_LEN].
+    Concepts = 4,
+    TypesOffsets = 5,
+    TypesData = 6,
+    CodesOffsets = 7,
+    CodesData = 8,
+    AtomsOffsets = 9,
+    AtomsData = 10,
+    ParentsOffsets = 11,
+    ParentsData = 12,
+    ChildrenOffsets = 13,
+    ChildrenData = 14,
+    SimilarOffsets = 15,
+    SimilarData = 16,
+    SynonymOffsets = 17,
+    SynonymData = 18,
+    OtherRelationshipOffsets = 19,
+    OtherRelationshipData = 20,
+    RelatedPossiblySynonymousOffsets = 21,
+    RelatedPossiblySynonymousData = 22,
+    AllowedQualifierOffsets = 23,
+    AllowedQualifierData = 24,
+    QualifiedByOffsets = 25,
+    QualifiedByData = 26,
+    ParentKindsOffsets = 27,
+    ParentKindsData = 28,
+    ChildKindsOffsets = 29,
+    ChildKindsData = 30,
+    /// Only present when the index was built with an ancestor closure.
+    AncestorsOffsets = 31,
+    AncestorsData = 32,
+    HierarchiesOffsets = 33,
+    HierarchiesData = 34,
+    /// The atom paths for [HIERARCHIES], each stored as the number of atoms followed by the atom
+    /// indexes.
+    HierarchyPaths = 35,
+    RelationsOffsets = 36,
+    RelationsData = 37,
+    DefinitionsOffsets = 38,
+    DefinitionsData = 39,
+    AttributesOffsets = 40,
+    AttributesData = 41,
+    /// The history of retired CUIs. The CUI history FST points to a count followed by that many
+    /// records of [CUI_HISTORY_RECORD_LEN] values.
+    CuiHistory = 42,
+    /// Source-asserted mappings, laid out like [Section::CuiHistory] with records of
+    /// [MAPPING_RECORD_LEN] values.
+    Mappings = 43,
+}
+
+/// The number of u32 values in each concept record: the CUI string and the preferred name string.
+pub(crate) const CONCEPT_RECORD_LEN: usize = 2;
+
+/// The number of u32 values in each CUI history record: the new CUI string (empty for a
+/// deletion), the version string and the [super::CuiChangeKind].
+pub(crate) const CUI_HISTORY_RECORD_LEN: usize = 3;
+
+/// The number of u32 values in each mapping record: the target code, target source, map set CUI,
+/// group, priority, rule, advice, REL, RELA and map type. The group and priority are numbers and
+/// the rest are strings.
+pub(crate) const MAPPING_RECORD_LEN: usize = 10;
+
+/// A per-concept list table, made up of an offsets section and a data section.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ListTable {
+    pub offsets: Section,
+    pub data: Section,
+    /// The number of u32 values in each record of the table.
+    pub stride: usize,
+}
+
+impl ListTable {
+    const fn new(offsets: Section, data: Section, stride: usize) -> ListTable {
+        ListTable {
+            offsets,
+            data,
+            stride,
+        }
+    }
+}
+
+/// Semantic type numbers
+pub(crate) const TYPES: ListTable = ListTable::new(Section::TypesOffsets, Section::TypesData, 1);
+/// Pairs of (source string, code string)
+pub(crate) const CODES: ListTable = ListTable::new(Section::CodesOffsets, Section::CodesData, 2);
+/// Records of (AUI string, string, TTY string, source string, code string, flags). See
+/// [ATOM_PREFERRED_FLAG].
+pub(crate) const ATOMS: ListTable = ListTable::new(Section::AtomsOffsets, Section::AtomsData, 6);
+pub(crate) const PARENTS: ListTable =
+    ListTable::new(Section::ParentsOffsets, Section::ParentsData, 1);
+pub(crate) const CHILDREN: ListTable =
+    ListTable::new(Section::ChildrenOffsets, Section::ChildrenData, 1);
+pub(crate) const SIMILAR: ListTable =
+    ListTable::new(Section::SimilarOffsets, Section::SimilarData, 1);
+pub(crate) const SYNONYM: ListTable =
+    ListTable::new(Section::SynonymOffsets, Section::SynonymData, 1);
+pub(crate) const OTHER_RELATIONSHIP: ListTable = ListTable::new(
+    Section::OtherRelationshipOffsets,
+    Section::OtherRelationshipData,
+    1,
+);
+pub(crate) const RELATED_POSSIBLY_SYNONYMOUS: ListTable = ListTable::new(
+    Section::RelatedPossiblySynonymousOffsets,
+    Section::RelatedPossiblySynonymousData,
+    1,
+);
+pub(crate) const ALLOWED_QUALIFIER: ListTable = ListTable::new(
+    Section::AllowedQualifierOffsets,
+    Section::AllowedQualifierData,
+    1,
+);
+pub(crate) const QUALIFIED_BY: ListTable =
+    ListTable::new(Section::QualifiedByOffsets, Section::QualifiedByData, 1);
+
+/// The [super::RelationKinds] of each parent, in the same order as [PARENTS].
+pub(crate) const PARENT_KINDS: ListTable =
+    ListTable::new(Section::ParentKindsOffsets, Section::ParentKindsData, 1);
+/// The [super::RelationKinds] of each child, in the same order as [CHILDREN].
+pub(crate) const CHILD_KINDS: ListTable =
+    ListTable::new(Section::ChildKindsOffsets, Section::ChildKindsData, 1);
+/// The sorted IDs of every ancestor of each concept.
+pub(crate) const ANCESTORS: ListTable =
+    ListTable::new(Section::AncestorsOffsets, Section::AncestorsData, 1);
+/// Records of (atom, RELA string, HCD string, path offset, complete) for each MRHIER context of
+/// the concept's atoms. Atoms are referenced by the index of their record in [ATOMS], and the path
+/// offset points into [Section::HierarchyPaths]. Paths run from the root of the source's
+/// hierarchy down to the context's parent. Complete is 0 if atoms below the root were missing
+/// from the path.
+pub(crate) const HIERARCHIES: ListTable =
+    ListTable::new(Section::HierarchiesOffsets, Section::HierarchiesData, 5);
+/// Records of (other concept, REL string, RELA string, source string, RG string, direction) for
+/// each MRREL row where the concept is CUI1. See [RELATION_DIRECTION_ASSERTED] for the direction
+/// values.
+pub(crate) const RELATIONS: ListTable =
+    ListTable::new(Section::RelationsOffsets, Section::RelationsData, 6);
+/// Pairs of (source string, definition string)
+pub(crate) const DEFINITIONS: ListTable =
+    ListTable::new(Section::DefinitionsOffsets, Section::DefinitionsData, 2);
+/// Records of (name string, value string, source string, code string)
+pub(crate) const ATTRIBUTES: ListTable =
+    ListTable::new(Section::AttributesOffsets, Section::AttributesData, 4);
+
+/// The tables that every index must contain.
+const REQUIRED_TABLES: [ListTable; 17] = [
+    TYPES,
+    CODES,
+    ATOMS,
+    PARENTS,
+    CHILDREN,
+    SIMILAR,
+    SYNONYM,
+    OTHER_RELATIONSHIP,
+    RELATED_POSSIBLY_SYNONYMOUS,
+    ALLOWED_QUALIFIER,
+    QUALIFIED_BY,
+    PARENT_KINDS,
+    CHILD_KINDS,
+    HIERARCHIES,
+    RELATIONS,
+    DEFINITIONS,
+    ATTRIBUTES,
+];
+
+/// Tables that are validated if they are present.
+const OPTIONAL_TABLES: [ListTable; 1] = [ANCESTORS];
+
+/// Set in an atom's flags when it is the preferred atom for its string.
+pub(crate) const ATOM_PREFERRED_FLAG: u32 = 1;
+/// The atom's [super::Suppress] value is stored in the flags, shifted left by this amount.
+pub(crate) const ATOM_SUPPRESS_SHIFT: u32 = 1;
+
+/// The direction value of a relation when the source asserted it in this direction (DIR is Y).
+pub(crate) const RELATION_DIRECTION_ASSERTED: u32 = 1;
+/// The direction value of a relation that is the inverse of what the source asserted (DIR is N).
+/// When DIR is empty the value is 0.
+pub(crate) const RELATION_DIRECTION_INVERSE: u32 = 2;
+
+/// A memory-mapped store file.
+pub(crate) struct Store {
+    data: Mmap,
+    sections: Vec<Option<Range<usize>>>,
+    num_concepts: usize,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Store> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: The index files are written once by the index builder and are not expected to
+        // be modified while they are in use.
+        let data = unsafe { Mmap::map(&file)? };
+
+        if data.len() < HEADER_LEN as usize || &data[0..8] != MAGIC {
+            return Err(eyre!("{} is not a UMLS index file", path.display()));
+        }
+
+        let version = read_u32(&data, 8);
+        if version != FORMAT_VERSION {
+            return Err(eyre!(
+                "{} has format version {version}, but this program reads version {FORMAT_VERSION}. Please rebuild the index.",
+                path.display()
+            ));
+        }
+
+        let section_count = read_u32(&data, 12) as usize;
+        let table_offset = read_u64(&data, 16) as usize;
+        let table_len = section_count * SECTION_TABLE_ENTRY_LEN;
+        if table_len > data.len() || table_offset > data.len() - table_len {
+            return Err(eyre!("{} is truncated", path.display()));
+        }
+
+        let mut sections = Vec::new();
+        for i in 0..section_count {
+            let entry = table_offset + i * SECTION_TABLE_ENTRY_LEN;
+            let id = read_u32(&data, entry) as usize;
+            let offset = read_u64(&data, entry + 8) as usize;
+            let len = read_u64(&data, entry + 16) as usize;
+
+            if offset % 8 != 0 || offset > data.len() || len > data.len() - offset {
+                return Err(eyre!("{} has an invalid section {id}", path.display()));
+            }
+
+            if sections.len() <= id {
+                sections.resize(id + 1, None);
+            }
+            sections[id] = Some(offset..offset + len);
+        }
+
+        let mut store = Store {
+            data,
+            sections,
+            num_concepts: 0,
+        };
+
+        store.num_concepts = store.required_u32s(Section::Concepts)?.len() / CONCEPT_RECORD_LEN;
+        let string_offsets = store.required_u64s(Section::StringOffsets)?;
+        let string_data = store.required_bytes(Section::StringData)?;
+        let strings_valid = sorted(string_offsets)
+            && string_offsets
+                .last()
+                .is_some_and(|&last| last <= string_data.len() as u64);
+        if !strings_valid {
+            return Err(eyre!(
+                "Index section {:?} is invalid",
+                Section::StringOffsets
+            ));
+        }
+        store.required_u32s(Section::Postings)?;
+        store.required_u32s(Section::HierarchyPaths)?;
+        store.required_u32s(Section::CuiHistory)?;
+        store.required_u32s(Section::Mappings)?;
+        for table in REQUIRED_TABLES {
+            store.validate_table(table)?;
+        }
+        for table in OPTIONAL_TABLES {
+            if store.has_table(table) {
+                store.validate_table(table)?;
+            }
+        }
+
+        Ok(store)
+    }
+
+    fn validate_table(&self, table: ListTable) -> Result<()> {
+        let offsets = self.required_u32s(table.offsets)?;
+        let data = self.required_u32s(table.data)?;
+
+        let valid = offsets.len() == self.num_concepts + 1
+            && offsets
+                .last()
+                .map(|&last| last as usize * table.stride == data.len())
+                .unwrap_or(false)
+            && sorted(offsets);
+        if !valid {
+            return Err(eyre!("Index table {:?} is invalid", table.data));
+        }
+
+        Ok(())
+    }
+
+    pub fn num_concepts(&self) -> usize {
+        self.num_concepts
+    }
+
+    pub fn has_table(&self, table: ListTable) -> bool {
+        self.section_bytes(table.offsets).is_some()
+    }
+
+    fn section_bytes(&self, section: Section) -> Option<&[u8]> {
+        let range = self.sections.get(section as usize)?.clone()?;
+        Some(&self.data[range])
+    }
+
+    fn required_bytes(&self, section: Section) -> Result<&[u8]> {
+        self.section_bytes(section)
+            .ok_or_else(|| eyre!("Index is missing section {section:?}"))
+    }
+
+    fn required_u32s(&self, section: Section) -> Result<&[u32]> {
+        bytemuck::try_cast_slice(self.required_bytes(section)?)
+            .map_err(|e| eyre!("Index section {section:?} is invalid: {e}"))
+    }
+
+    fn required_u64s(&self, section: Section) -> Result<&[u64]> {
+        bytemuck::try_cast_slice(self.required_bytes(section)?)
+            .map_err(|e| eyre!("Index section {section:?} is invalid: {e}"))
+    }
+
+    /// Get a section as a list of u32 values. Sections are validated when the file is opened, so
+    /// this only returns an empty slice if the section is missing.
+    pub fn u32s(&self, section: Section) -> &[u32] {
+        self.section_bytes(section)
+            .and_then(|bytes| bytemuck::try_cast_slice(bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn u64s(&self, section: Section) -> &[u64] {
+        self.section_bytes(section)
+            .and_then(|bytes| bytemuck::try_cast_slice(bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Get a string from the string pool.
+    pub fn string(&self, id: u32) -> &str {
+        let offsets = self.u64s(Section::StringOffsets);
+        let data = self.section_bytes(Section::StringData).unwrap_or_default();
+        let start = offsets[id as usize] as usize;
+        let end = offsets[id as usize + 1] as usize;
+        std::str::from_utf8(&data[start..end]).unwrap_or_default()
+    }
+
+    /// Get the fixed-size record for a concept.
+    pub fn concept_record(&self, concept: u32) -> &[u32] {
+        let start = concept as usize * CONCEPT_RECORD_LEN;
+        &self.u32s(Section::Concepts)[start..start + CONCEPT_RECORD_LEN]
+    }
+
+    /// Get the records for a concept from a list table. The returned slice contains
+    /// `table.stride` values for each record.
+    pub fn list(&self, table: ListTable, concept: u32) -> &[u32] {
+        let offsets = self.u32s(table.offsets);
+        let start = offsets[concept as usize] as usize * table.stride;
+        let end = offsets[concept as usize + 1] as usize * table.stride;
+        &self.u32s(table.data)[start..end]
+    }
+
+    /// Get a single record from a list table, by its position among the records of all the
+    /// concepts.
+    pub fn record(&self, table: ListTable, index: u32) -> &[u32] {
+        let start = index as usize * table.stride;
+        &self.u32s(table.data)[start..start + table.stride]
+    }
+
+    /// Get the records at `offset` in a section that stores groups of records, each starting
+    /// with the number of records in the group. The offsets come from an FST.
+    pub fn counted_records(&self, section: Section, offset: u64, record_len: usize) -> &[u32] {
+        let data = self.u32s(section);
+        let offset = offset as usize;
+        let count = data[offset] as usize;
+        &data[offset + 1..offset + 1 + count * record_len]
+    }
+
+    /// Find the concept that a record in a list table belongs to.
+    pub fn record_concept(&self, table: ListTable, index: u32) -> u32 {
+        let offsets = self.u32s(table.offsets);
+        (offsets.partition_point(|&offset| offset <= index) - 1) as u32
+    }
+}
+
+/// Check that a list of offsets never decreases, so that along with a check of the last offset,
+/// every range between neighbouring offsets is within the data.
+fn sorted<T: PartialOrd>(offsets: &[T]) -> bool {
+    offsets.windows(2).all(|w| w[0] <= w[1])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Interns strings for the string pool.
+#[derive(Default)]
+pub(crate) struct StringPool {
+    ids: HashMap<SmolStr, u32>,
+    offsets: Vec<u64>,
+    data: Vec<u8>,
+}
+
+impl StringPool {
+    pub fn new() -> StringPool {
+        StringPool {
+            ids: HashMap::new(),
+            offsets: vec![0],
+            data: Vec::new(),
+        }
+    }
+
+    /// Get the ID for a string, adding it to the pool if it isn't there already.
+    pub fn intern(&mut self, s: &str) -> u32 {
+        if let Some(id) = self.ids.get(s) {
+            return *id;
+        }
+
+        let id = (self.offsets.len() - 1) as u32;
+        self.data.extend_from_slice(s.as_bytes());
+        self.offsets.push(self.data.len() as u64);
+        self.ids.insert(SmolStr::from(s), id);
+        id
+    }
+
+    pub fn write<W: Write + Seek>(self, writer: &mut StoreWriter<W>) -> Result<()> {
+        writer.write_section(Section::StringOffsets, bytemuck::cast_slice(&self.offsets))?;
+        writer.write_section(Section::StringData, &self.data)
+    }
+}
+
+/// Builds a list table, one concept at a time.
+pub(crate) struct ListBuilder {
+    table: ListTable,
+    offsets: Vec<u32>,
+    data: Vec<u32>,
+}
+
+impl ListBuilder {
+    pub fn new(table: ListTable) -> ListBuilder {
+        ListBuilder {
+            table,
+            offsets: vec![0],
+            data: Vec::new(),
+        }
+    }
+
+    /// Add a record to the current concept.
+    pub fn push(&mut self, record: &[u32]) {
+        debug_assert_eq!(record.len(), self.table.stride);
+        self.data.extend_from_slice(record);
+    }
+
+    /// Finish the current concept and move on to the next one.
+    pub fn finish_concept(&mut self) {
+        self.offsets
+            .push((self.data.len() / self.table.stride) as u32);
+    }
+
+    pub fn write<W: Write + Seek>(self, writer: &mut StoreWriter<W>) -> Result<()> {
+        writer.write_section(self.table.offsets, bytemuck::cast_slice(&self.offsets))?;
+        writer.write_section(self.table.data, bytemuck::cast_slice(&self.data))
+    }
+}
+
+pub(crate) struct StoreWriter<W: Write + Seek> {
+    writer: W,
+    position: u64,
+    sections: Vec<(Section, u64, u64)>,
+}
+
+impl<W: Write + Seek> StoreWriter<W> {
+    pub fn new(mut writer: W) -> Result<StoreWriter<W>> {
+        // Write a placeholder header. The real one is written in `finish`.
+        writer.write_all(&[0u8; HEADER_LEN as usize])?;
+        Ok(StoreWriter {
+            writer,
+            position: HEADER_LEN,
+            sections: Vec::new(),
+        })
+    }
+
+    fn pad(&mut self) -> Result<()> {
+        let padding = (8 - self.position % 8) % 8;
+        self.writer.write_all(&[0u8; 8][0..padding as usize])?;
+        self.position += padding;
+        Ok(())
+    }
+
+    pub fn write_section(&mut self, section: Section, data: &[u8]) -> Result<()> {
+        self.pad()?;
+        self.writer.write_all(data)?;
+        self.sections
+            .push((section, self.position, data.len() as u64));
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.pad()?;
+        let table_offset = self.position;
+        for (section, offset, len) in &self.sections {
+            self.writer.write_all(&(*section as u32).to_le_bytes())?;
+            self.writer.write_all(&[0u8; 4])?;
+            self.writer.write_all(&offset.to_le_bytes())?;
+            self.writer.write_all(&len.to_le_bytes())?;
+        }
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(MAGIC)?;
+        self.writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        self.writer
+            .write_all(&(self.sections.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&table_offset.to_le_bytes())?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut writer = StoreWriter::new(Cursor::new(Vec::new())).unwrap();
+
+        let mut strings = StringPool::new();
+        let cui = strings.intern("C0000001");
+        let name = strings.intern("Something");
+        assert_eq!(strings.intern("C0000001"), cui);
+
+        writer
+            .write_section(Section::Concepts, bytemuck::cast_slice(&[cui, name]))
+            .unwrap();
+        writer.write_section(Section::Postings, &[]).unwrap();
+        writer.write_section(Section::HierarchyPaths, &[]).unwrap();
+        writer.write_section(Section::CuiHistory, &[]).unwrap();
+        writer.write_section(Section::Mappings, &[]).unwrap();
+        strings.write(&mut writer).unwrap();
+
+        for table in REQUIRED_TABLES {
+            let mut list = ListBuilder::new(table);
+            if table.offsets == Section::ParentsOffsets {
+                list.push(&[5]);
+                list.push(&[7]);
+            }
+            if table.offsets == Section::AtomsOffsets {
+                list.push(&[0, 1, 0, 0, 0, 0]);
+                list.push(&[0, 1, 0, 0, 0, 0]);
+            }
+            list.finish_concept();
+            list.write(&mut writer).unwrap();
+        }
+
+        let data = writer.finish().unwrap().into_inner();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.bin");
+        std::fs::write(&path, data).unwrap();
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.num_concepts(), 1);
+        let record = store.concept_record(0);
+        assert_eq!(store.string(record[0]), "C0000001");
+        assert_eq!(store.string(record[1]), "Something");
+        assert_eq!(store.list(PARENTS, 0), &[5, 7]);
+        assert!(store.list(CHILDREN, 0).is_empty());
+        assert!(!store.has_table(ANCESTORS));
+        assert_eq!(store.record(ATOMS, 1)[1], 1);
+        assert_eq!(store.record_concept(ATOMS, 1), 0);
+    }
+
+    #[test]
+    fn unsorted_offsets() {
+        let mut writer = StoreWriter::new(Cursor::new(Vec::new())).unwrap();
+        let mut strings = StringPool::new();
+        let cui = strings.intern("C0000001");
+        writer
+            .write_section(Section::Concepts, bytemuck::cast_slice(&[cui, cui]))
+            .unwrap();
+        writer.write_section(Section::Postings, &[]).unwrap();
+        writer.write_section(Section::HierarchyPaths, &[]).unwrap();
+        writer.write_section(Section::CuiHistory, &[]).unwrap();
+        writer.write_section(Section::Mappings, &[]).unwrap();
+        strings.write(&mut writer).unwrap();
+
+        for table in REQUIRED_TABLES {
+            if table.offsets == Section::ParentsOffsets {
+                // The last offset matches the data, but the first is past it.
+                writer
+                    .write_section(table.offsets, bytemuck::cast_slice(&[2u32, 1]))
+                    .unwrap();
+                writer
+                    .write_section(table.data, bytemuck::cast_slice(&[5u32]))
+                    .unwrap();
+            } else {
+                let mut list = ListBuilder::new(table);
+                list.finish_concept();
+                list.write(&mut writer).unwrap();
+            }
+        }
+
+        let data = writer.finish().unwrap().into_inner();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.bin");
+        std::fs::write(&path, data).unwrap();
+
+        let err = Store::open(&path).err().unwrap();
+        assert_eq!(err.to_string(), "Index table ParentsData is invalid");
+    }
+}
@@ -1,5 +1,10 @@
+pub mod annotate;
 pub mod extract;
 pub mod files;
 pub mod index;
+pub mod server;
+
+#[cfg(test)]
+mod test_data;
 
 pub use index::Concept;
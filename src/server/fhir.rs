@@ -0,0 +1,684 @@
+//! A FHIR R4 terminology service facade over the index.
+//!
+//! Each UMLS source is exposed as a code system, identified by its canonical FHIR URI where one
+//! exists (see [system_uri]) and by `http://www.nlm.nih.gov/research/umls/<sab>` otherwise. The
+//! Metathesaurus itself is the code system `http://www.nlm.nih.gov/research/umls`, with CUIs as
+//! its codes.
+//!
+//! The operations accept their parameters either in the query string of a GET request, or as a
+//! `Parameters` resource in the body of a POST request.
+//!
+//! - `CodeSystem/$lookup` - `system` and `code`, or `coding`
+//! - `CodeSystem/$validate-code` - `url` (or `system`) and `code`, or `coding`, and an optional
+//!   `display` to check
+//! - `CodeSystem/$subsumes` - `system`, `codeA` and `codeB`, or `codingA` and `codingB`
+//! - `ConceptMap/$translate` - `system` and `code`, or `coding`, and an optional `targetsystem`.
+//!   Codes are translated to the other codes of the concepts that they belong to.
+
+use std::borrow::Cow;
+
+use ahash::{HashSet, HashSetExt};
+use serde_json::{json, Value};
+use smol_str::SmolStr;
+
+use super::{Query, Reply};
+use crate::index::{Atom, ConceptRef, Index, Suppress, TraversalOptions};
+
+const UMLS_SYSTEM: &str = "http://www.nlm.nih.gov/research/umls";
+
+/// Sources that have a canonical URI in FHIR.
+const KNOWN_SYSTEMS: &[(&str, &str)] = &[
+    ("SNOMEDCT_US", "http://snomed.info/sct"),
+    ("LNC", "http://loinc.org"),
+    ("RXNORM", "http://www.nlm.nih.gov/research/umls/rxnorm"),
+    ("ICD10CM", "http://hl7.org/fhir/sid/icd-10-cm"),
+    ("ICD10", "http://hl7.org/fhir/sid/icd-10"),
+    ("ICD9CM", "http://hl7.org/fhir/sid/icd-9-cm"),
+    ("CPT", "http://www.ama-assn.org/go/cpt"),
+    ("CVX", "http://hl7.org/fhir/sid/cvx"),
+    ("HPO", "http://human-phenotype-ontology.org"),
+];
+
+/// The FHIR code system URI for a UMLS source.
+pub fn system_uri(source: &str) -> Cow<'static, str> {
+    KNOWN_SYSTEMS
+        .iter()
+        .find(|(sab, _)| *sab == source)
+        .map(|(_, uri)| Cow::Borrowed(*uri))
+        .unwrap_or_else(|| Cow::Owned(format!("{UMLS_SYSTEM}/{}", source.to_lowercase())))
+}
+
+/// A code system that codes can be looked up in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CodeSystem {
+    /// The Metathesaurus itself, where the codes are CUIs.
+    Umls,
+    Source(SmolStr),
+}
+
+impl CodeSystem {
+    fn from_uri(uri: &str) -> Option<CodeSystem> {
+        let uri = uri.trim_end_matches('/');
+        if uri == UMLS_SYSTEM {
+            return Some(CodeSystem::Umls);
+        }
+
+        if let Some((sab, _)) = KNOWN_SYSTEMS.iter().find(|(_, u)| *u == uri) {
+            return Some(CodeSystem::Source(SmolStr::from(*sab)));
+        }
+
+        uri.strip_prefix(UMLS_SYSTEM)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|sab| !sab.is_empty() && !sab.contains('/'))
+            .map(|sab| CodeSystem::Source(sab.to_uppercase().into()))
+    }
+
+    fn uri(&self) -> Cow<'static, str> {
+        match self {
+            CodeSystem::Umls => Cow::Borrowed(UMLS_SYSTEM),
+            CodeSystem::Source(sab) => system_uri(sab),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            CodeSystem::Umls => "UMLS",
+            CodeSystem::Source(sab) => sab,
+        }
+    }
+
+    /// Find the concepts that a code in this system belongs to.
+    fn concepts<'a>(&self, index: &'a Index, code: &str) -> Vec<ConceptRef<'a>> {
+        let ids = match self {
+            CodeSystem::Umls => index.find_cui(code).into_iter().collect(),
+            CodeSystem::Source(sab) => index.find_by_code(sab, code),
+        };
+
+        ids.into_iter().map(|id| index.concept(id)).collect()
+    }
+
+    /// The atoms of a concept that belong to a code in this system.
+    fn atoms(&self, concept: ConceptRef<'_>, code: &str) -> Vec<Atom> {
+        match self {
+            CodeSystem::Umls => concept.atoms().collect(),
+            CodeSystem::Source(sab) => concept
+                .atoms()
+                .filter(|a| a.source == *sab && a.code == code)
+                .collect(),
+        }
+    }
+
+    /// The display string for a code. This is the first current preferred atom for the code, or
+    /// the concept's preferred name when there is no such atom.
+    fn display(&self, concept: ConceptRef<'_>, code: &str) -> SmolStr {
+        if self == &CodeSystem::Umls {
+            return concept.preferred_name().into();
+        }
+
+        let atoms = self.atoms(concept, code);
+        atoms
+            .iter()
+            .find(|a| a.preferred && a.suppress == Suppress::No)
+            .or_else(|| atoms.iter().find(|a| a.suppress == Suppress::No))
+            .or_else(|| atoms.first())
+            .map(|a| a.string.clone())
+            .unwrap_or_else(|| concept.preferred_name().into())
+    }
+
+    /// This system's codes for a concept.
+    fn codes(&self, concept: ConceptRef<'_>) -> Vec<SmolStr> {
+        match self {
+            CodeSystem::Umls => vec![concept.cui().into()],
+            CodeSystem::Source(sab) => concept
+                .codes()
+                .filter(|c| c.source == *sab)
+                .map(|c| c.code)
+                .collect(),
+        }
+    }
+}
+
+/// A response containing a FHIR resource.
+fn resource(status: u16, body: Value) -> Reply {
+    Reply {
+        status,
+        content_type: "application/fhir+json",
+        body,
+    }
+}
+
+/// An error response, as an OperationOutcome resource.
+fn outcome(status: u16, code: &str, message: impl Into<String>) -> Reply {
+    resource(
+        status,
+        json!({
+            "resourceType": "OperationOutcome",
+            "issue": [{
+                "severity": "error",
+                "code": code,
+                "diagnostics": message.into(),
+            }],
+        }),
+    )
+}
+
+fn parameters(parameter: Vec<Value>) -> Reply {
+    resource(
+        200,
+        json!({
+            "resourceType": "Parameters",
+            "parameter": parameter,
+        }),
+    )
+}
+
+/// A parameter with a value. `kind` is the FHIR type of the value, such as `String` or `Code`.
+fn param(name: &str, kind: &str, value: impl Into<Value>) -> Value {
+    let mut param = serde_json::Map::new();
+    param.insert("name".into(), name.into());
+    param.insert(format!("value{kind}"), value.into());
+    Value::Object(param)
+}
+
+/// A parameter made of other parameters.
+fn parts(name: &str, part: Vec<Value>) -> Value {
+    json!({ "name": name, "part": part })
+}
+
+fn coding(system: &CodeSystem, code: &str, display: &str) -> Value {
+    json!({
+        "system": system.uri(),
+        "code": code,
+        "display": display,
+    })
+}
+
+/// Convert the parameters from a `Parameters` resource into a query. Coding values are
+/// flattened into `name.system`, `name.code` and `name.display`.
+fn query_from_parameters(body: &[u8]) -> Result<Query, Reply> {
+    let body: Value = serde_json::from_slice(body)
+        .map_err(|e| outcome(400, "invalid", format!("Invalid request body: {e}")))?;
+    if body["resourceType"] != "Parameters" {
+        return Err(outcome(
+            400,
+            "invalid",
+            "The request body must be a Parameters resource",
+        ));
+    }
+
+    let mut query = Vec::new();
+    for param in body["parameter"].as_array().into_iter().flatten() {
+        let Some(name) = param["name"].as_str() else {
+            continue;
+        };
+
+        let Some((_, value)) = param
+            .as_object()
+            .into_iter()
+            .flatten()
+            .find(|(key, _)| key.starts_with("value"))
+        else {
+            continue;
+        };
+
+        match value {
+            Value::String(s) => query.push((name.to_string(), s.clone())),
+            Value::Bool(b) => query.push((name.to_string(), b.to_string())),
+            Value::Object(coding) => {
+                for field in ["system", "code", "display"] {
+                    if let Some(v) = coding.get(field).and_then(|v| v.as_str()) {
+                        query.push((format!("{name}.{field}"), v.to_string()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Query(query))
+}
+
+/// Get a system and code from either separate parameters or a coding parameter.
+fn coded<'q>(
+    query: &'q Query,
+    system_params: &[&str],
+    code_param: &str,
+    coding_param: &str,
+) -> Result<(CodeSystem, &'q str), Reply> {
+    let coding_system = format!("{coding_param}.system");
+    let coding_code = format!("{coding_param}.code");
+
+    let system = system_params
+        .iter()
+        .find_map(|p| query.get(p))
+        .or_else(|| query.get(&coding_system));
+    let code = query.get(code_param).or_else(|| query.get(&coding_code));
+
+    let (Some(system), Some(code)) = (system, code) else {
+        return Err(outcome(
+            400,
+            "required",
+            format!(
+                "Missing parameters {} and {code_param}, or {coding_param}",
+                system_params[0]
+            ),
+        ));
+    };
+
+    let system = CodeSystem::from_uri(system)
+        .ok_or_else(|| outcome(404, "not-found", format!("Unknown code system {system}")))?;
+    Ok((system, code))
+}
+
+/// Handle a request for an operation. `segments` is the path after the `fhir` prefix. For POST
+/// requests, `body` contains the request body.
+pub(super) fn handle(index: &Index, segments: &[&str], query: Query, body: Option<&[u8]>) -> Reply {
+    let query = match body {
+        Some(body) => match query_from_parameters(body) {
+            Ok(mut params) => {
+                params.0.extend(query.0);
+                params
+            }
+            Err(e) => return e,
+        },
+        None => query,
+    };
+
+    let result = match segments {
+        ["CodeSystem", "$lookup"] => lookup(index, &query),
+        ["CodeSystem", "$validate-code"] => validate_code(index, &query),
+        ["CodeSystem", "$subsumes"] => subsumes(index, &query),
+        ["ConceptMap", "$translate"] => translate(index, &query),
+        _ => Err(outcome(404, "not-supported", "Unknown operation")),
+    };
+
+    result.unwrap_or_else(|e| e)
+}
+
+fn lookup(index: &Index, query: &Query) -> Result<Reply, Reply> {
+    let (system, code) = coded(query, &["system"], "code", "coding")?;
+    let concepts = system.concepts(index, code);
+    let Some(&concept) = concepts.first() else {
+        return Err(outcome(
+            404,
+            "not-found",
+            format!("Code {code} not found in {}", system.uri()),
+        ));
+    };
+
+    let mut output = vec![
+        param("name", "String", system.name()),
+        param("display", "String", system.display(concept, code).as_str()),
+    ];
+
+    let atoms = concepts
+        .iter()
+        .flat_map(|&c| system.atoms(c, code))
+        .collect::<Vec<_>>();
+    for atom in &atoms {
+        output.push(parts(
+            "designation",
+            vec![param("value", "String", atom.string.as_str())],
+        ));
+    }
+
+    let property = |code: &str, kind: &str, value: Value| {
+        parts(
+            "property",
+            vec![param("code", "Code", code), param("value", kind, value)],
+        )
+    };
+
+    let inactive = !atoms.is_empty() && atoms.iter().all(|a| a.suppress != Suppress::No);
+    output.push(property("inactive", "Boolean", inactive.into()));
+
+    for &concept in &concepts {
+        output.push(property("cui", "Code", concept.cui().into()));
+    }
+
+    let related = [
+        (
+            "parent",
+            concepts
+                .iter()
+                .flat_map(|c| c.parents())
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "child",
+            concepts.iter().flat_map(|c| c.children()).collect(),
+        ),
+    ];
+    for (name, ids) in related {
+        let mut seen = HashSet::new();
+        for &id in ids {
+            for related_code in system.codes(index.concept(id)) {
+                if seen.insert(related_code.clone()) {
+                    output.push(property(name, "Code", related_code.as_str().into()));
+                }
+            }
+        }
+    }
+
+    Ok(parameters(output))
+}
+
+fn validate_code(index: &Index, query: &Query) -> Result<Reply, Reply> {
+    let (system, code) = match coded(query, &["url", "system"], "code", "coding") {
+        Ok(coded) => coded,
+        // An unknown code system is a validation failure rather than an error.
+        Err(e) if e.status == 404 => {
+            return Ok(parameters(vec![
+                param("result", "Boolean", false),
+                param("message", "String", "Unknown code system"),
+            ]))
+        }
+        Err(e) => return Err(e),
+    };
+
+    let concepts = system.concepts(index, code);
+    let Some(&concept) = concepts.first() else {
+        return Ok(parameters(vec![
+            param("result", "Boolean", false),
+            param(
+                "message",
+                "String",
+                format!("Code {code} not found in {}", system.uri()),
+            ),
+        ]));
+    };
+
+    let display = system.display(concept, code);
+    let mut output = vec![];
+
+    let requested_display = query.get("display").or_else(|| query.get("coding.display"));
+    let display_valid = requested_display
+        .map(|requested| {
+            requested.eq_ignore_ascii_case(&display)
+                || concepts
+                    .iter()
+                    .flat_map(|&c| system.atoms(c, code))
+                    .any(|a| a.string.eq_ignore_ascii_case(requested))
+        })
+        .unwrap_or(true);
+
+    output.push(param("result", "Boolean", display_valid));
+    if !display_valid {
+        output.push(param(
+            "message",
+            "String",
+            format!(
+                "The display \"{}\" is not a valid display for code {code}",
+                requested_display.unwrap_or_default()
+            ),
+        ));
+    }
+    output.push(param("display", "String", display.as_str()));
+
+    Ok(parameters(output))
+}
+
+fn subsumes(index: &Index, query: &Query) -> Result<Reply, Reply> {
+    let (system_a, code_a) = coded(query, &["system"], "codeA", "codingA")?;
+    let (system_b, code_b) = coded(query, &["system"], "codeB", "codingB")?;
+    if system_a != system_b {
+        return Err(outcome(
+            400,
+            "invalid",
+            "codingA and codingB must be from the same code system",
+        ));
+    }
+
+    let lookup = |code: &str| {
+        let ids = system_a
+            .concepts(index, code)
+            .iter()
+            .map(|c| c.id())
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            Err(outcome(
+                404,
+                "not-found",
+                format!("Code {code} not found in {}", system_a.uri()),
+            ))
+        } else {
+            Ok(ids)
+        }
+    };
+
+    let a = lookup(code_a)?;
+    let b = lookup(code_b)?;
+
+    let result = if a.iter().any(|id| b.contains(id)) {
+        "equivalent"
+    } else if has_ancestor(index, &b, &a) {
+        "subsumes"
+    } else if has_ancestor(index, &a, &b) {
+        "subsumed-by"
+    } else {
+        "not-subsumed"
+    };
+
+    Ok(parameters(vec![param("outcome", "Code", result)]))
+}
+
+/// Check if any of `concepts` is a descendant of any of `ancestors`.
+fn has_ancestor(index: &Index, concepts: &[u32], ancestors: &[u32]) -> bool {
+    let options = TraversalOptions::default();
+    concepts
+        .iter()
+        .any(|&id| ancestors.iter().any(|&a| index.is_a(id, a, &options)))
+}
+
+fn translate(index: &Index, query: &Query) -> Result<Reply, Reply> {
+    let (system, code) = coded(query, &["system"], "code", "coding")?;
+    let target = query
+        .get("targetsystem")
+        .map(|uri| {
+            CodeSystem::from_uri(uri)
+                .ok_or_else(|| outcome(404, "not-found", format!("Unknown code system {uri}")))
+        })
+        .transpose()?;
+
+    let mut matches = Vec::new();
+    for concept in system.concepts(index, code) {
+        let mut targets = concept
+            .codes()
+            .map(|c| (CodeSystem::Source(c.source), c.code))
+            .collect::<Vec<_>>();
+        targets.push((CodeSystem::Umls, concept.cui().into()));
+
+        for (target_system, target_code) in targets {
+            let wanted = match &target {
+                Some(target) => target == &target_system,
+                None => target_system != system,
+            };
+            if !wanted || (target_system == system && target_code == code) {
+                continue;
+            }
+
+            let display = target_system.display(concept, &target_code);
+            matches.push(parts(
+                "match",
+                vec![
+                    param("equivalence", "Code", "equivalent"),
+                    param(
+                        "concept",
+                        "Coding",
+                        coding(&target_system, &target_code, &display),
+                    ),
+                ],
+            ));
+        }
+    }
+
+    let mut output = vec![param("result", "Boolean", !matches.is_empty())];
+    if matches.is_empty() {
+        output.push(param(
+            "message",
+            "String",
+            format!("No translations found for code {code}"),
+        ));
+    }
+    output.extend(matches);
+
+    Ok(parameters(output))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpStream},
+        sync::{Arc, OnceLock},
+    };
+
+    use serde_json::{json, Value};
+
+    use crate::{server::Server, test_data::TestData};
+
+    /// Start a server for the test data, shared between all the tests.
+    fn server() -> SocketAddr {
+        static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+        *ADDR.get_or_init(|| {
+            let data = TestData::new();
+            let server = Server::bind("127.0.0.1:0").unwrap();
+            let addr = server.local_addr().unwrap();
+            std::thread::spawn(move || {
+                let TestData { dir: _dir, index } = data;
+                server.run(Arc::new(index), 2).unwrap();
+            });
+            addr
+        })
+    }
+
+    fn request(method: &str, path: &str, body: Option<Value>) -> (u16, String, Value) {
+        let body = body.map(|b| b.to_string()).unwrap_or_default();
+        let mut stream = TcpStream::connect(server()).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        let content_type = head
+            .lines()
+            .find_map(|l| l.strip_prefix("Content-Type: "))
+            .unwrap_or_default()
+            .to_string();
+        (status, content_type, serde_json::from_str(body).unwrap())
+    }
+
+    fn get(path: &str) -> Value {
+        let (status, content_type, body) = request("GET", path, None);
+        assert_eq!(status, 200, "{body}");
+        assert_eq!(content_type, "application/fhir+json");
+        assert_eq!(body["resourceType"], "Parameters");
+        body
+    }
+
+    /// The values of every parameter with the given name.
+    fn values<'a>(params: &'a Value, name: &str) -> Vec<&'a Value> {
+        params["parameter"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|p| p["name"] == name)
+            .collect()
+    }
+
+    fn property<'a>(params: &'a Value, code: &str) -> Vec<&'a Value> {
+        values(params, "property")
+            .into_iter()
+            .filter(|p| p["part"][0]["valueCode"] == code)
+            .map(|p| &p["part"][1])
+            .collect()
+    }
+
+    #[test]
+    fn lookup() {
+        let params = get("/fhir/CodeSystem/$lookup?system=http://snomed.info/sct&code=44054006");
+        assert_eq!(values(&params, "name")[0]["valueString"], "SNOMEDCT_US");
+        assert_eq!(
+            values(&params, "display")[0]["valueString"],
+            "Diabetes mellitus type 2"
+        );
+        assert_eq!(values(&params, "designation").len(), 3);
+        assert_eq!(property(&params, "cui")[0]["valueCode"], "C0011860");
+        assert_eq!(property(&params, "parent")[0]["valueCode"], "73211009");
+        assert_eq!(property(&params, "inactive")[0]["valueBoolean"], false);
+
+        let (status, _, body) = request(
+            "GET",
+            "/fhir/CodeSystem/$lookup?system=http://snomed.info/sct&code=1",
+            None,
+        );
+        assert_eq!(status, 404);
+        assert_eq!(body["resourceType"], "OperationOutcome");
+    }
+
+    #[test]
+    fn lookup_post() {
+        let (status, _, params) = request(
+            "POST",
+            "/fhir/CodeSystem/$lookup",
+            Some(json!({
+                "resourceType": "Parameters",
+                "parameter": [{
+                    "name": "coding",
+                    "valueCoding": {"system": "http://hl7.org/fhir/sid/icd-10-cm", "code": "I10"},
+                }],
+            })),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(
+            values(&params, "display")[0]["valueString"],
+            "Essential (primary) hypertension"
+        );
+    }
+
+    #[test]
+    fn validate_code() {
+        let params = get("/fhir/CodeSystem/$validate-code?url=http://snomed.info/sct&code=44054006&display=Type%202%20diabetes");
+        assert_eq!(values(&params, "result")[0]["valueBoolean"], true);
+
+        let params = get("/fhir/CodeSystem/$validate-code?url=http://snomed.info/sct&code=44054006&display=Hypertension");
+        assert_eq!(values(&params, "result")[0]["valueBoolean"], false);
+
+        let params = get("/fhir/CodeSystem/$validate-code?url=http://snomed.info/sct&code=123456");
+        assert_eq!(values(&params, "result")[0]["valueBoolean"], false);
+    }
+
+    #[test]
+    fn translate() {
+        let params = get("/fhir/ConceptMap/$translate?system=http://snomed.info/sct&code=44054006&targetsystem=http://hl7.org/fhir/sid/icd-10-cm");
+        assert_eq!(values(&params, "result")[0]["valueBoolean"], true);
+        let matches = values(&params, "match");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0]["part"][1]["valueCoding"]["code"], "E11");
+
+        let params = get("/fhir/ConceptMap/$translate?system=http://hl7.org/fhir/sid/icd-10-cm&code=E11&targetsystem=http://www.nlm.nih.gov/research/umls");
+        let matches = values(&params, "match");
+        assert_eq!(matches[0]["part"][1]["valueCoding"]["code"], "C0011860");
+    }
+
+    #[test]
+    fn subsumes() {
+        let outcome = |a: &str, b: &str| {
+            let params = get(&format!(
+                "/fhir/CodeSystem/$subsumes?system=http://snomed.info/sct&codeA={a}&codeB={b}"
+            ));
+            values(&params, "outcome")[0]["valueCode"].clone()
+        };
+
+        assert_eq!(outcome("64572001", "44054006"), "subsumes");
+        assert_eq!(outcome("44054006", "64572001"), "subsumed-by");
+        assert_eq!(outcome("44054006", "44054006"), "equivalent");
+        assert_eq!(outcome("44054006", "38341003"), "not-subsumed");
+    }
+}
@@ -0,0 +1,845 @@
+//! A small HTTP server exposing the index as a JSON API.
+//!
+//! All endpoints use GET and return JSON, except for the FHIR operations under `/fhir`, which
+//! also accept POST and return FHIR resources. See [fhir] for details.
+//!
+//! - `/search?q=...` - Ranked search. Also accepts `fuzzy`, `threshold`, `limit`, and any number
+//!   of `source` and `type` parameters, matching [SearchOptions]. Long queries are rejected with
+//!   413.
+//! - `/search/exact?q=...` - Exact match search, ignoring case. Long queries are rejected with 413.
+//! - `/search/regex?q=...` - Regex search. Long or complex patterns are rejected with 413, as are
+//!   patterns that match too many concepts. See [RegexLimits].
+//! - `/concepts/{cui}` - A concept, with its semantic types, definitions, codes and atoms
+//! - `/concepts/{cui}/codes` - The codes for a concept, optionally filtered by `source`
+//! - `/concepts/{cui}/parents` - The parents of a concept
+//! - `/concepts/{cui}/children` - The children of a concept
+//! - `/concepts/{cui}/ancestors` and `/concepts/{cui}/descendants` - All the ancestors or
+//!   descendants of a concept with their depth, optionally limited by `max_depth` and filtered to
+//!   a relationship `kind` of `parent`, `broader`, or `all`. Traversals that find more than
+//!   [MAX_TRAVERSAL_RESULTS] concepts are rejected with 413.
+//! - `/concepts/{cui}/relations` - The relationships of a concept from MRREL with their
+//!   attributes, optionally filtered by any number of `rel`, `rela` and `source` parameters
+//! - `/concepts/{cui}/attributes` - The MRSAT attributes of a concept that were included in the
+//!   index, optionally filtered by `name`
+//! - `/attributes?name=...&value=...` - The concepts with an attribute value, such as an NDC
+//! - `/mappings?source=...&code=...` - The source-asserted mappings from a code, optionally
+//!   filtered by a `target` source
+//! - `/concepts/{cui}/downstream_codes` - The codes of a concept and all its descendants,
+//!   optionally filtered by `source`
+//! - `/fhir/CodeSystem/$lookup`, `/fhir/CodeSystem/$validate-code`, `/fhir/CodeSystem/$subsumes`
+//!   and `/fhir/ConceptMap/$translate` - FHIR R4 terminology operations. POST bodies larger than
+//!   [MAX_BODY_LEN] bytes are rejected with 413.
+
+pub mod fhir;
+
+use std::{
+    io::Read,
+    net::{SocketAddr, ToSocketAddrs},
+    panic::AssertUnwindSafe,
+    sync::{mpsc, Arc},
+};
+
+use eyre::{eyre, Result};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use serde_json::json;
+use smol_str::SmolStr;
+
+use crate::index::{
+    Atom, ConceptCode, ConceptRef, Definition, GraphNode, Index, RegexLimits, RegexSearchError,
+    RelationFilter, SearchOptions, TraversalOptions,
+};
+
+/// The largest POST body the server accepts, in bytes.
+pub const MAX_BODY_LEN: u64 = 1 << 20;
+
+/// The most concepts the ancestors and descendants endpoints return.
+pub const MAX_TRAVERSAL_RESULTS: usize = 10_000;
+
+/// The parsed query string of a request.
+pub(crate) struct Query(Vec<(String, String)>);
+
+impl Query {
+    fn parse(query: &str) -> Query {
+        Query(
+            form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<SmolStr> {
+        self.0
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| SmolStr::from(v))
+            .collect()
+    }
+
+    pub fn required(&self, key: &str) -> Result<&str, Reply> {
+        self.get(key)
+            .ok_or_else(|| Reply::error(400, format!("Missing parameter {key}")))
+    }
+
+    pub fn parse_value<T: std::str::FromStr>(&self, key: &str) -> Result<Option<T>, Reply> {
+        self.get(key)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| Reply::error(400, format!("Invalid value for {key}")))
+            })
+            .transpose()
+    }
+}
+
+/// A response to a request.
+#[derive(Debug)]
+pub struct Reply {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: serde_json::Value,
+}
+
+impl Reply {
+    pub fn ok(body: impl Serialize) -> Reply {
+        Reply {
+            status: 200,
+            content_type: "application/json",
+            body: serde_json::to_value(body).unwrap_or_default(),
+        }
+    }
+
+    pub fn error(status: u16, message: impl Into<String>) -> Reply {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: json!({ "error": message.into() }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ConceptSummary {
+    cui: SmolStr,
+    preferred_name: SmolStr,
+}
+
+impl From<ConceptRef<'_>> for ConceptSummary {
+    fn from(concept: ConceptRef<'_>) -> Self {
+        ConceptSummary {
+            cui: concept.cui().into(),
+            preferred_name: concept.preferred_name().into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SemanticTypeSummary {
+    tui: SmolStr,
+    name: SmolStr,
+    tree_number: SmolStr,
+}
+
+#[derive(Serialize)]
+struct ConceptDetail {
+    cui: SmolStr,
+    preferred_name: SmolStr,
+    semantic_types: Vec<SemanticTypeSummary>,
+    definitions: Vec<Definition>,
+    codes: Vec<ConceptCode>,
+    atoms: Vec<Atom>,
+}
+
+#[derive(Serialize)]
+struct SearchResult {
+    string: String,
+    cui: SmolStr,
+    preferred_name: SmolStr,
+    score: f32,
+    kind: crate::index::MatchKind,
+}
+
+#[derive(Serialize)]
+struct RelatedConcept {
+    #[serde(flatten)]
+    concept: ConceptSummary,
+    depth: u32,
+}
+
+#[derive(Serialize)]
+struct ConceptRelation {
+    #[serde(flatten)]
+    concept: ConceptSummary,
+    rel: SmolStr,
+    rela: SmolStr,
+    source: SmolStr,
+    group: SmolStr,
+    asserted: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct DownstreamCode {
+    cui: SmolStr,
+    source: SmolStr,
+    code: SmolStr,
+}
+
+/// Handle a GET request for the given path and query string.
+pub fn handle(index: &Index, url: &str) -> Reply {
+    route(index, url, None)
+}
+
+/// Handle a POST request for the given path and query string. Only the FHIR operations accept
+/// POST requests.
+pub fn handle_post(index: &Index, url: &str, body: &[u8]) -> Reply {
+    route(index, url, Some(body))
+}
+
+fn route(index: &Index, url: &str, body: Option<&[u8]>) -> Reply {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let query = Query::parse(query);
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let segments = segments.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+
+    if let ["fhir", rest @ ..] = segments.as_slice() {
+        return fhir::handle(index, rest, query, body);
+    }
+
+    if body.is_some() {
+        return Reply::error(405, "Method not allowed");
+    }
+
+    let result = match segments.as_slice() {
+        ["search"] => search(index, &query),
+        ["search", "exact"] => search_ids(index, &query, |q| search_exact(index, q)),
+        ["search", "regex"] => search_ids(index, &query, |q| search_regex(index, q)),
+        ["concepts", cui] => concept(index, cui),
+        ["concepts", cui, "codes"] => codes(index, cui, &query),
+        ["concepts", cui, "parents"] => related(index, cui, |c| c.parents()),
+        ["concepts", cui, "children"] => related(index, cui, |c| c.children()),
+        ["concepts", cui, "ancestors"] => {
+            traverse(index, cui, &query, MAX_TRAVERSAL_RESULTS, Index::ancestors)
+        }
+        ["concepts", cui, "descendants"] => traverse(
+            index,
+            cui,
+            &query,
+            MAX_TRAVERSAL_RESULTS,
+            Index::descendants,
+        ),
+        ["concepts", cui, "relations"] => relations(index, cui, &query),
+        ["concepts", cui, "attributes"] => attributes(index, cui, &query),
+        ["attributes"] => find_by_attribute(index, &query),
+        ["mappings"] => mappings(index, &query),
+        ["concepts", cui, "downstream_codes"] => downstream_codes(index, cui, &query),
+        _ => Err(Reply::error(404, "Not found")),
+    };
+
+    result.unwrap_or_else(|e| e)
+}
+
+pub(crate) fn lookup_cui<'a>(index: &'a Index, cui: &str) -> Result<ConceptRef<'a>, Reply> {
+    index
+        .find_cui(cui)
+        .map(|id| index.concept(id))
+        .ok_or_else(|| Reply::error(404, format!("Concept {cui} not found")))
+}
+
+fn search(index: &Index, query: &Query) -> Result<Reply, Reply> {
+    let q = query.required("q")?;
+    check_query_len(q)?;
+    let defaults = SearchOptions::default();
+    let options = SearchOptions {
+        max_edits: query.parse_value("fuzzy")?.unwrap_or(defaults.max_edits),
+        score_threshold: query
+            .parse_value("threshold")?
+            .unwrap_or(defaults.score_threshold),
+        limit: query.parse_value("limit")?,
+        sources: query.get_all("source"),
+        semantic_types: query.get_all("type"),
+    };
+
+    let hits = index
+        .search_ranked(q, &options)
+        .map_err(|e| Reply::error(400, e.to_string()))?;
+
+    let results = hits
+        .into_iter()
+        .map(|hit| SearchResult {
+            preferred_name: index.concept(hit.concept_id).preferred_name().into(),
+            string: hit.string,
+            cui: hit.cui,
+            score: hit.score,
+            kind: hit.kind,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Reply::ok(results))
+}
+
+fn search_ids(
+    index: &Index,
+    query: &Query,
+    search: impl FnOnce(&str) -> Result<Vec<u64>, Reply>,
+) -> Result<Reply, Reply> {
+    let q = query.required("q")?;
+    let ids = search(q)?;
+    let results = ids
+        .into_iter()
+        .map(|id| ConceptSummary::from(index.concept_id(id)))
+        .collect::<Vec<_>>();
+    Ok(Reply::ok(results))
+}
+
+/// Searches build automatons from the query, so limit its length the same way as a regex.
+fn check_query_len(q: &str) -> Result<(), Reply> {
+    let max_len = RegexLimits::default().max_pattern_len;
+    if q.len() > max_len {
+        return Err(Reply::error(
+            413,
+            format!("The query is longer than {max_len} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+fn search_exact(index: &Index, q: &str) -> Result<Vec<u64>, Reply> {
+    check_query_len(q)?;
+    index
+        .search(q)
+        .map_err(|e| Reply::error(400, e.to_string()))
+}
+
+fn search_regex(index: &Index, q: &str) -> Result<Vec<u64>, Reply> {
+    index
+        .search_regex_limited(q, &RegexLimits::default())
+        .map_err(|e| {
+            let status = match e {
+                RegexSearchError::Invalid(_) => 400,
+                _ => 413,
+            };
+            Reply::error(status, e.to_string())
+        })
+}
+
+fn concept(index: &Index, cui: &str) -> Result<Reply, Reply> {
+    let concept = lookup_cui(index, cui)?;
+    let detail = ConceptDetail {
+        cui: concept.cui().into(),
+        preferred_name: concept.preferred_name().into(),
+        semantic_types: concept
+            .types()
+            .filter_map(|t| index.semantic_types.get(&t))
+            .map(|t| SemanticTypeSummary {
+                tui: t.tui.clone(),
+                name: t.name.clone(),
+                tree_number: t.tree_number.clone(),
+            })
+            .collect(),
+        definitions: concept.definitions().collect(),
+        codes: concept.codes().collect(),
+        atoms: concept.atoms().collect(),
+    };
+
+    Ok(Reply::ok(detail))
+}
+
+fn codes(index: &Index, cui: &str, query: &Query) -> Result<Reply, Reply> {
+    let concept = lookup_cui(index, cui)?;
+    let sources = query.get_all("source");
+    let codes = concept
+        .codes()
+        .filter(|c| sources.is_empty() || sources.contains(&c.source))
+        .collect::<Vec<_>>();
+    Ok(Reply::ok(codes))
+}
+
+fn related<'a>(
+    index: &'a Index,
+    cui: &str,
+    list: impl FnOnce(ConceptRef<'a>) -> &'a [u32],
+) -> Result<Reply, Reply> {
+    let concept = lookup_cui(index, cui)?;
+    let related = list(concept)
+        .iter()
+        .map(|&id| ConceptSummary::from(index.concept(id)))
+        .collect::<Vec<_>>();
+    Ok(Reply::ok(related))
+}
+
+fn traverse(
+    index: &Index,
+    cui: &str,
+    query: &Query,
+    max_results: usize,
+    traverse: impl FnOnce(&Index, u32, &TraversalOptions) -> Vec<GraphNode>,
+) -> Result<Reply, Reply> {
+    let concept = lookup_cui(index, cui)?;
+    let options = TraversalOptions {
+        max_depth: query.parse_value("max_depth")?,
+        kinds: query
+            .parse_value("kind")?
+            .unwrap_or(TraversalOptions::default().kinds),
+        // Find one more than the limit to tell whether there were too many.
+        max_results: Some(max_results + 1),
+    };
+
+    let nodes = traverse(index, concept.id(), &options);
+    if nodes.len() > max_results {
+        return Err(Reply::error(
+            413,
+            format!("Found more than {max_results} concepts. Try a smaller max_depth."),
+        ));
+    }
+
+    let nodes = nodes
+        .into_iter()
+        .map(|node| RelatedConcept {
+            concept: ConceptSummary::from(index.concept(node.concept_id)),
+            depth: node.depth,
+        })
+        .collect::<Vec<_>>();
+    Ok(Reply::ok(nodes))
+}
+
+fn relations(index: &Index, cui: &str, query: &Query) -> Result<Reply, Reply> {
+    let concept = lookup_cui(index, cui)?;
+    let filter = RelationFilter {
+        rels: query.get_all("rel"),
+        relas: query.get_all("rela"),
+        sources: query.get_all("source"),
+    };
+
+    let relations = index
+        .relations(concept.id(), &filter)
+        .into_iter()
+        .map(|r| ConceptRelation {
+            concept: ConceptSummary::from(index.concept(r.concept_id)),
+            rel: r.rel,
+            rela: r.rela,
+            source: r.source,
+            group: r.group,
+            asserted: r.asserted,
+        })
+        .collect::<Vec<_>>();
+    Ok(Reply::ok(relations))
+}
+
+fn attributes(index: &Index, cui: &str, query: &Query) -> Result<Reply, Reply> {
+    let concept = lookup_cui(index, cui)?;
+    let names = query.get_all("name");
+    let attributes = concept
+        .attributes()
+        .filter(|a| names.is_empty() || names.contains(&a.name))
+        .collect::<Vec<_>>();
+    Ok(Reply::ok(attributes))
+}
+
+fn find_by_attribute(index: &Index, query: &Query) -> Result<Reply, Reply> {
+    let name = query.required("name")?;
+    let value = query.required("value")?;
+    let concepts = index
+        .find_by_attribute(name, value)
+        .into_iter()
+        .map(|id| ConceptSummary::from(index.concept(id)))
+        .collect::<Vec<_>>();
+    Ok(Reply::ok(concepts))
+}
+
+fn mappings(index: &Index, query: &Query) -> Result<Reply, Reply> {
+    let source = query.required("source")?;
+    let code = query.required("code")?;
+    Ok(Reply::ok(index.mappings(source, code, query.get("target"))))
+}
+
+fn downstream_codes(index: &Index, cui: &str, query: &Query) -> Result<Reply, Reply> {
+    let concept = lookup_cui(index, cui)?;
+    let sources = query.get_all("source");
+    let codes = index
+        .downstream_codes(concept.id(), &sources)
+        .map(|(id, code)| DownstreamCode {
+            cui: index.concept(id as u32).cui().into(),
+            source: code.source,
+            code: code.code,
+        })
+        .collect::<Vec<_>>();
+    Ok(Reply::ok(codes))
+}
+
+/// An HTTP server for the API.
+pub struct Server {
+    server: Arc<tiny_http::Server>,
+}
+
+impl Server {
+    /// Start listening on `address`.
+    pub fn bind(address: impl ToSocketAddrs) -> Result<Server> {
+        let server = tiny_http::Server::http(address).map_err(|e| eyre!(e))?;
+        Ok(Server {
+            server: Arc::new(server),
+        })
+    }
+
+    /// The address that the server is listening on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Handle requests on `threads` worker threads. A request that panics gets a 500 reply,
+    /// and the worker carries on with the next request. This function only returns if a worker
+    /// thread fails anyway, as soon as any of them does.
+    pub fn run(&self, index: Arc<Index>, threads: usize) -> Result<()> {
+        let server = self.server.clone();
+        run_workers(threads, move || {
+            for mut request in server.incoming_requests() {
+                let reply =
+                    std::panic::catch_unwind(AssertUnwindSafe(|| reply_to(&index, &mut request)))
+                        .unwrap_or_else(|_| Reply::error(500, "Internal server error"));
+
+                let body = serde_json::to_vec(&reply.body).unwrap_or_default();
+                let header = tiny_http::Header::from_bytes("Content-Type", reply.content_type)
+                    .expect("valid header");
+                let response = tiny_http::Response::from_data(body)
+                    .with_status_code(reply.status)
+                    .with_header(header);
+
+                // An error here means the client went away, so there's nothing to do.
+                request.respond(response).ok();
+            }
+        })
+    }
+}
+
+fn reply_to(index: &Index, request: &mut tiny_http::Request) -> Reply {
+    match request.method() {
+        tiny_http::Method::Get => handle(index, request.url()),
+        tiny_http::Method::Post => {
+            // Read one byte past the limit to tell whether the body was too large.
+            let mut body = Vec::new();
+            match request
+                .as_reader()
+                .take(MAX_BODY_LEN + 1)
+                .read_to_end(&mut body)
+            {
+                Ok(_) if body.len() as u64 > MAX_BODY_LEN => Reply::error(
+                    413,
+                    format!("The request body is larger than {MAX_BODY_LEN} bytes"),
+                ),
+                Ok(_) => handle_post(index, request.url(), &body),
+                Err(e) => Reply::error(400, e.to_string()),
+            }
+        }
+        _ => Reply::error(405, "Method not allowed"),
+    }
+}
+
+/// Sends a message when the thread that owns it panics.
+struct PanicGuard(mpsc::Sender<()>);
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.send(()).ok();
+        }
+    }
+}
+
+/// Run `work` on `threads` threads. This returns once every thread has finished, or with an
+/// error as soon as any of them panics.
+fn run_workers(threads: usize, work: impl Fn() + Send + Sync + 'static) -> Result<()> {
+    let work = Arc::new(work);
+    let (panicked_tx, panicked_rx) = mpsc::channel();
+    for _ in 0..threads.max(1) {
+        let work = work.clone();
+        let guard = PanicGuard(panicked_tx.clone());
+        std::thread::spawn(move || {
+            let _guard = guard;
+            work();
+        });
+    }
+    drop(panicked_tx);
+
+    // The channel closes without a message if every worker finishes normally.
+    match panicked_rx.recv() {
+        Ok(()) => Err(eyre!("Server worker thread panicked")),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Serve the API on `address`, handling requests on `threads` worker threads. This function
+/// only returns if the server fails to start, or if a worker thread fails.
+pub fn serve(index: Arc<Index>, address: impl ToSocketAddrs, threads: usize) -> Result<()> {
+    Server::bind(address)?.run(index, threads)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        OnceLock,
+    };
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::test_data::TestData;
+
+    fn data() -> &'static TestData {
+        static DATA: OnceLock<TestData> = OnceLock::new();
+        DATA.get_or_init(TestData::new)
+    }
+
+    fn get(url: &str) -> Value {
+        let reply = handle(&data().index, url);
+        assert_eq!(reply.status, 200, "{url}: {}", reply.body);
+        assert_eq!(reply.content_type, "application/json");
+        reply.body
+    }
+
+    fn error(url: &str) -> (u16, String) {
+        let reply = handle(&data().index, url);
+        (
+            reply.status,
+            reply.body["error"].as_str().unwrap().to_string(),
+        )
+    }
+
+    /// The values of `key` in each object of an array.
+    fn field<'a>(body: &'a Value, key: &str) -> Vec<&'a str> {
+        body.as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v[key].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn routing() {
+        assert_eq!(error("/nothing"), (404, "Not found".to_string()));
+        assert_eq!(error("/concepts"), (404, "Not found".to_string()));
+        assert_eq!(
+            error("/concepts/C0011860/nothing"),
+            (404, "Not found".to_string())
+        );
+        assert_eq!(
+            error("/concepts/C9999999"),
+            (404, "Concept C9999999 not found".to_string())
+        );
+        assert_eq!(
+            error("/concepts/C9999999/parents"),
+            (404, "Concept C9999999 not found".to_string())
+        );
+
+        // Trailing slashes are ignored.
+        assert_eq!(get("/concepts/C0011860/")["cui"], "C0011860");
+
+        let reply = handle_post(&data().index, "/concepts/C0011860", b"{}");
+        assert_eq!(reply.status, 405);
+    }
+
+    #[test]
+    fn query_errors() {
+        assert_eq!(error("/search"), (400, "Missing parameter q".to_string()));
+        assert_eq!(
+            error("/search/exact"),
+            (400, "Missing parameter q".to_string())
+        );
+        assert_eq!(
+            error("/search?q=diabetes&limit=many"),
+            (400, "Invalid value for limit".to_string())
+        );
+        assert_eq!(
+            error("/concepts/C0011860/ancestors?kind=sideways"),
+            (400, "Invalid value for kind".to_string())
+        );
+        assert_eq!(
+            error("/attributes?name=NDC"),
+            (400, "Missing parameter value".to_string())
+        );
+        assert_eq!(error("/search/regex?q=(").0, 400);
+        assert_eq!(
+            error(&format!("/search/regex?q={}", "a".repeat(300))).0,
+            413
+        );
+        assert_eq!(
+            error(&format!("/search/exact?q={}", "a".repeat(300))).0,
+            413
+        );
+        assert_eq!(error(&format!("/search?q={}", "a".repeat(300))).0, 413);
+    }
+
+    #[test]
+    fn limits() {
+        let index = &data().index;
+        let query = Query::parse("");
+        let reply = traverse(index, "C0012634", &query, 1, Index::descendants).unwrap_err();
+        assert_eq!(reply.status, 413);
+        let reply = traverse(index, "C0011860", &query, 2, Index::ancestors).unwrap();
+        assert_eq!(reply.body.as_array().unwrap().len(), 2);
+
+        let post = |body: String| {
+            let mut request = tiny_http::TestRequest::new()
+                .with_method(tiny_http::Method::Post)
+                .with_path("/fhir/CodeSystem/$lookup")
+                .with_body(Box::leak(body.into_boxed_str()))
+                .into();
+            reply_to(index, &mut request).status
+        };
+        let too_large = " ".repeat(MAX_BODY_LEN as usize + 1);
+        assert_eq!(post(too_large), 413);
+        assert_ne!(post("{}".to_string()), 413);
+    }
+
+    #[test]
+    fn query_parsing() {
+        // Percent-encoded and plus-encoded spaces in the query, and encoded path segments.
+        let body = get("/search/exact?q=type%202+diabetes");
+        assert_eq!(field(&body, "cui"), ["C0011860"]);
+        assert_eq!(get("/concepts/C00118%36%30")["cui"], "C0011860");
+
+        // Repeated parameters are all used.
+        let body = get("/concepts/C0011860/codes?source=ICD10CM&source=SNOMEDCT_US");
+        assert_eq!(field(&body, "source").len(), 2);
+        let body = get("/concepts/C0011860/codes?source=ICD10CM");
+        assert_eq!(body, json!([{ "source": "ICD10CM", "code": "E11" }]));
+    }
+
+    #[test]
+    fn search() {
+        let body = get("/search?q=type%202%20diabetes&limit=1");
+        assert_eq!(
+            body.as_array().unwrap()[0]
+                .as_object()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            ["cui", "kind", "preferred_name", "score", "string"]
+        );
+        assert_eq!(body[0]["cui"], "C0011860");
+        assert_eq!(body[0]["preferred_name"], "Diabetes mellitus type 2");
+
+        let body = get("/search/exact?q=dm");
+        assert_eq!(
+            body,
+            json!([
+                { "cui": "C0011849", "preferred_name": "Diabetes mellitus" },
+                { "cui": "C0011860", "preferred_name": "Diabetes mellitus type 2" },
+            ])
+        );
+
+        let body = get("/search/regex?q=diabetes%20mellitus.*");
+        assert_eq!(field(&body, "cui"), ["C0011849", "C0011860"]);
+    }
+
+    #[test]
+    fn concept() {
+        let body = get("/concepts/C0011849");
+        assert_eq!(body["cui"], "C0011849");
+        assert_eq!(body["preferred_name"], "Diabetes mellitus");
+        assert_eq!(
+            body["semantic_types"],
+            json!([{
+                "tui": "T047",
+                "name": "Disease or Syndrome",
+                "tree_number": "B2.2.1.2.1",
+            }])
+        );
+        assert_eq!(field(&body["definitions"], "source"), ["NCI", "MSH"]);
+        assert_eq!(
+            field(&body["atoms"], "aui"),
+            ["A0010", "A0011", "A0012", "A0013"]
+        );
+        assert_eq!(body["codes"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn related() {
+        assert_eq!(
+            get("/concepts/C0011849/parents"),
+            json!([{ "cui": "C0012634", "preferred_name": "Disease" }])
+        );
+        assert_eq!(
+            field(&get("/concepts/C0012634/children"), "cui"),
+            ["C0011849", "C0020538"]
+        );
+        assert_eq!(
+            get("/concepts/C0011860/ancestors?max_depth=1"),
+            json!([{ "cui": "C0011849", "preferred_name": "Diabetes mellitus", "depth": 1 }])
+        );
+
+        let body = get("/concepts/C0011860/relations?rela=may_treat");
+        assert_eq!(
+            body,
+            json!([{
+                "cui": "C0025598",
+                "preferred_name": "metformin",
+                "rel": "RO",
+                "rela": "may_treat",
+                "source": "MED-RT",
+                "group": "",
+                "asserted": true,
+            }])
+        );
+    }
+
+    #[test]
+    fn attributes_and_mappings() {
+        assert_eq!(
+            field(&get("/concepts/C0025598/attributes?name=NDC"), "value"),
+            ["00093-1048-01", "00093-1049-01"]
+        );
+        assert_eq!(
+            get("/attributes?name=NDC&value=00093-1049-01"),
+            json!([{ "cui": "C0025598", "preferred_name": "metformin" }])
+        );
+        assert_eq!(get("/attributes?name=NDC&value=none"), json!([]));
+
+        let body = get("/mappings?source=SNOMEDCT_US&code=38341003&target=ICD10CM");
+        assert_eq!(field(&body, "target_code"), ["I15.9", "I10"]);
+        assert_eq!(
+            get("/mappings?source=SNOMEDCT_US&code=38341003&target=MSH"),
+            json!([])
+        );
+
+        let body = get("/concepts/C0011849/downstream_codes?source=ICD10CM");
+        assert_eq!(
+            body,
+            json!([
+                { "cui": "C0011849", "source": "ICD10CM", "code": "E08-E13" },
+                { "cui": "C0011860", "source": "ICD10CM", "code": "E11" },
+            ])
+        );
+    }
+
+    #[test]
+    fn worker_panic() {
+        static STARTED: AtomicUsize = AtomicUsize::new(0);
+        static DONE: AtomicBool = AtomicBool::new(false);
+
+        // The first worker keeps running, so the panic in the second has to be noticed while
+        // the first is still busy.
+        let result = run_workers(2, || {
+            if STARTED.fetch_add(1, Ordering::SeqCst) == 0 {
+                while !DONE.load(Ordering::SeqCst) {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+            } else {
+                panic!("worker failed");
+            }
+        });
+        DONE.store(true, Ordering::SeqCst);
+        assert!(result.is_err());
+
+        assert!(run_workers(3, || {}).is_ok());
+    }
+}
@@ -0,0 +1,254 @@
+//! A tiny UMLS release for tests that need a real index.
+
+use std::{io::Write, path::Path};
+
+use flate2::{write::GzEncoder, Compression};
+use tempfile::TempDir;
+
+use crate::{
+    files::Files,
+    index::{
+        build::{build_index, IndexBuilderOptions},
+        Index, RelationKinds,
+    },
+};
+
+const MRCONSO_COLUMNS: &str =
+    "CUI,LAT,TS,LUI,STT,SUI,ISPREF,AUI,SAUI,SCUI,SDUI,SAB,TTY,CODE,STR,SRL,SUPPRESS,CVF";
+const MRREL_COLUMNS: &str =
+    "CUI1,AUI1,STYPE1,REL,CUI2,AUI2,STYPE2,RELA,RUI,SRUI,SAB,SL,RG,DIR,SUPPRESS,CVF";
+const MRSTY_COLUMNS: &str = "CUI,TUI,STN,STY,ATUI,CVF";
+const MRRANK_COLUMNS: &str = "RANK,SAB,TTY,SUPPRESS";
+const MRHIER_COLUMNS: &str = "CUI,AUI,CXN,PAUI,SAB,RELA,PTR,HCD,CVF";
+const MRSAT_COLUMNS: &str = "CUI,LUI,SUI,METAUI,STYPE,CODE,ATUI,SATUI,ATN,SAB,ATV,SUPPRESS,CVF";
+const MRCUI_COLUMNS: &str = "CUI1,VER,REL,RELA,MAPREASON,CUI2,MAPIN";
+const MRMAP_COLUMNS: &str = "MAPSETCUI,MAPSETSAB,MAPSUBSETID,MAPRANK,MAPID,MAPSID,FROMID,FROMSID,FROMEXPR,FROMTYPE,FROMRULE,FROMRES,REL,RELA,TOID,TOSID,TOEXPR,TOTYPE,TORULE,TORES,MAPRULE,MAPRES,MAPTYPE,MAPATN,MAPATV,CVF";
+const MRSMAP_COLUMNS: &str =
+    "MAPSETCUI,MAPSETSAB,MAPID,MAPSID,FROMEXPR,FROMTYPE,REL,RELA,TOEXPR,TOTYPE,CVF";
+const MRSAB_COLUMNS: &str = "VCUI,RCUI,VSAB,RSAB,SON,SF,SVER,VSTART,VEND,IMETA,RMETA,SLC,SCC,SRL,TFR,CFR,CXTY,TTYL,ATNL,LAT,CENC,CURVER,SABIN,SSN,SCIT";
+const MRDEF_COLUMNS: &str = "CUI,AUI,ATUI,SATUI,SAB,DEF,SUPPRESS,CVF";
+
+const MRCONSO: &[&str] = &[
+    "C0012634|ENG|P|L0001|PF|S0001|Y|A0001||||SNOMEDCT_US|PT|64572001|Disease|9|N||",
+    "C0012634|ENG|P|L0002|PF|S0002|Y|A0002||||MSH|MH|D004194|Diseases|0|N||",
+    "C0011849|ENG|P|L0010|PF|S0010|Y|A0010||||SNOMEDCT_US|PT|73211009|Diabetes mellitus|9|N||",
+    "C0011849|ENG|P|L0011|PF|S0011|Y|A0011||||ICD10CM|HT|E08-E13|Diabetes mellitus|4|N||",
+    "C0011849|ENG|S|L0012|PF|S0012|N|A0012||||SNOMEDCT_US|SY|73211009|DM|9|N||",
+    "C0011849|ENG|P|L0010|PF|S0010|N|A0013||||MSH|MH|D003920|Diabetes mellitus|0|N||",
+    "C0011860|ENG|P|L0020|PF|S0020|Y|A0020||||SNOMEDCT_US|PT|44054006|Diabetes mellitus type 2|9|N||",
+    "C0011860|ENG|S|L0021|PF|S0021|N|A0021||||SNOMEDCT_US|SY|44054006|Type 2 diabetes|9|N||",
+    "C0011860|ENG|P|L0022|PF|S0022|Y|A0022||||ICD10CM|PT|E11|Type 2 diabetes mellitus|4|N||",
+    "C0011860|ENG|S|L0012|PF|S0012|N|A0023||||SNOMEDCT_US|SY|44054006|DM|9|N||",
+    "C0011860|ENG|S|L0021|PF|S0021|N|A0024||||MSH|EN|D003920|Type 2 diabetes|0|N||",
+    "C0020538|ENG|P|L0030|PF|S0030|Y|A0030||||SNOMEDCT_US|PT|38341003|Hypertensive disorder|9|N||",
+    "C0020538|ENG|P|L0031|PF|S0031|Y|A0031||||ICD10CM|PT|I10|Essential (primary) hypertension|4|N||",
+    "C0020538|ENG|S|L0032|PF|S0032|N|A0032||||SNOMEDCT_US|OP|999999|High blood pressure|9|O||",
+    "C0025598|ENG|P|L0040|PF|S0040|Y|A0040||||RXNORM|IN|6809|metformin|0|N||",
+];
+
+const MRREL: &[&str] = &[
+    "C0011849|A0010|AUI|PAR|C0012634|A0001|AUI|inverse_isa|R001||SNOMEDCT_US|SNOMEDCT_US|||N||",
+    "C0012634|A0001|AUI|CHD|C0011849|A0010|AUI|isa|R002||SNOMEDCT_US|SNOMEDCT_US|||N||",
+    "C0011860|A0020|AUI|PAR|C0011849|A0010|AUI|inverse_isa|R003||SNOMEDCT_US|SNOMEDCT_US|||N||",
+    "C0011849|A0010|AUI|CHD|C0011860|A0020|AUI|isa|R004||SNOMEDCT_US|SNOMEDCT_US|||N||",
+    "C0020538|A0030|AUI|PAR|C0012634|A0001|AUI|inverse_isa|R005||SNOMEDCT_US|SNOMEDCT_US|||N||",
+    "C0012634|A0001|AUI|CHD|C0020538|A0030|AUI|isa|R006||SNOMEDCT_US|SNOMEDCT_US|||N||",
+    "C0025598|A0040|CUI|RO|C0011860|A0020|CUI|may_be_treated_by|R007||MED-RT|MED-RT|||N||",
+    "C0011860|A0020|CUI|RO|C0025598|A0040|CUI|may_treat|R008||MED-RT|MED-RT||Y|N||",
+];
+
+// The root SRC atoms A9999 and A8888 are not in MRCONSO, and neither is A7777, so the path to A0013
+// is incomplete. The last two rows are more contexts for
+// the row before them, using the compressed format that carries over the start of the PTR. The
+// last one has a PTR too short to add anything to the carried-over start.
+const MRHIER: &[&str] = &[
+    "C0012634|A0001|1|A9999|SNOMEDCT_US|isa|A9999|||",
+    "C0012634|A0002|1|A8888|MSH||A8888|C||",
+    "C0011849|A0013|1|A7777|MSH||A8888.A0002.A7777|C18.452.394.750||",
+    "C0011849|A0010|1|A0001|SNOMEDCT_US|isa|A9999.A0001|||",
+    "C0020538|A0030|1|A0001|SNOMEDCT_US|isa|A9999.A0001|||",
+    "C0011860|A0020|1|A0010|SNOMEDCT_US|isa|A9999.A0001.A0010|||",
+    "||2|A0030|||..A0030|||",
+    "||3|A0001|||.|||",
+];
+
+const MRDEF: &[&str] = &[
+    "C0011849|A0010|AT10||NCI|A metabolic disorder characterized by abnormally high blood sugar.|N||",
+    "C0011849|A0011|AT11||MSH|A heterogeneous group of disorders characterized by hyperglycemia.|N||",
+    "C0011849|A0011|AT12||MSH|An obsolete definition.|O||",
+];
+
+// The second row carries over the CUI, METAUI, STYPE and SAB from the first.
+const MRSAT: &[&str] = &[
+    "C0025598|L0040|S0040|A0040|AUI|6809|AT100||NDC|RXNORM|00093-1048-01|N||",
+    "|||||6809|AT101||NDC||00093-1049-01|N||",
+    "C0012634|L0001|S0001|A0001|AUI|64572001|AT102||CTV3ID|SNOMEDCT_US|X0003|N||",
+    "C2919943|||C2919943|CUI||AT103||FROMRSAB|SNOMEDCT_US|SNOMEDCT_US|N||",
+    "C2919943|||C2919943|CUI||AT104||TORSAB|SNOMEDCT_US|ICD10CM|N||",
+];
+
+// The rules for hypertension are listed out of priority order. The map set in MRSMAP is also in
+// MRMAP, so it is ignored, while C9999998 is only in MRSMAP. The SNOMED CT map set maps from
+// SCUIs, which are also SNOMED CT codes, while the MeSH SCUI M0006138 is not a MeSH code.
+const MRMAP: &[&str] = &[
+    "C2919943|SNOMEDCT_US|1|2|M1||F1||38341003|SCUI|||RO|447561005|T1||I10|CODE|||OTHERWISE TRUE|ALWAYS I10|447637006||||",
+    "C2919943|SNOMEDCT_US|1|1|M2||F2||38341003|SCUI|||RO|447561005|T2||I15.9|CODE|||IFA 445518008 < 18.0 years|ALWAYS I15.9|447639009||||",
+];
+const MRSMAP: &[&str] = &[
+    "C2919943|SNOMEDCT_US|M1||38341003|SCUI|RO||I10|CODE||",
+    "C9999998|MSH|M9||D003924|CODE|RO||E11|CODE||",
+    "C9999998|MSH|M10||M0006138|SCUI|RO||E11|CODE||",
+];
+
+// C0000004 was merged into C0000002, which was later merged into C0011860.
+const MRCUI: &[&str] = &[
+    "C0000001|2019AA|DEL|||||",
+    "C0000002|2019AB|SY|||C0011860|Y|",
+    "C0000003|2020AA|RB|||C0011849|Y|",
+    "C0000003|2020AA|RN|||C0020538|Y|",
+    "C0000004|2018AA|SY|||C0000002|N|",
+];
+
+const MERGEDCUI: &[&str] = &["C0000005|C0012634|", "C0000002|C0011860|"];
+const DELETEDCUI: &[&str] = &["C0000006|ENG|Some old concept|"];
+
+const MRSAB: &[&str] = &[
+    "C1000001|C1000002|SNOMEDCT_US_2023_09_01|SNOMEDCT_US|SNOMED CT, US Edition|SNOMEDCT|2023_09_01|||2023AB||||9|0|0||PT,SY,OP||ENG|UTF-8|Y|Y|SNOMED CT||",
+    "C1000003|C1000004|MSH2024|MSH|MeSH|MSH|2024|||2023AB||||0|0|0||MH||ENG|UTF-8|Y|Y|MeSH||",
+    "C1000005|C1000006|ICD10CM_2024|ICD10CM|ICD-10-CM|ICD10CM|2024|||2023AB||||4|0|0||PT,HT||ENG|UTF-8|Y|Y|ICD-10-CM||",
+    "C1000007|C1000008|RXNORM_23AB|RXNORM|RxNorm|RXNORM|23AB|||2023AB||||0|0|0||IN|NDC|ENG|UTF-8|Y|Y|RxNorm||",
+    "C1000009|C1000010|MED-RT_2023|MED-RT|MED-RT|MED-RT|2023|||2023AB||||0|0|0||||ENG|UTF-8|Y|Y|MED-RT||",
+    "C1000011|C1000012|NCI_2023|NCI|NCI Thesaurus|NCI|2023|||2023AB||||0|0|0||||ENG|UTF-8|Y|Y|NCI||",
+];
+
+const MRSTY: &[&str] = &[
+    "C0012634|T047|B2.2.1.2.1|Disease or Syndrome|AT01||",
+    "C0011849|T047|B2.2.1.2.1|Disease or Syndrome|AT02||",
+    "C0011860|T047|B2.2.1.2.1|Disease or Syndrome|AT03||",
+    "C0020538|T047|B2.2.1.2.1|Disease or Syndrome|AT04||",
+    "C0025598|T121|A1.4.1.1.1|Pharmacologic Substance|AT05||",
+];
+
+const MRRANK: &[&str] = &[
+    "400|SNOMEDCT_US|PT|N|",
+    "390|SNOMEDCT_US|SY|N|",
+    "100|SNOMEDCT_US|OP|N|",
+    "350|MSH|MH|N|",
+    "300|ICD10CM|PT|N|",
+    "290|ICD10CM|HT|N|",
+    "380|RXNORM|IN|N|",
+];
+
+const SRDEF: &[&str] = &[
+    "STY|T047|Disease or Syndrome|B2.2.1.2.1|A condition which alters or interferes with a normal process.||||dsyn||",
+    "STY|T046|Pathologic Function|B2.2.1.2|A disordered process.||||patf||",
+    "STY|T121|Pharmacologic Substance|A1.4.1.1.1|A drug.||||phsu||",
+    "RL|T186|isa|H|The basic hierarchical link.||||IS|inverse_isa|",
+    "RL|T154|treats|R3.1.2|Applies a remedy.||||TR|treated_by|",
+];
+
+const SRSTRE1: &[&str] = &["T047|T186|T046|", "T121|T154|T046|", "T121|T154|T047|"];
+
+/// A small release written to a temporary directory, along with an index built from it.
+pub(crate) struct TestData {
+    pub dir: TempDir,
+    pub index: Index,
+}
+
+impl TestData {
+    pub fn new() -> TestData {
+        Self::build(|_| {})
+    }
+
+    /// Build the index with an ancestor closure.
+    pub fn with_closure(kinds: RelationKinds) -> TestData {
+        Self::build(|options| options.closure = Some(kinds))
+    }
+
+    /// Build the index, after letting `configure` change the default options.
+    pub fn build(configure: impl FnOnce(&mut IndexBuilderOptions)) -> TestData {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join("META");
+        let net = dir.path().join("NET");
+        std::fs::create_dir_all(meta.join("CHANGE")).unwrap();
+        std::fs::create_dir(&net).unwrap();
+
+        let files = [
+            ("MRCONSO", MRCONSO_COLUMNS, MRCONSO),
+            ("MRREL", MRREL_COLUMNS, MRREL),
+            ("MRSTY", MRSTY_COLUMNS, MRSTY),
+            ("MRRANK", MRRANK_COLUMNS, MRRANK),
+            ("MRHIER", MRHIER_COLUMNS, MRHIER),
+            ("MRDEF", MRDEF_COLUMNS, MRDEF),
+            ("MRSAB", MRSAB_COLUMNS, MRSAB),
+            ("MRSAT", MRSAT_COLUMNS, MRSAT),
+            ("MRCUI", MRCUI_COLUMNS, MRCUI),
+            ("MRMAP", MRMAP_COLUMNS, MRMAP),
+            ("MRSMAP", MRSMAP_COLUMNS, MRSMAP),
+            ("CHANGE/MERGEDCUI", "PCUI,CUI", MERGEDCUI),
+            ("CHANGE/DELETEDCUI", "PCUI,LAT,PSTR", DELETEDCUI),
+        ];
+
+        let mut mrfiles = files
+            .iter()
+            .map(|(name, columns, rows)| {
+                format!(
+                    "{name}.RRF|{name}|{columns}|{}|{}|{}|",
+                    columns.split(',').count(),
+                    rows.len(),
+                    rows.iter().map(|r| r.len() + 1).sum::<usize>()
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // The MRFILES row includes its own length in the byte count.
+        let mrfiles_rows = mrfiles.len() + 1;
+        let other_bytes = mrfiles.iter().map(|r| r.len() + 1).sum::<usize>();
+        let mut bytes = 0;
+        let own_row = loop {
+            let row =
+                format!("MRFILES.RRF|Files|FIL,DES,FMT,CLS,RWS,BTS|6|{mrfiles_rows}|{bytes}|");
+            if other_bytes + row.len() + 1 == bytes {
+                break row;
+            }
+            bytes = other_bytes + row.len() + 1;
+        };
+        mrfiles.push(own_row);
+
+        for (name, _, rows) in files {
+            write_gz(&meta.join(format!("{name}.RRF.gz")), rows);
+        }
+        write_gz(&meta.join("MRFILES.RRF.gz"), &mrfiles);
+        std::fs::write(net.join("SRDEF"), SRDEF.join("\n") + "\n").unwrap();
+        std::fs::write(net.join("SRSTRE1"), SRSTRE1.join("\n") + "\n").unwrap();
+
+        let index_dir = dir.path().join("index");
+        std::fs::create_dir(&index_dir).unwrap();
+        let files = Files::new(dir.path()).unwrap();
+        let mut options = IndexBuilderOptions {
+            output_dir: &index_dir,
+            files: &files,
+            case_insensitive: true,
+            languages: Vec::new(),
+            sources: Vec::new(),
+            semantic_types: Vec::new(),
+            closure: None,
+            definition_sources: Vec::new(),
+            attributes: vec!["NDC".into()],
+        };
+        configure(&mut options);
+        build_index(options).unwrap();
+
+        let index = Index::new(&index_dir).unwrap();
+        TestData { dir, index }
+    }
+}
+
+fn write_gz(path: &Path, rows: &[impl AsRef<str>]) {
+    let file = std::fs::File::create(path).unwrap();
+    let mut encoder = GzEncoder::new(file, Compression::fast());
+    for row in rows {
+        writeln!(encoder, "{}", row.as_ref()).unwrap();
+    }
+    encoder.finish().unwrap();
+}